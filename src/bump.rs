@@ -0,0 +1,263 @@
+use crate::math::{cross_product, dot_product, to_unit_vector, Color, Ray, Vec3};
+use crate::texture::Texture;
+use crate::trace::{HitRecord, Material, ScatterRecord, BLACK};
+use std::sync::Arc;
+
+// Computes a new shading normal for a hit.
+pub trait NormalPerturbation {
+    fn shading_normal(&self, hit: &HitRecord) -> Vec3;
+}
+
+// Wraps `material` and shades it with the normal from `perturbation`. The geometric normal is
+// left alone, and directions that land on opposite sides of the shading and geometric surfaces
+// are dropped rather than let light leak through.
+pub struct PerturbedMaterial<P> {
+    pub material: Arc<dyn Material + Send + Sync>,
+    pub perturbation: P,
+}
+
+// Treats the average of the texture's channels as a height above the surface, scaled by
+// `scale` into world units.
+pub struct BumpMap {
+    pub height: Arc<dyn Texture + Send + Sync>,
+    pub scale: f64,
+}
+
+// Tangent-space normals encoded as colors, with x along dp/du, y along dp/dv and z along the
+// normal. The texture must hold linear values.
+pub struct NormalMap {
+    pub normals: Arc<dyn Texture + Send + Sync>,
+}
+
+impl<P: NormalPerturbation> PerturbedMaterial<P> {
+    fn perturb(&self, hit: &HitRecord) -> HitRecord {
+        let mut perturbed = hit.clone();
+        perturbed.set_shading_normal(&self.perturbation.shading_normal(hit));
+        perturbed
+    }
+}
+
+fn is_consistent(hit: &HitRecord, direction: &Vec3) -> bool {
+    let geometric = dot_product(direction, &hit.geometric_normal);
+    let shading = dot_product(direction, &hit.normal);
+    geometric * shading > 0.0
+}
+
+impl<P: NormalPerturbation> Material for PerturbedMaterial<P> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
+        let hit = self.perturb(hit);
+        match self.material.scatter(ray, &hit)? {
+            ScatterRecord::Specular {
+                ray: scattered_ray,
+                attenuation,
+            } => {
+                if !is_consistent(&hit, &scattered_ray.direction) {
+                    return None;
+                }
+                Some(ScatterRecord::Specular {
+                    ray: scattered_ray,
+                    attenuation,
+                })
+            }
+            sampled => Some(sampled),
+        }
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color {
+        let hit = self.perturb(hit);
+        if !is_consistent(&hit, direction) {
+            return BLACK;
+        }
+        self.material.eval(ray, &hit, direction)
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Color {
+        self.material.emitted(ray, hit)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+}
+
+impl NormalPerturbation for BumpMap {
+    // Displaces the surface along the normal and takes the normal of the displaced surface from
+    // finite differences of the height along u and v.
+    fn shading_normal(&self, hit: &HitRecord) -> Vec3 {
+        const DELTA: f64 = 1e-3;
+        let height = |u: f64, v: f64, offset: Vec3| {
+            let value = self.height.value(u, v, &(hit.point + offset));
+            self.scale * (value.x() + value.y() + value.z()) / 3.0
+        };
+        let base = height(hit.u, hit.v, Vec3::new(0.0, 0.0, 0.0));
+        let du = height(hit.u + DELTA, hit.v, hit.dpdu * DELTA);
+        let dv = height(hit.u, hit.v + DELTA, hit.dpdv * DELTA);
+
+        let dpdu = hit.dpdu + hit.normal * ((du - base) / DELTA);
+        let dpdv = hit.dpdv + hit.normal * ((dv - base) / DELTA);
+        // The cross product's orientation depends on the parameterization.
+        let bumped = cross_product(&dpdu, &dpdv);
+        if dot_product(&bumped, &hit.normal) < 0.0 {
+            -bumped
+        } else {
+            bumped
+        }
+    }
+}
+
+impl NormalPerturbation for NormalMap {
+    fn shading_normal(&self, hit: &HitRecord) -> Vec3 {
+        let encoded = self.normals.value(hit.u, hit.v, &hit.point);
+        let local = encoded * 2.0 - Vec3::new(1.0, 1.0, 1.0);
+
+        let normal = hit.normal;
+        let tangent = to_unit_vector(&(hit.dpdu - normal * dot_product(&normal, &hit.dpdu)));
+        let mut bitangent = cross_product(&normal, &tangent);
+        if dot_product(&bitangent, &hit.dpdv) < 0.0 {
+            bitangent = -bitangent;
+        }
+        tangent * local.x() + bitangent * local.y() + normal * local.z()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Point;
+    use crate::texture::SolidColor;
+    use crate::trace::LambertianMaterial;
+    use std::f64::consts::PI;
+
+    // Height growing with u, the same in every channel.
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, u: f64, _v: f64, _point: &Point) -> Color {
+            Color::new(u, u, u)
+        }
+    }
+
+    fn solid(r: f64, g: f64, b: f64) -> Arc<dyn Texture + Send + Sync> {
+        Arc::new(SolidColor::new(Color::new(r, g, b)))
+    }
+
+    // A hit on the z = 0 plane seen from above, with dp/du along x and dp/dv along y, or along
+    // -y for a mirrored parameterization.
+    fn hit(mirrored: bool) -> HitRecord {
+        let material = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+        let ray = Ray {
+            origin: Point::new(0.0, 0.0, 1.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let mut hit = HitRecord::from_hit(
+            &Point::new(0.0, 0.0, 0.0),
+            &ray,
+            1.0,
+            &Vec3::new(0.0, 0.0, 1.0),
+            0.25,
+            0.5,
+            material,
+        );
+        let dpdv = if mirrored { -1.0 } else { 1.0 };
+        hit.set_tangents(&Vec3::new(1.0, 0.0, 0.0), &Vec3::new(0.0, dpdv, 0.0));
+        hit
+    }
+
+    fn assert_direction(actual: &Vec3, expected: &Vec3) {
+        let actual = to_unit_vector(actual);
+        let expected = to_unit_vector(expected);
+        assert!(
+            (actual - expected).length() < 1e-9,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn flat_normal_maps_keep_the_normal() {
+        let normal_map = NormalMap {
+            normals: solid(0.5, 0.5, 1.0),
+        };
+        for mirrored in [false, true] {
+            assert_direction(
+                &normal_map.shading_normal(&hit(mirrored)),
+                &Vec3::new(0.0, 0.0, 1.0),
+            );
+        }
+    }
+
+    #[test]
+    fn normal_map_y_follows_dpdv() {
+        let normal_map = NormalMap {
+            normals: solid(0.5, 1.0, 0.5),
+        };
+        assert_direction(
+            &normal_map.shading_normal(&hit(false)),
+            &Vec3::new(0.0, 1.0, 0.0),
+        );
+        assert_direction(
+            &normal_map.shading_normal(&hit(true)),
+            &Vec3::new(0.0, -1.0, 0.0),
+        );
+        let normal_map = NormalMap {
+            normals: solid(1.0, 0.5, 0.5),
+        };
+        assert_direction(
+            &normal_map.shading_normal(&hit(true)),
+            &Vec3::new(1.0, 0.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn constant_bump_maps_keep_the_normal() {
+        let bump_map = BumpMap {
+            height: solid(0.3, 0.6, 0.9),
+            scale: 2.0,
+        };
+        for mirrored in [false, true] {
+            assert_direction(
+                &bump_map.shading_normal(&hit(mirrored)),
+                &Vec3::new(0.0, 0.0, 1.0),
+            );
+        }
+    }
+
+    #[test]
+    fn bump_maps_tilt_away_from_rising_height() {
+        // The height rises by `scale` per unit of u, so the surface slopes up along x.
+        let bump_map = BumpMap {
+            height: Arc::new(Ramp),
+            scale: 0.5,
+        };
+        for mirrored in [false, true] {
+            assert_direction(
+                &bump_map.shading_normal(&hit(mirrored)),
+                &Vec3::new(-0.5, 0.0, 1.0),
+            );
+        }
+    }
+
+    #[test]
+    fn directions_between_the_shading_and_geometric_surfaces_are_dropped() {
+        // Shading normal tilted 45 degrees towards +x.
+        let material = PerturbedMaterial {
+            material: Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5))),
+            perturbation: NormalMap {
+                normals: solid(0.5 + 0.5 * 0.5f64.sqrt(), 0.5, 0.5 + 0.5 * 0.5f64.sqrt()),
+            },
+        };
+        let hit = hit(false);
+        let ray = Ray {
+            origin: Point::new(0.0, 0.0, 1.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        // Above the geometric surface but below the shading one, and the other way round.
+        assert_eq!(material.eval(&ray, &hit, &Vec3::new(-1.0, 0.0, 0.5)), BLACK);
+        assert_eq!(material.eval(&ray, &hit, &Vec3::new(1.0, 0.0, -0.5)), BLACK);
+        let lit = material.eval(&ray, &hit, &Vec3::new(1.0, 0.0, 1.0));
+        assert!((lit.x() - 0.5 / PI).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,417 @@
+use crate::math::{Aabb, Ray, Vec3};
+use crate::trace::{HitRecord, Hittable, HittableCollection};
+use std::sync::Arc;
+
+const NUM_SAH_BUCKETS: usize = 12;
+const MAX_PRIMITIVES_IN_LEAF: usize = 4;
+const TRAVERSAL_COST: f64 = 0.125;
+
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    hittables: Vec<Arc<dyn Hittable + Send + Sync>>,
+    unbounded: Vec<Arc<dyn Hittable + Send + Sync>>,
+}
+
+// Nodes are stored depth first, so the first child of an interior node is always the next node
+// and only the second child needs an explicit index.
+struct BvhNode {
+    bbox: Aabb,
+    offset: usize,
+    num_hittables: usize,
+    axis: usize,
+}
+
+#[derive(Copy, Clone)]
+struct BuildItem {
+    index: usize,
+    bbox: Aabb,
+    centroid: Vec3,
+}
+
+#[derive(Copy, Clone)]
+struct SahBucket {
+    count: usize,
+    bbox: Aabb,
+}
+
+impl Bvh {
+    pub fn new(collection: HittableCollection) -> Self {
+        let mut bounded = vec![];
+        let mut unbounded = vec![];
+        let mut items = vec![];
+        for hittable in collection.hittables {
+            match hittable.bounding_box() {
+                Some(bbox) => {
+                    items.push(BuildItem {
+                        index: bounded.len(),
+                        bbox,
+                        centroid: bbox.centroid(),
+                    });
+                    bounded.push(hittable);
+                }
+                None => unbounded.push(hittable),
+            }
+        }
+
+        let mut nodes = vec![];
+        let mut ordered_indices = vec![];
+        if !items.is_empty() {
+            Bvh::build(&mut items, &mut nodes, &mut ordered_indices);
+        }
+
+        let hittables = ordered_indices
+            .into_iter()
+            .map(|i| bounded[i].clone())
+            .collect();
+
+        Bvh {
+            nodes,
+            hittables,
+            unbounded,
+        }
+    }
+
+    fn build(
+        items: &mut [BuildItem],
+        nodes: &mut Vec<BvhNode>,
+        ordered_indices: &mut Vec<usize>,
+    ) -> usize {
+        let bbox = items.iter().fold(Aabb::empty(), |acc, item| {
+            Aabb::surrounding(&acc, &item.bbox)
+        });
+        let centroid_bbox = items
+            .iter()
+            .fold(Aabb::empty(), |acc, item| acc.grow(&item.centroid));
+        let axis = centroid_bbox.longest_axis();
+
+        let node_idx = nodes.len();
+        nodes.push(BvhNode {
+            bbox,
+            offset: 0,
+            num_hittables: 0,
+            axis,
+        });
+
+        let is_degenerate = centroid_bbox.max.e[axis] <= centroid_bbox.min.e[axis];
+        let split = if items.len() == 1 || is_degenerate {
+            None
+        } else {
+            Bvh::find_split(items, &bbox, &centroid_bbox, axis)
+        };
+
+        match split {
+            None => {
+                nodes[node_idx].offset = ordered_indices.len();
+                nodes[node_idx].num_hittables = items.len();
+                ordered_indices.extend(items.iter().map(|item| item.index));
+            }
+            Some(mid) => {
+                let (left, right) = items.split_at_mut(mid);
+                Bvh::build(left, nodes, ordered_indices);
+                let second_child = Bvh::build(right, nodes, ordered_indices);
+                nodes[node_idx].offset = second_child;
+            }
+        }
+
+        node_idx
+    }
+
+    // Partitions `items` along `axis` using the binned surface area heuristic and returns the
+    // split position, or None if a leaf is cheaper than any split.
+    fn find_split(
+        items: &mut [BuildItem],
+        bbox: &Aabb,
+        centroid_bbox: &Aabb,
+        axis: usize,
+    ) -> Option<usize> {
+        if items.len() <= 2 {
+            items.sort_by(|a, b| {
+                a.centroid.e[axis]
+                    .partial_cmp(&b.centroid.e[axis])
+                    .expect("NaN centroid")
+            });
+            return Some(items.len() / 2);
+        }
+
+        let axis_min = centroid_bbox.min.e[axis];
+        let axis_extent = centroid_bbox.max.e[axis] - axis_min;
+        let bucket_of = |item: &BuildItem| -> usize {
+            let b = (NUM_SAH_BUCKETS as f64 * (item.centroid.e[axis] - axis_min) / axis_extent)
+                as usize;
+            b.min(NUM_SAH_BUCKETS - 1)
+        };
+
+        let mut buckets = [SahBucket {
+            count: 0,
+            bbox: Aabb::empty(),
+        }; NUM_SAH_BUCKETS];
+        for item in items.iter() {
+            let bucket = &mut buckets[bucket_of(item)];
+            bucket.count += 1;
+            bucket.bbox = Aabb::surrounding(&bucket.bbox, &item.bbox);
+        }
+
+        // Sweep from the right to get the cost of everything above each split plane, then from
+        // the left to combine it with everything below.
+        let mut right_areas = [0.0; NUM_SAH_BUCKETS];
+        let mut right_counts = [0; NUM_SAH_BUCKETS];
+        let mut right_bbox = Aabb::empty();
+        let mut right_count = 0;
+        for i in (1..NUM_SAH_BUCKETS).rev() {
+            right_bbox = Aabb::surrounding(&right_bbox, &buckets[i].bbox);
+            right_count += buckets[i].count;
+            right_areas[i] = right_bbox.surface_area();
+            right_counts[i] = right_count;
+        }
+
+        let mut best_cost = f64::INFINITY;
+        let mut best_bucket = 0;
+        let mut left_bbox = Aabb::empty();
+        let mut left_count = 0;
+        for i in 0..(NUM_SAH_BUCKETS - 1) {
+            left_bbox = Aabb::surrounding(&left_bbox, &buckets[i].bbox);
+            left_count += buckets[i].count;
+            if left_count == 0 || right_counts[i + 1] == 0 {
+                continue;
+            }
+            let cost = left_count as f64 * left_bbox.surface_area()
+                + right_counts[i + 1] as f64 * right_areas[i + 1];
+            if cost < best_cost {
+                best_cost = cost;
+                best_bucket = i;
+            }
+        }
+
+        let area = bbox.surface_area();
+        let split_cost = if area > 0.0 {
+            TRAVERSAL_COST + best_cost / area
+        } else {
+            TRAVERSAL_COST
+        };
+        let leaf_cost = items.len() as f64;
+        if items.len() <= MAX_PRIMITIVES_IN_LEAF && leaf_cost <= split_cost {
+            return None;
+        }
+
+        if best_cost.is_infinite() {
+            let mid = items.len() / 2;
+            items.select_nth_unstable_by(mid, |a, b| {
+                a.centroid.e[axis]
+                    .partial_cmp(&b.centroid.e[axis])
+                    .expect("NaN centroid")
+            });
+            return Some(mid);
+        }
+
+        let mut mid = 0;
+        for i in 0..items.len() {
+            if bucket_of(&items[i]) <= best_bucket {
+                items.swap(i, mid);
+                mid += 1;
+            }
+        }
+        Some(mid)
+    }
+
+    pub fn num_hittables(&self) -> usize {
+        self.hittables.len() + self.unbounded.len()
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn depth(&self) -> usize {
+        if self.nodes.is_empty() {
+            0
+        } else {
+            self.node_depth(0)
+        }
+    }
+
+    fn node_depth(&self, node_idx: usize) -> usize {
+        let node = &self.nodes[node_idx];
+        if node.num_hittables > 0 {
+            1
+        } else {
+            1 + self
+                .node_depth(node_idx + 1)
+                .max(self.node_depth(node.offset))
+        }
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut closest_hit: Option<HitRecord> = None;
+        let mut closest_hit_t = t_max;
+
+        for hittable in self.unbounded.iter() {
+            if let Some(hit) = hittable.hit(ray, t_min, closest_hit_t) {
+                closest_hit_t = hit.t;
+                closest_hit = Some(hit);
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return closest_hit;
+        }
+
+        let inv_direction = Vec3::new(
+            1.0 / ray.direction.x(),
+            1.0 / ray.direction.y(),
+            1.0 / ray.direction.z(),
+        );
+        let dir_is_negative = [
+            inv_direction.x() < 0.0,
+            inv_direction.y() < 0.0,
+            inv_direction.z() < 0.0,
+        ];
+
+        let mut stack = Vec::with_capacity(64);
+        let mut node_idx = 0;
+        loop {
+            let node = &self.nodes[node_idx];
+            if node.bbox.hit(ray, &inv_direction, t_min, closest_hit_t) {
+                if node.num_hittables > 0 {
+                    let leaf = &self.hittables[node.offset..(node.offset + node.num_hittables)];
+                    for hittable in leaf.iter() {
+                        if let Some(hit) = hittable.hit(ray, t_min, closest_hit_t) {
+                            closest_hit_t = hit.t;
+                            closest_hit = Some(hit);
+                        }
+                    }
+                } else {
+                    // Visit the child nearer to the ray origin first so the far child can be
+                    // culled by a closer hit.
+                    let (near, far) = if dir_is_negative[node.axis] {
+                        (node.offset, node_idx + 1)
+                    } else {
+                        (node_idx + 1, node.offset)
+                    };
+                    stack.push(far);
+                    node_idx = near;
+                    continue;
+                }
+            }
+
+            match stack.pop() {
+                Some(next) => node_idx = next,
+                None => break,
+            }
+        }
+
+        closest_hit
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() || self.nodes.is_empty() {
+            return None;
+        }
+        Some(self.nodes[0].bbox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{random_in_range, to_unit_vector, Color, Point};
+    use crate::trace::{LambertianMaterial, Sphere};
+
+    // Random spheres in a 10 unit cube, with a stack of coincident ones that no split can
+    // separate.
+    fn spheres() -> Vec<Arc<dyn Hittable + Send + Sync>> {
+        let material = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+        let mut spheres: Vec<Arc<dyn Hittable + Send + Sync>> = (0..200)
+            .map(|_| {
+                let center = Vec3::random_in_range(-5.0, 5.0);
+                let sphere = Sphere::new(&center, random_in_range(0.05, 0.5), material.clone());
+                Arc::new(sphere) as Arc<dyn Hittable + Send + Sync>
+            })
+            .collect();
+        for _ in 0..10 {
+            spheres.push(Arc::new(Sphere::new(
+                &Point::new(1.0, 2.0, 3.0),
+                0.25,
+                material.clone(),
+            )));
+        }
+        spheres
+    }
+
+    fn collection(hittables: &[Arc<dyn Hittable + Send + Sync>]) -> HittableCollection {
+        let mut collection = HittableCollection::new();
+        for hittable in hittables {
+            collection.add(hittable.clone());
+        }
+        collection
+    }
+
+    fn encloses(outer: &Aabb, inner: &Aabb) -> bool {
+        (0..3).all(|i| outer.min.e[i] <= inner.min.e[i] && inner.max.e[i] <= outer.max.e[i])
+    }
+
+    #[test]
+    fn hits_match_a_linear_scan() {
+        let spheres = spheres();
+        let linear = collection(&spheres);
+        let bvh = Bvh::new(collection(&spheres));
+        assert_eq!(bvh.num_hittables(), spheres.len());
+        let mut num_hits = 0;
+        for _ in 0..2000 {
+            let ray = Ray {
+                origin: Vec3::random_in_range(-8.0, 8.0),
+                direction: to_unit_vector(&Vec3::random_in_range(-1.0, 1.0)),
+                time: 0.0,
+            };
+            let expected = linear.hit(&ray, 0.001, f64::INFINITY);
+            let actual = bvh.hit(&ray, 0.001, f64::INFINITY);
+            match (expected, actual) {
+                (None, None) => {}
+                (Some(expected), Some(actual)) => {
+                    assert_eq!(expected.t, actual.t);
+                    assert_eq!(expected.normal, actual.normal);
+                    num_hits += 1;
+                }
+                (expected, actual) => panic!(
+                    "linear scan hit at {:?}, BVH at {:?}",
+                    expected.map(|hit| hit.t),
+                    actual.map(|hit| hit.t)
+                ),
+            }
+        }
+        assert!(num_hits > 100);
+    }
+
+    #[test]
+    fn bounding_boxes_enclose_every_child() {
+        let spheres = spheres();
+        let bvh = Bvh::new(collection(&spheres));
+        assert!(bvh.depth() > 1);
+        let mut num_leaf_hittables = 0;
+        for (node_idx, node) in bvh.nodes.iter().enumerate() {
+            if node.num_hittables > 0 {
+                for hittable in &bvh.hittables[node.offset..(node.offset + node.num_hittables)] {
+                    assert!(encloses(&node.bbox, &hittable.bounding_box().unwrap()));
+                }
+                num_leaf_hittables += node.num_hittables;
+            } else {
+                assert!(encloses(&node.bbox, &bvh.nodes[node_idx + 1].bbox));
+                assert!(encloses(&node.bbox, &bvh.nodes[node.offset].bbox));
+            }
+        }
+        assert_eq!(num_leaf_hittables, spheres.len());
+        let root = bvh.bounding_box().unwrap();
+        for sphere in &spheres {
+            assert!(encloses(&root, &sphere.bounding_box().unwrap()));
+        }
+    }
+
+    #[test]
+    fn coincident_hittables_share_a_leaf() {
+        let spheres = spheres();
+        let bvh = Bvh::new(collection(&spheres[200..]));
+        assert_eq!(bvh.num_nodes(), 1);
+        assert_eq!(bvh.nodes[0].num_hittables, 10);
+    }
+}
@@ -0,0 +1,158 @@
+use crate::image::ImageFormat;
+use crate::render::RenderSettings;
+use crate::tonemap::{ToneMapOperator, ToneMapping};
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(name = "rtiow-r", version, about = "Ray Tracing In One Weekend - Rust")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Render a single image from the scene camera
+    Render(RenderCommand),
+    /// Render frames with the camera orbiting its look-at point
+    Turntable(TurntableCommand),
+    /// Print statistics about a scene without rendering it
+    Info(SceneArgs),
+    /// Render the same frame repeatedly and report timings
+    Bench(BenchCommand),
+}
+
+#[derive(Args)]
+pub struct SceneArgs {
+    /// Scene description file. The built-in random spheres scene is used if omitted
+    pub scene: Option<PathBuf>,
+}
+
+// Overrides for the scene file's [render] settings.
+#[derive(Args)]
+pub struct RenderArgs {
+    /// Image width in pixels
+    #[arg(long)]
+    pub width: Option<u32>,
+
+    /// Image height in pixels
+    #[arg(long)]
+    pub height: Option<u32>,
+
+    /// Samples per pixel
+    #[arg(short, long)]
+    pub spp: Option<u32>,
+
+    /// Maximum number of bounces per path
+    #[arg(short = 'd', long)]
+    pub max_depth: Option<u32>,
+
+    /// Tone mapping operator for 8 and 16-bit output
+    #[arg(long, value_enum)]
+    pub tone_map: Option<ToneMapOperator>,
+
+    /// Exposure compensation in stops for 8 and 16-bit output
+    #[arg(long, allow_negative_numbers = true)]
+    pub exposure: Option<f64>,
+
+    /// Number of render threads. Defaults to one per logical CPU
+    #[arg(short = 'j', long)]
+    pub threads: Option<usize>,
+}
+
+#[derive(Args)]
+pub struct RenderCommand {
+    #[command(flatten)]
+    pub scene: SceneArgs,
+
+    #[command(flatten)]
+    pub render: RenderArgs,
+
+    /// Output image path
+    #[arg(short, long, default_value = "output.ppm")]
+    pub output: PathBuf,
+
+    /// Image format to write. Inferred from the output extension by default
+    #[arg(short, long, value_enum)]
+    pub format: Option<ImageFormat>,
+
+    /// Add an alpha channel with each pixel's coverage (EXR only)
+    #[arg(long)]
+    pub alpha: bool,
+}
+
+#[derive(Args)]
+pub struct TurntableCommand {
+    #[command(flatten)]
+    pub scene: SceneArgs,
+
+    #[command(flatten)]
+    pub render: RenderArgs,
+
+    /// Output path template; a run of '#' is replaced by the zero-padded frame number
+    #[arg(short, long, default_value = "output_###.ppm")]
+    pub output: String,
+
+    /// Image format to write. Inferred from the output extension by default
+    #[arg(short, long, value_enum)]
+    pub format: Option<ImageFormat>,
+
+    /// Add an alpha channel with each pixel's coverage (EXR only)
+    #[arg(long)]
+    pub alpha: bool,
+
+    /// Number of frames in a full orbit
+    #[arg(long, default_value_t = 240)]
+    pub frames: u32,
+
+    /// First frame to render
+    #[arg(long, default_value_t = 0)]
+    pub start_frame: u32,
+
+    /// Frame to stop before. Defaults to the end of the orbit
+    #[arg(long)]
+    pub end_frame: Option<u32>,
+
+    /// Fraction of each frame the shutter stays open, blurring the camera's motion. Overrides
+    /// the scene camera's shutter
+    #[arg(long)]
+    pub shutter: Option<f64>,
+
+    /// CSV file for per-frame render times
+    #[arg(long, default_value = "output_stats.csv")]
+    pub stats: PathBuf,
+}
+
+#[derive(Args)]
+pub struct BenchCommand {
+    #[command(flatten)]
+    pub scene: SceneArgs,
+
+    #[command(flatten)]
+    pub render: RenderArgs,
+
+    /// Number of timed renders
+    #[arg(short = 'n', long, default_value_t = 3)]
+    pub iterations: u32,
+
+    /// CSV file for per-iteration render times
+    #[arg(long)]
+    pub stats: Option<PathBuf>,
+}
+
+impl RenderArgs {
+    pub fn apply(&self, settings: &RenderSettings) -> RenderSettings {
+        RenderSettings {
+            image_width: self.width.unwrap_or(settings.image_width),
+            image_height: self.height.unwrap_or(settings.image_height),
+            samples_per_pixel: self.spp.unwrap_or(settings.samples_per_pixel),
+            max_depth: self.max_depth.unwrap_or(settings.max_depth),
+            tone_mapping: ToneMapping {
+                operator: self.tone_map.unwrap_or(settings.tone_mapping.operator),
+                exposure: self.exposure.unwrap_or(settings.tone_mapping.exposure),
+            },
+            ..*settings
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use crate::math::{Aabb, Ray};
+use crate::trace::{HitRecord, Hittable};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    // Carves `right` out of `left`.
+    Difference,
+}
+
+// Combines two closed hittables as solids. A ray is inside a hittable between a crossing that
+// enters it and the next that leaves it, which the operands report through `front_face`, and
+// the combination's surface is wherever the ray passes in or out of the combined solid. Each
+// crossing keeps the material of the operand it came from, so carved faces take on the
+// material of what carved them.
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub left: Arc<dyn Hittable + Send + Sync>,
+    pub right: Arc<dyn Hittable + Send + Sync>,
+}
+
+impl CsgOperation {
+    fn contains(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.hit_all(ray, t_min, t_max).into_iter().next()
+    }
+
+    fn hit_all(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord> {
+        // Whether the ray starts inside an operand is only known from its next crossing, so
+        // the operands are searched past `t_max`.
+        let left = self.left.hit_all(ray, t_min, f64::INFINITY);
+        let right = self.right.hit_all(ray, t_min, f64::INFINITY);
+        let mut in_left = left.first().is_some_and(|hit| !hit.front_face);
+        let mut in_right = right.first().is_some_and(|hit| !hit.front_face);
+        let mut inside = self.operation.contains(in_left, in_right);
+
+        let mut left = left.into_iter().peekable();
+        let mut right = right.into_iter().peekable();
+        let mut hits = vec![];
+        loop {
+            let from_left = match (left.peek(), right.peek()) {
+                (Some(l), Some(r)) => l.t <= r.t,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            let mut hit = if from_left {
+                let hit = left.next().unwrap();
+                in_left = hit.front_face;
+                hit
+            } else {
+                let hit = right.next().unwrap();
+                in_right = hit.front_face;
+                hit
+            };
+            if hit.t >= t_max {
+                break;
+            }
+            // Crossings at the same distance, as on coincident surfaces, are taken together so
+            // they cannot open an empty interval.
+            if from_left {
+                if let Some(other) = right.next_if(|other| other.t == hit.t) {
+                    in_right = other.front_face;
+                }
+            } else if let Some(other) = left.next_if(|other| other.t == hit.t) {
+                in_left = other.front_face;
+            }
+            let now_inside = self.operation.contains(in_left, in_right);
+            if now_inside != inside {
+                // Normals already face the ray, so only the side needs fixing, e.g. entering
+                // the right operand of a difference leaves the result.
+                hit.front_face = now_inside;
+                hits.push(hit);
+                inside = now_inside;
+            }
+        }
+        hits
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let left = self.left.bounding_box();
+        let right = self.right.bounding_box();
+        match self.operation {
+            CsgOperation::Union => Some(Aabb::surrounding(&left?, &right?)),
+            CsgOperation::Intersection => match (left, right) {
+                (Some(left), Some(right)) => Some(Aabb::overlap(&left, &right)),
+                (left, right) => left.or(right),
+            },
+            CsgOperation::Difference => left,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{Color, Point, Vec3};
+    use crate::trace::{LambertianMaterial, Sphere};
+
+    fn sphere(x: f64) -> Arc<dyn Hittable + Send + Sync> {
+        let material = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+        Arc::new(Sphere::new(&Point::new(x, 0.0, 0.0), 1.0, material))
+    }
+
+    // Spheres of radius 1 at `left` and `right` on the x axis, crossed by a ray along x from
+    // `origin`, so each crossing's t is its x coordinate minus `origin`.
+    fn crossings(operation: CsgOperation, left: f64, right: f64, origin: f64) -> Vec<(f64, bool)> {
+        let csg = Csg {
+            operation,
+            left: sphere(left),
+            right: sphere(right),
+        };
+        let ray = Ray {
+            origin: Point::new(origin, 0.0, 0.0),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        let hits = csg.hit_all(&ray, 0.001, f64::INFINITY);
+        if let Some(first) = csg.hit(&ray, 0.001, f64::INFINITY) {
+            assert_eq!(first.t, hits[0].t);
+        }
+        hits.iter().map(|hit| (hit.t, hit.front_face)).collect()
+    }
+
+    fn assert_crossings(actual: Vec<(f64, bool)>, expected: &[(f64, bool)]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for ((t, front_face), (expected_t, expected_front_face)) in actual.iter().zip(expected) {
+            assert!((t - expected_t).abs() < 1e-9, "{:?}", actual);
+            assert_eq!(front_face, expected_front_face, "{:?}", actual);
+        }
+    }
+
+    #[test]
+    fn overlapping_spheres() {
+        // Left spans t 4 to 6 and right 5.5 to 7.5.
+        assert_crossings(
+            crossings(CsgOperation::Union, 0.0, 1.5, -5.0),
+            &[(4.0, true), (7.5, false)],
+        );
+        assert_crossings(
+            crossings(CsgOperation::Intersection, 0.0, 1.5, -5.0),
+            &[(5.5, true), (6.0, false)],
+        );
+        assert_crossings(
+            crossings(CsgOperation::Difference, 0.0, 1.5, -5.0),
+            &[(4.0, true), (5.5, false)],
+        );
+        // Seen from the other side, the difference is entered where the right sphere ends.
+        assert_crossings(
+            crossings(CsgOperation::Difference, 1.5, 0.0, -5.0),
+            &[(6.0, true), (7.5, false)],
+        );
+    }
+
+    #[test]
+    fn disjoint_spheres() {
+        // Left spans t 4 to 6 and right 7 to 9.
+        assert_crossings(
+            crossings(CsgOperation::Union, 0.0, 3.0, -5.0),
+            &[(4.0, true), (6.0, false), (7.0, true), (9.0, false)],
+        );
+        assert_crossings(crossings(CsgOperation::Intersection, 0.0, 3.0, -5.0), &[]);
+        assert_crossings(
+            crossings(CsgOperation::Difference, 0.0, 3.0, -5.0),
+            &[(4.0, true), (6.0, false)],
+        );
+    }
+
+    #[test]
+    fn coincident_spheres() {
+        // A sphere carved out of itself leaves nothing.
+        assert_crossings(crossings(CsgOperation::Difference, 0.0, 0.0, -5.0), &[]);
+        assert_crossings(
+            crossings(CsgOperation::Union, 0.0, 0.0, -5.0),
+            &[(4.0, true), (6.0, false)],
+        );
+        assert_crossings(
+            crossings(CsgOperation::Intersection, 0.0, 0.0, -5.0),
+            &[(4.0, true), (6.0, false)],
+        );
+    }
+
+    #[test]
+    fn rays_starting_inside() {
+        // From x = 0.75 the ray is inside both spheres; the left one ends at t 0.25 and the
+        // right one at 1.75.
+        assert_crossings(
+            crossings(CsgOperation::Union, 0.0, 1.5, 0.75),
+            &[(1.75, false)],
+        );
+        assert_crossings(
+            crossings(CsgOperation::Intersection, 0.0, 1.5, 0.75),
+            &[(0.25, false)],
+        );
+        assert_crossings(crossings(CsgOperation::Difference, 0.0, 1.5, 0.75), &[]);
+        // Inside the left sphere only: the difference is left where the right one starts.
+        assert_crossings(
+            crossings(CsgOperation::Difference, 0.0, 1.5, 0.0),
+            &[(0.5, false)],
+        );
+    }
+
+    #[test]
+    fn crossings_past_t_max_are_dropped() {
+        let csg = Csg {
+            operation: CsgOperation::Union,
+            left: sphere(0.0),
+            right: sphere(3.0),
+        };
+        let ray = Ray {
+            origin: Point::new(-5.0, 0.0, 0.0),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        let ts: Vec<f64> = csg
+            .hit_all(&ray, 0.001, 6.5)
+            .iter()
+            .map(|hit| hit.t)
+            .collect();
+        assert_eq!(ts.len(), 2, "{:?}", ts);
+        assert!((ts[1] - 6.0).abs() < 1e-9);
+    }
+}
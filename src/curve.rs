@@ -0,0 +1,425 @@
+use crate::math::{
+    cross_product, dot_product, to_unit_vector, Aabb, Onb, Point, Ray, Transform, Vec3,
+};
+use crate::trace::{HitRecord, Hittable, Material};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CurveKind {
+    // Flat strip that always faces the ray.
+    Ribbon,
+    // The same strip, shaded as if it were a round tube.
+    Cylinder,
+}
+
+// Cubic Bezier curve swept with a width going from `widths[0]` at its start to `widths[1]` at
+// its end. Texture coordinates are the curve parameter along it and the position across its
+// width, which is what hair shading reads.
+pub struct Curve {
+    pub control_points: [Point; 4],
+    pub widths: [f64; 2],
+    pub kind: CurveKind,
+    pub material: Arc<dyn Material + Send + Sync>,
+}
+
+fn lerp(t: f64, a: &Point, b: &Point) -> Point {
+    *a * (1.0 - t) + *b * t
+}
+
+// de Casteljau split of a curve into halves, which share the middle point.
+fn subdivide(cp: &[Point; 4]) -> [Point; 7] {
+    [
+        cp[0],
+        (cp[0] + cp[1]) * 0.5,
+        (cp[0] + cp[1] * 2.0 + cp[2]) * 0.25,
+        (cp[0] + cp[1] * 3.0 + cp[2] * 3.0 + cp[3]) * 0.125,
+        (cp[1] + cp[2] * 2.0 + cp[3]) * 0.25,
+        (cp[2] + cp[3]) * 0.5,
+        cp[3],
+    ]
+}
+
+// Point on the curve at `u` and the derivative there.
+fn evaluate(cp: &[Point; 4], u: f64) -> (Point, Vec3) {
+    let a = [
+        lerp(u, &cp[0], &cp[1]),
+        lerp(u, &cp[1], &cp[2]),
+        lerp(u, &cp[2], &cp[3]),
+    ];
+    let b = [lerp(u, &a[0], &a[1]), lerp(u, &a[1], &a[2])];
+    // The derivative vanishes where control points coincide; the chord stands in for it.
+    let derivative = if (b[1] - b[0]).length_squared() > 0.0 {
+        (b[1] - b[0]) * 3.0
+    } else {
+        cp[3] - cp[0]
+    };
+    (lerp(u, &b[0], &b[1]), derivative)
+}
+
+impl Curve {
+    const MAX_DEPTH: u32 = 10;
+
+    // Searches the part of the curve from `u0` to `u1`, with control points `cp` in a space
+    // where the ray starts at the origin and runs along +z, halving it `depth` more times
+    // before treating it as a straight segment. Returns the distance along the ray, the curve
+    // parameter, the position across the width and the width there.
+    #[allow(clippy::too_many_arguments)]
+    fn hit_segment(
+        &self,
+        cp: &[Point; 4],
+        u0: f64,
+        u1: f64,
+        depth: u32,
+        z_min: f64,
+        z_max: f64,
+    ) -> Option<(f64, f64, f64, f64)> {
+        let width_at = |u: f64| self.widths[0] * (1.0 - u) + self.widths[1] * u;
+        if depth > 0 {
+            let split = subdivide(cp);
+            let u = [u0, (u0 + u1) * 0.5, u1];
+            let mut z_max = z_max;
+            let mut closest = None;
+            for half in 0..2 {
+                let cp = [
+                    split[3 * half],
+                    split[3 * half + 1],
+                    split[3 * half + 2],
+                    split[3 * half + 3],
+                ];
+                // Cull halves whose bounds, grown by half the width, miss the ray.
+                let radius = 0.5 * width_at(u[half]).max(width_at(u[half + 1]));
+                let misses = (0..3).any(|axis| {
+                    let low = cp.iter().map(|p| p.e[axis]).fold(f64::INFINITY, f64::min);
+                    let high = cp
+                        .iter()
+                        .map(|p| p.e[axis])
+                        .fold(f64::NEG_INFINITY, f64::max);
+                    let (range_min, range_max) = if axis == 2 {
+                        (z_min, z_max)
+                    } else {
+                        (0.0, 0.0)
+                    };
+                    high + radius < range_min || low - radius > range_max
+                });
+                if misses {
+                    continue;
+                }
+                if let Some(hit) =
+                    self.hit_segment(&cp, u[half], u[half + 1], depth - 1, z_min, z_max)
+                {
+                    z_max = hit.0;
+                    closest = Some(hit);
+                }
+            }
+            return closest;
+        }
+
+        // The ray must pass between the planes through the segment's ends perpendicular to it,
+        // so neighbouring segments don't both report the same hit.
+        let start_edge = (cp[1].y() - cp[0].y()) * -cp[0].y() + cp[0].x() * (cp[0].x() - cp[1].x());
+        let end_edge = (cp[2].y() - cp[3].y()) * -cp[3].y() + cp[3].x() * (cp[3].x() - cp[2].x());
+        if start_edge < 0.0 || end_edge < 0.0 {
+            return None;
+        }
+
+        // Closest point to the ray along the chord, then on the curve itself.
+        let chord = (cp[3].x() - cp[0].x(), cp[3].y() - cp[0].y());
+        let denominator = chord.0 * chord.0 + chord.1 * chord.1;
+        if denominator == 0.0 {
+            return None;
+        }
+        let w = -(cp[0].x() * chord.0 + cp[0].y() * chord.1) / denominator;
+        let u = (u0 + (u1 - u0) * w).clamp(u0, u1);
+        let width = width_at(u);
+        let (pc, dpcdw) = evaluate(cp, w.clamp(0.0, 1.0));
+        // The surface turns to face every ray, so a ray leaving the curve would find it again
+        // no further away than half the width; hits that close are taken to be the ray leaving.
+        let z_min = z_min.max(0.5 * width);
+        let distance_squared = pc.x() * pc.x() + pc.y() * pc.y();
+        if distance_squared > width * width * 0.25 || pc.z() < z_min || pc.z() > z_max {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        let side = dpcdw.x() * -pc.y() + pc.x() * dpcdw.y();
+        let v = if side > 0.0 {
+            0.5 + distance / width
+        } else {
+            0.5 - distance / width
+        };
+        Some((pc.z(), u, v, width))
+    }
+}
+
+impl Hittable for Curve {
+    // Nakamaru and Ohno's ray/curve intersection, as in pbrt: the curve is split until each
+    // piece is close enough to straight, within 5% of its width, to be tested as a segment.
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let frame = Onb::from_w(&ray.direction);
+        let to_ray_space = |p: &Point| {
+            let offset = *p - ray.origin;
+            Point::new(
+                dot_product(&offset, &frame.u),
+                dot_product(&offset, &frame.v),
+                dot_product(&offset, &frame.w),
+            )
+        };
+        let cp = self.control_points.map(|p| to_ray_space(&p));
+
+        let mut flatness: f64 = 0.0;
+        for i in 0..2 {
+            let bend = cp[i] - cp[i + 1] * 2.0 + cp[i + 2];
+            flatness = flatness.max(bend.x().abs().max(bend.y().abs()).max(bend.z().abs()));
+        }
+        let tolerance = self.widths[0].max(self.widths[1]) * 0.05;
+        let depth = if flatness > 0.0 {
+            let levels =
+                (std::f64::consts::SQRT_2 * 6.0 * flatness / (8.0 * tolerance)).log2() * 0.5;
+            levels.ceil().clamp(0.0, Curve::MAX_DEPTH as f64) as u32
+        } else {
+            0
+        };
+
+        let speed = ray.direction.length();
+        let (z, u, v, width) =
+            self.hit_segment(&cp, 0.0, 1.0, depth, t_min * speed, t_max * speed)?;
+        let t = z / speed;
+
+        let dpdu = evaluate(&self.control_points, u).1;
+        let across = to_unit_vector(&cross_product(&frame.w, &dpdu));
+        let facing = to_unit_vector(&cross_product(&dpdu, &across));
+        let mut hit = HitRecord::from_hit(&ray.at(t), ray, t, &facing, u, v, self.material.clone());
+        match self.kind {
+            CurveKind::Ribbon => hit.set_tangents(&dpdu, &(across * width)),
+            CurveKind::Cylinder => {
+                // Tilt the normal as a tube's would be at this offset from its axis, so it turns
+                // edge on at either side.
+                let tilt = Transform::rotate(&dpdu, -(2.0 * v - 1.0).asin().to_degrees());
+                let dpdv = tilt.vector(&across) * width;
+                hit.set_tangents(&dpdu, &dpdv);
+                hit.set_shading_normal(&cross_product(&dpdu, &dpdv));
+            }
+        }
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = 0.5 * self.widths[0].max(self.widths[1]);
+        let padding = Vec3::new(radius, radius, radius);
+        let bounds = self.control_points[1..].iter().fold(
+            Aabb::new(self.control_points[0], self.control_points[0]),
+            |bounds, p| bounds.grow(p),
+        );
+        Some(Aabb::new(bounds.min - padding, bounds.max + padding))
+    }
+}
+
+// Reads a strand file: one strand per line, each a list of vertices written `x y z width`.
+// Strands pass smoothly through their vertices as Catmull-Rom splines, each span becoming one
+// Bezier curve. Blank lines and lines starting with `#` are skipped.
+pub fn load_strands(
+    path: &Path,
+    kind: CurveKind,
+    material: Arc<dyn Material + Send + Sync>,
+) -> io::Result<Vec<Curve>> {
+    read_strands(path, BufReader::new(File::open(path)?), kind, material)
+}
+
+// Parses strands from `reader`; `path` only names the file in errors.
+fn read_strands(
+    path: &Path,
+    reader: impl BufRead,
+    kind: CurveKind,
+    material: Arc<dyn Material + Send + Sync>,
+) -> io::Result<Vec<Curve>> {
+    let error = |line: usize, msg: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}:{}: {}", path.display(), line, msg),
+        )
+    };
+    let mut curves = vec![];
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let numbers = line
+            .split_whitespace()
+            .map(|token| token.parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| error(idx + 1, "expected numbers"))?;
+        if numbers.len() % 4 != 0 || numbers.len() < 8 {
+            return Err(error(
+                idx + 1,
+                "expected at least two vertices of four numbers each",
+            ));
+        }
+        let vertices: Vec<(Point, f64)> = numbers
+            .chunks_exact(4)
+            .map(|vertex| (Point::new(vertex[0], vertex[1], vertex[2]), vertex[3]))
+            .collect();
+        if vertices.iter().any(|(_, width)| *width <= 0.0) {
+            return Err(error(idx + 1, "widths must be positive"));
+        }
+
+        let last = vertices.len() - 1;
+        for span in 0..last {
+            let before = vertices[span.saturating_sub(1)].0;
+            let (start, start_width) = vertices[span];
+            let (end, end_width) = vertices[span + 1];
+            let after = vertices[(span + 2).min(last)].0;
+            curves.push(Curve {
+                control_points: [
+                    start,
+                    start + (end - before) / 6.0,
+                    end - (after - start) / 6.0,
+                    end,
+                ],
+                widths: [start_width, end_width],
+                kind,
+                material: material.clone(),
+            });
+        }
+    }
+    Ok(curves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Color;
+    use crate::test_util::error_message;
+    use crate::trace::LambertianMaterial;
+
+    fn material() -> Arc<dyn Material + Send + Sync> {
+        Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn parse(text: &str) -> io::Result<Vec<Curve>> {
+        read_strands(
+            Path::new("test.strands"),
+            text.as_bytes(),
+            CurveKind::Ribbon,
+            material(),
+        )
+    }
+
+    // A straight strand along y from the origin to y = 1.
+    fn strand(width: f64) -> Curve {
+        Curve {
+            control_points: [
+                Point::new(0.0, 0.0, 0.0),
+                Point::new(0.0, 1.0 / 3.0, 0.0),
+                Point::new(0.0, 2.0 / 3.0, 0.0),
+                Point::new(0.0, 1.0, 0.0),
+            ],
+            widths: [width, width],
+            kind: CurveKind::Cylinder,
+            material: material(),
+        }
+    }
+
+    fn ray(origin: Point, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time: 0.0,
+        }
+    }
+
+    #[test]
+    fn strands_become_catmull_rom_spans() {
+        let curves = parse("# a comment\n\n0 0 0 0.1  0 1 0 0.2  1 1 0 0.3\n").unwrap();
+        assert_eq!(curves.len(), 2);
+        let [p0, p1, p2, p3] = curves[0].control_points;
+        assert_eq!(p0, Point::new(0.0, 0.0, 0.0));
+        // The first vertex stands in for the one before it.
+        assert_eq!(p1, Point::new(0.0, 1.0 / 6.0, 0.0));
+        assert_eq!(p2, Point::new(-1.0 / 6.0, 5.0 / 6.0, 0.0));
+        assert_eq!(p3, Point::new(0.0, 1.0, 0.0));
+        assert_eq!(curves[0].widths, [0.1, 0.2]);
+        assert_eq!(curves[1].control_points[0], p3);
+        assert_eq!(curves[1].control_points[3], Point::new(1.0, 1.0, 0.0));
+        assert_eq!(curves[1].widths, [0.2, 0.3]);
+    }
+
+    #[test]
+    fn every_line_is_a_strand() {
+        let curves =
+            parse("0 0 0 1 0 1 0 1\n  # indented comment\n0 0 1 1 0 1 1 1 0 2 1 1\n").unwrap();
+        assert_eq!(curves.len(), 3);
+    }
+
+    #[test]
+    fn malformed_strands_are_errors() {
+        let message = error_message(parse("0 0 0 1 0 1 0 1\n0 0 0 1 0 1 0\n"));
+        assert_eq!(
+            message,
+            "test.strands:2: expected at least two vertices of four numbers each"
+        );
+        let message = error_message(parse("0 0 0 1\n"));
+        assert!(message.starts_with("test.strands:1: "), "{}", message);
+        let message = error_message(parse("\n0 0 0 1 0 1 0 x\n"));
+        assert_eq!(message, "test.strands:2: expected numbers");
+        let message = error_message(parse("0 0 0 1 0 1 0 0\n"));
+        assert_eq!(message, "test.strands:1: widths must be positive");
+    }
+
+    #[test]
+    fn rays_hit_the_strand_across_its_width() {
+        let curve = strand(0.1);
+        let hit = curve
+            .hit(
+                &ray(Point::new(0.02, 0.5, -2.0), Vec3::new(0.0, 0.0, 1.0)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-9);
+        assert!((hit.u - 0.5).abs() < 1e-6);
+        assert!(((hit.v - 0.5).abs() - 0.2).abs() < 1e-6);
+        assert!(curve
+            .hit(
+                &ray(Point::new(0.06, 0.5, -2.0), Vec3::new(0.0, 0.0, 1.0)),
+                0.001,
+                f64::INFINITY,
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn rays_leaving_the_strand_do_not_hit_it_again() {
+        let curve = strand(0.1);
+        let incoming = ray(Point::new(0.03, 0.5, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = curve.hit(&incoming, 0.001, f64::INFINITY).unwrap();
+        for direction in [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, 0.2),
+            Vec3::new(-1.0, 0.3, 0.5),
+            Vec3::new(-0.2, 0.1, 1.0),
+        ] {
+            let outgoing = hit.spawn_ray(&direction);
+            assert!(
+                curve.hit(&outgoing, 0.001, f64::INFINITY).is_none(),
+                "{:?}",
+                direction
+            );
+        }
+    }
+
+    #[test]
+    fn rays_starting_near_another_strand_still_hit_it() {
+        // The origin is within one width of the strand, but well outside it.
+        let curve = strand(0.1);
+        let near = ray(Point::new(0.03, 0.5, -0.06), Vec3::new(0.0, 0.0, 1.0));
+        let hit = curve.hit(&near, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 0.06).abs() < 1e-9);
+    }
+}
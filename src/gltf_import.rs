@@ -0,0 +1,443 @@
+use crate::bump::{NormalMap, PerturbedMaterial};
+use crate::image::Image;
+use crate::instance::Instance;
+use crate::math::{Color, Mat4, Point, Transform, Vec3};
+use crate::mesh::{Mesh, TriangleMesh};
+use crate::pbr::PbrMaterial;
+use crate::scene::CameraSettings;
+use crate::texture::{ImageTexture, SolidColor, Texture};
+use crate::trace::{Hittable, Material, WHITE};
+use gltf::image::Format;
+use gltf::mesh::Mode;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// What a glTF file's default scene, or else its first, turns into.
+pub struct GltfScene {
+    // One instance per mesh primitive per node, placed by the node's world transform.
+    pub hittables: Vec<Arc<dyn Hittable + Send + Sync>>,
+    // Perspective cameras in the order their nodes are visited, each with the aspect ratio it
+    // asks for, if any.
+    pub cameras: Vec<(CameraSettings, Option<f64>)>,
+}
+
+struct Importer<'a> {
+    path: &'a Path,
+    buffers: Vec<gltf::buffer::Data>,
+    images: Vec<gltf::image::Data>,
+    // Images by index and whether they hold sRGB colors.
+    textures: HashMap<(usize, bool), Arc<dyn Texture + Send + Sync>>,
+    // Materials by index, with None for glTF's default material.
+    materials: HashMap<Option<usize>, Arc<dyn Material + Send + Sync>>,
+    // Primitives by mesh and primitive index, shared by every node using the mesh; None for
+    // primitives without triangles.
+    meshes: HashMap<(usize, usize), Option<Arc<Mesh>>>,
+    scene: GltfScene,
+}
+
+// Reads a .gltf file, with its buffers and images beside it or embedded, or a .glb file.
+//
+// Meshes keep their normals, first set of texture coordinates and first set of vertex colors;
+// points and lines are skipped. Materials follow the metallic-roughness model, with base color,
+// metallic-roughness, normal and emissive textures read through the first texture coordinates.
+// Extensions, alpha and double-sidedness are ignored.
+pub fn load_gltf(path: &Path) -> io::Result<GltfScene> {
+    let error = |msg: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), msg),
+        )
+    };
+    let (document, buffers, images) = gltf::import(path).map_err(|err| error(&err.to_string()))?;
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or_else(|| error("no scenes"))?;
+
+    let mut importer = Importer {
+        path,
+        buffers,
+        images,
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        meshes: HashMap::new(),
+        scene: GltfScene {
+            hittables: vec![],
+            cameras: vec![],
+        },
+    };
+    for node in scene.nodes() {
+        importer.add_node(&node, &Mat4::identity())?;
+    }
+    Ok(importer.scene)
+}
+
+impl<'a> Importer<'a> {
+    fn error(&self, msg: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", self.path.display(), msg),
+        )
+    }
+
+    fn add_node(&mut self, node: &gltf::Node, parent: &Mat4) -> io::Result<()> {
+        // glTF stores matrices by column.
+        let columns = node.transform().matrix();
+        let mut local = [[0.0; 4]; 4];
+        for (i, row) in local.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = columns[j][i] as f64;
+            }
+        }
+        let world = *parent * Mat4::new(local);
+
+        // Nodes scaled to nothing are hidden along with everything on them.
+        if let Some(transform) = Transform::from_matrix(world) {
+            if let Some(camera) = node.camera() {
+                if let gltf::camera::Projection::Perspective(perspective) = camera.projection() {
+                    // Cameras look down their -z axis with +y up.
+                    let look_from = transform.point(&Point::new(0.0, 0.0, 0.0));
+                    let forward = transform.vector(&Vec3::new(0.0, 0.0, -1.0));
+                    self.scene.cameras.push((
+                        CameraSettings {
+                            look_from,
+                            look_at: look_from + forward,
+                            vup: transform.vector(&Vec3::new(0.0, 1.0, 0.0)),
+                            vfov: (perspective.yfov() as f64).to_degrees(),
+                            aperture: 0.0,
+                            focus_distance: None,
+                            shutter_open: 0.0,
+                            shutter_close: 0.0,
+                        },
+                        perspective.aspect_ratio().map(|ratio| ratio as f64),
+                    ));
+                }
+            }
+            if let Some(mesh) = node.mesh() {
+                for primitive in mesh.primitives() {
+                    if let Some(data) = self.primitive(&mesh, &primitive)? {
+                        self.scene
+                            .hittables
+                            .push(Arc::new(Instance::new(data, transform)));
+                    }
+                }
+            }
+        }
+
+        for child in node.children() {
+            self.add_node(&child, &world)?;
+        }
+        Ok(())
+    }
+
+    fn primitive(
+        &mut self,
+        mesh: &gltf::Mesh,
+        primitive: &gltf::Primitive,
+    ) -> io::Result<Option<Arc<Mesh>>> {
+        let key = (mesh.index(), primitive.index());
+        if let Some(data) = self.meshes.get(&key) {
+            return Ok(data.clone());
+        }
+        let data = self.read_primitive(mesh, primitive)?.map(Arc::new);
+        self.meshes.insert(key, data.clone());
+        Ok(data)
+    }
+
+    fn read_primitive(
+        &mut self,
+        mesh: &gltf::Mesh,
+        primitive: &gltf::Primitive,
+    ) -> io::Result<Option<Mesh>> {
+        let mode = primitive.mode();
+        if !matches!(
+            mode,
+            Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan
+        ) {
+            return Ok(None);
+        }
+        let context = format!(
+            "mesh {} primitive {}",
+            mesh.name().map_or(mesh.index().to_string(), str::to_string),
+            primitive.index()
+        );
+
+        let buffers = &self.buffers;
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let positions: Vec<Point> = reader
+            .read_positions()
+            .ok_or_else(|| self.error(&format!("{}: no positions", context)))?
+            .map(|p| Point::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+        let normals: Vec<Vec3> = reader.read_normals().map_or(vec![], |normals| {
+            normals
+                .map(|n| Vec3::new(n[0] as f64, n[1] as f64, n[2] as f64))
+                .collect()
+        });
+        let uvs: Vec<(f64, f64)> = reader.read_tex_coords(0).map_or(vec![], |uvs| {
+            uvs.into_f32().map(texture_coordinates).collect()
+        });
+        let colors: Vec<Color> = reader.read_colors(0).map_or(vec![], |colors| {
+            colors
+                .into_rgb_f32()
+                .map(|c| Color::new(c[0] as f64, c[1] as f64, c[2] as f64))
+                .collect()
+        });
+        let vertices: Vec<usize> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+            None => (0..positions.len()).collect(),
+        };
+        for (name, count) in [
+            ("normals", normals.len()),
+            ("texture coordinates", uvs.len()),
+            ("colors", colors.len()),
+        ] {
+            if count != 0 && count != positions.len() {
+                return Err(self.error(&format!(
+                    "{}: {} {} for {} positions",
+                    context,
+                    count,
+                    name,
+                    positions.len()
+                )));
+            }
+        }
+        if let Some(index) = vertices.iter().find(|&&index| index >= positions.len()) {
+            return Err(self.error(&format!(
+                "{}: index {} is out of range for {} positions",
+                context,
+                index,
+                positions.len()
+            )));
+        }
+
+        let indices = triangle_indices(mode, &vertices);
+        if indices.is_empty() {
+            return Ok(None);
+        }
+
+        let material = self.material(&primitive.material());
+        Ok(Some(Mesh::new(TriangleMesh {
+            positions,
+            normals,
+            uvs,
+            colors,
+            indices,
+            material,
+        })))
+    }
+
+    fn material(&mut self, material: &gltf::Material) -> Arc<dyn Material + Send + Sync> {
+        if let Some(built) = self.materials.get(&material.index()) {
+            return built.clone();
+        }
+        let pbr = material.pbr_metallic_roughness();
+        let base_color = pbr.base_color_factor();
+        let emissive = material.emissive_factor();
+        let surface = Arc::new(PbrMaterial {
+            base_color: Color::new(
+                base_color[0] as f64,
+                base_color[1] as f64,
+                base_color[2] as f64,
+            ),
+            base_color_texture: self
+                .texture(pbr.base_color_texture().map(|info| info.texture()), true),
+            metallic: pbr.metallic_factor() as f64,
+            roughness: pbr.roughness_factor() as f64,
+            metallic_roughness_texture: self.texture(
+                pbr.metallic_roughness_texture().map(|info| info.texture()),
+                false,
+            ),
+            emissive: Color::new(emissive[0] as f64, emissive[1] as f64, emissive[2] as f64),
+            emissive_texture: self
+                .texture(material.emissive_texture().map(|info| info.texture()), true),
+        });
+        let built: Arc<dyn Material + Send + Sync> = match material.normal_texture() {
+            Some(normal) => Arc::new(PerturbedMaterial {
+                material: surface,
+                perturbation: NormalMap {
+                    normals: self.texture(Some(normal.texture()), false),
+                },
+            }),
+            None => surface,
+        };
+        self.materials.insert(material.index(), built.clone());
+        built
+    }
+
+    // The texture's image, or white where there is none.
+    fn texture(
+        &mut self,
+        texture: Option<gltf::Texture>,
+        srgb: bool,
+    ) -> Arc<dyn Texture + Send + Sync> {
+        let Some(texture) = texture else {
+            return Arc::new(SolidColor::new(WHITE));
+        };
+        let key = (texture.source().index(), srgb);
+        let images = &self.images;
+        self.textures
+            .entry(key)
+            .or_insert_with(|| Arc::new(ImageTexture::new(convert_image(&images[key.0]), srgb)))
+            .clone()
+    }
+}
+
+// Images with one or two channels are gray, the second channel being alpha. Alpha is dropped.
+// glTF puts v = 0 at the top of an image, where textures here have v = 1.
+fn texture_coordinates([u, v]: [f32; 2]) -> (f64, f64) {
+    (u as f64, 1.0 - v as f64)
+}
+
+// Triangles from a list of vertex indices, read as separate triangles, a strip or a fan. Left
+// over indices that make no whole triangle are dropped.
+fn triangle_indices(mode: Mode, vertices: &[usize]) -> Vec<[usize; 3]> {
+    let triangle_count = match mode {
+        Mode::Triangles => vertices.len() / 3,
+        _ => vertices.len().saturating_sub(2),
+    };
+    (0..triangle_count)
+        .map(|i| match mode {
+            Mode::Triangles => [vertices[3 * i], vertices[3 * i + 1], vertices[3 * i + 2]],
+            // Every other triangle of a strip is flipped to keep the winding.
+            Mode::TriangleStrip if i % 2 == 1 => [vertices[i + 1], vertices[i], vertices[i + 2]],
+            Mode::TriangleStrip => [vertices[i], vertices[i + 1], vertices[i + 2]],
+            _ => [vertices[0], vertices[i + 1], vertices[i + 2]],
+        })
+        .collect()
+}
+
+fn convert_image(data: &gltf::image::Data) -> Image {
+    let (channels, size) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    let sample = |bytes: &[u8]| match bytes {
+        [byte] => *byte as f64 / 255.0,
+        [a, b] => u16::from_ne_bytes([*a, *b]) as f64 / 65535.0,
+        _ => f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+    };
+    let pixels = data
+        .pixels
+        .chunks_exact(channels * size)
+        .map(|pixel| {
+            let channel = |i: usize| sample(&pixel[i * size..(i + 1) * size]);
+            if channels < 3 {
+                Color::new(channel(0), channel(0), channel(0))
+            } else {
+                Color::new(channel(0), channel(1), channel(2))
+            }
+        })
+        .collect();
+    Image {
+        width: data.width as usize,
+        height: data.height as usize,
+        pixels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(format: Format, pixels: Vec<u8>) -> Image {
+        convert_image(&gltf::image::Data {
+            pixels,
+            format,
+            width: 2,
+            height: 1,
+        })
+    }
+
+    #[test]
+    fn separate_triangles() {
+        assert_eq!(
+            triangle_indices(Mode::Triangles, &[0, 1, 2, 2, 1, 3, 4]),
+            vec![[0, 1, 2], [2, 1, 3]]
+        );
+        assert!(triangle_indices(Mode::Triangles, &[0, 1]).is_empty());
+    }
+
+    #[test]
+    fn strips_keep_their_winding() {
+        assert_eq!(
+            triangle_indices(Mode::TriangleStrip, &[0, 1, 2, 3, 4]),
+            vec![[0, 1, 2], [2, 1, 3], [2, 3, 4]]
+        );
+        assert!(triangle_indices(Mode::TriangleStrip, &[0, 1]).is_empty());
+        assert!(triangle_indices(Mode::TriangleStrip, &[]).is_empty());
+    }
+
+    #[test]
+    fn fans_share_their_first_vertex() {
+        assert_eq!(
+            triangle_indices(Mode::TriangleFan, &[5, 1, 2, 3, 4]),
+            vec![[5, 1, 2], [5, 2, 3], [5, 3, 4]]
+        );
+        assert!(triangle_indices(Mode::TriangleFan, &[0]).is_empty());
+    }
+
+    #[test]
+    fn texture_coordinates_flip_v() {
+        assert_eq!(texture_coordinates([0.25, 0.0]), (0.25, 1.0));
+        assert_eq!(texture_coordinates([1.0, 0.75]), (1.0, 0.25));
+    }
+
+    #[test]
+    fn gray_images_fill_every_channel() {
+        let converted = image(Format::R8, vec![255, 51]);
+        assert_eq!((converted.width, converted.height), (2, 1));
+        assert_eq!(
+            converted.pixels,
+            vec![Color::new(1.0, 1.0, 1.0), Color::new(0.2, 0.2, 0.2)]
+        );
+    }
+
+    #[test]
+    fn alpha_is_dropped() {
+        // Gray and alpha.
+        let converted = image(Format::R8G8, vec![51, 0, 255, 128]);
+        assert_eq!(
+            converted.pixels,
+            vec![Color::new(0.2, 0.2, 0.2), Color::new(1.0, 1.0, 1.0)]
+        );
+        let converted = image(Format::R8G8B8A8, vec![255, 0, 51, 0, 0, 255, 0, 255]);
+        assert_eq!(
+            converted.pixels,
+            vec![Color::new(1.0, 0.0, 0.2), Color::new(0.0, 1.0, 0.0)]
+        );
+    }
+
+    #[test]
+    fn wide_samples_are_native_endian() {
+        let mut pixels = vec![];
+        for sample in [65535u16, 0, 0, 0, 0, 65535] {
+            pixels.extend_from_slice(&sample.to_ne_bytes());
+        }
+        let converted = image(Format::R16G16B16, pixels);
+        assert_eq!(
+            converted.pixels,
+            vec![Color::new(1.0, 0.0, 0.0), Color::new(0.0, 0.0, 1.0)]
+        );
+
+        let mut pixels = vec![];
+        for sample in [0.5f32, 2.0, 0.0, 1.0, 0.25, 0.0, 0.0, 0.0] {
+            pixels.extend_from_slice(&sample.to_ne_bytes());
+        }
+        let converted = image(Format::R32G32B32A32FLOAT, pixels);
+        assert_eq!(
+            converted.pixels,
+            vec![Color::new(0.5, 2.0, 0.0), Color::new(0.25, 0.0, 0.0)]
+        );
+    }
+}
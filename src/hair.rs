@@ -0,0 +1,482 @@
+use crate::math::{
+    cross_product, dot_product, random_float, to_unit_vector, Color, Onb, Ray, Vec3,
+};
+use crate::pdf::Pdf;
+use crate::trace::{HitRecord, Material, ScatterRecord};
+use std::f64::consts::PI;
+
+// Chiang, Bitterli, Tappan and Burley's hair scattering model, following pbrt. Light reflects
+// off the fiber (R), passes through it (TT), reflects once inside (TRT), and anything more is
+// lumped into one last term. `sigma_a` is the absorption inside the fiber per unit of its
+// diameter, `beta_m` and `beta_n` are the longitudinal and azimuthal roughness in (0, 1], and
+// `alpha` tilts the cuticle scales, in degrees.
+//
+// The fiber runs along the hit's `dpdu`, and `v` is the position across it from one edge to the
+// other, as curves report.
+pub struct HairMaterial {
+    pub sigma_a: Color,
+    pub eta: f64,
+    pub beta_m: f64,
+    pub beta_n: f64,
+    pub alpha: f64,
+}
+
+// The model set up for one hit, in a frame with x along the fiber and z along the normal.
+struct HairBsdf {
+    frame: Onb,
+    outgoing: Vec3,
+    h: f64,
+    gamma_o: f64,
+    eta: f64,
+    sigma_a: Color,
+    // Longitudinal variance and azimuthal scale of each term.
+    v: [f64; HairBsdf::P_MAX + 1],
+    s: f64,
+    // Sines and cosines of the cuticle angle times 2, 4 and 8.
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+}
+
+impl HairMaterial {
+    // Absorption of hair with the given concentrations of the two kinds of melanin; black hair
+    // has about 8 of eumelanin, brown about 1.3 and blonde 0.3, while red hair is mostly
+    // pheomelanin.
+    pub fn sigma_a_from_melanin(eumelanin: f64, pheomelanin: f64) -> Color {
+        Color::new(0.419, 0.697, 1.37) * eumelanin + Color::new(0.187, 0.4, 1.05) * pheomelanin
+    }
+
+    // Absorption that gives roughly `color` as the hair's overall color, for the given azimuthal
+    // roughness.
+    pub fn sigma_a_from_color(color: &Color, beta_n: f64) -> Color {
+        let scale = 5.969 - 0.215 * beta_n + 2.532 * beta_n.powi(2) - 10.73 * beta_n.powi(3)
+            + 5.574 * beta_n.powi(4)
+            + 0.245 * beta_n.powi(5);
+        let channel = |c: f64| (c.max(1e-4).ln() / scale).powi(2);
+        Color::new(channel(color.x()), channel(color.y()), channel(color.z()))
+    }
+
+    fn bsdf(&self, ray: &Ray, hit: &HitRecord) -> HairBsdf {
+        let normal = hit.normal;
+        let along = to_unit_vector(&(hit.dpdu - normal * dot_product(&hit.dpdu, &normal)));
+        let frame = Onb {
+            u: along,
+            v: cross_product(&normal, &along),
+            w: normal,
+        };
+        let outgoing = to_local(&frame, &-to_unit_vector(&ray.direction));
+        // The model measures the offset from the fiber's axis towards `frame.v`, which seen from
+        // the ray is the opposite side to where curves put `v` = 1.
+        HairBsdf::new(frame, outgoing, (1.0 - 2.0 * hit.v).clamp(-1.0, 1.0), self)
+    }
+}
+
+impl Material for HairMaterial {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Sampled(Box::new(self.bsdf(ray, hit))))
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color {
+        let bsdf = self.bsdf(ray, hit);
+        bsdf.eval(&to_local(&bsdf.frame, &to_unit_vector(direction)))
+    }
+}
+
+fn to_local(frame: &Onb, direction: &Vec3) -> Vec3 {
+    Vec3::new(
+        dot_product(direction, &frame.u),
+        dot_product(direction, &frame.v),
+        dot_product(direction, &frame.w),
+    )
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
+
+fn exp(c: &Color) -> Color {
+    Color::new(c.x().exp(), c.y().exp(), c.z().exp())
+}
+
+fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+// Unpolarized reflectance entering a dielectric of index `eta` from outside.
+fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let (cos_i, eta) = if cos_i < 0.0 {
+        (-cos_i, 1.0 / eta)
+    } else {
+        (cos_i, eta)
+    };
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+// Modified Bessel function of the first kind, order zero.
+fn bessel_i0(x: f64) -> f64 {
+    let mut value = 0.0;
+    let mut x_2i = 1.0;
+    let mut factorial: f64 = 1.0;
+    let mut four_i = 1.0;
+    for i in 0..10 {
+        if i > 1 {
+            factorial *= i as f64;
+        }
+        value += x_2i / (four_i * factorial * factorial);
+        x_2i *= x * x;
+        four_i *= 4.0;
+    }
+    value
+}
+
+fn log_bessel_i0(x: f64) -> f64 {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        bessel_i0(x).ln()
+    }
+}
+
+// Longitudinal scattering.
+fn m_p(cos_theta_i: f64, cos_theta_o: f64, sin_theta_i: f64, sin_theta_o: f64, v: f64) -> f64 {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    // Low roughness overflows the direct form.
+    if v <= 0.1 {
+        (log_bessel_i0(a) - b - 1.0 / v + std::f64::consts::LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        (-b).exp() * bessel_i0(a) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+// Attenuation of each term, given the transmittance `t` of one pass through the fiber.
+fn a_p(cos_theta_o: f64, eta: f64, h: f64, t: &Color) -> [Color; HairBsdf::P_MAX + 1] {
+    let cos_gamma_o = safe_sqrt(1.0 - h * h);
+    let f = fresnel_dielectric(cos_theta_o * cos_gamma_o, eta);
+    let mut a = [Color::new(f, f, f); HairBsdf::P_MAX + 1];
+    a[1] = *t * (1.0 - f) * (1.0 - f);
+    for p in 2..HairBsdf::P_MAX {
+        a[p] = a[p - 1] * *t * f;
+    }
+    let tf = *t * f;
+    let last = a[HairBsdf::P_MAX - 1] * tf;
+    a[HairBsdf::P_MAX] = Color::new(
+        last.x() / (1.0 - tf.x()),
+        last.y() / (1.0 - tf.y()),
+        last.z() / (1.0 - tf.z()),
+    );
+    a
+}
+
+fn phi(p: usize, gamma_o: f64, gamma_t: f64) -> f64 {
+    2.0 * p as f64 * gamma_t - 2.0 * gamma_o + p as f64 * PI
+}
+
+fn logistic(x: f64, s: f64) -> f64 {
+    let x = x.abs();
+    (-x / s).exp() / (s * (1.0 + (-x / s).exp()).powi(2))
+}
+
+fn logistic_cdf(x: f64, s: f64) -> f64 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+fn trimmed_logistic(x: f64, s: f64, a: f64, b: f64) -> f64 {
+    logistic(x, s) / (logistic_cdf(b, s) - logistic_cdf(a, s))
+}
+
+fn sample_trimmed_logistic(u: f64, s: f64, a: f64, b: f64) -> f64 {
+    let k = logistic_cdf(b, s) - logistic_cdf(a, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(a, s)) - 1.0).ln();
+    x.clamp(a, b)
+}
+
+// Azimuthal scattering.
+fn n_p(phi_difference: f64, p: usize, s: f64, gamma_o: f64, gamma_t: f64) -> f64 {
+    let mut dphi = phi_difference - phi(p, gamma_o, gamma_t);
+    while dphi > PI {
+        dphi -= 2.0 * PI;
+    }
+    while dphi < -PI {
+        dphi += 2.0 * PI;
+    }
+    trimmed_logistic(dphi, s, -PI, PI)
+}
+
+impl HairBsdf {
+    const P_MAX: usize = 3;
+
+    fn new(frame: Onb, outgoing: Vec3, h: f64, material: &HairMaterial) -> Self {
+        let beta_m = material.beta_m;
+        let beta_n = material.beta_n;
+        let v0 = (0.726 * beta_m + 0.812 * beta_m.powi(2) + 3.7 * beta_m.powi(20)).powi(2);
+        let s =
+            (PI / 8.0).sqrt() * (0.265 * beta_n + 1.194 * beta_n.powi(2) + 5.372 * beta_n.powi(22));
+        let mut sin_2k_alpha = [material.alpha.to_radians().sin(), 0.0, 0.0];
+        let mut cos_2k_alpha = [safe_sqrt(1.0 - sin_2k_alpha[0].powi(2)), 0.0, 0.0];
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1].powi(2) - sin_2k_alpha[i - 1].powi(2);
+        }
+        HairBsdf {
+            frame,
+            outgoing,
+            h,
+            gamma_o: h.asin(),
+            eta: material.eta,
+            sigma_a: material.sigma_a,
+            v: [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0],
+            s,
+            sin_2k_alpha,
+            cos_2k_alpha,
+        }
+    }
+
+    // The outgoing direction's longitudinal angle tilted by the cuticle scales for term `p`.
+    fn tilted(&self, p: usize, sin_theta_o: f64, cos_theta_o: f64) -> (f64, f64) {
+        let (sin, cos) = match p {
+            0 => (-self.sin_2k_alpha[1], self.cos_2k_alpha[1]),
+            1 => (self.sin_2k_alpha[0], self.cos_2k_alpha[0]),
+            2 => (self.sin_2k_alpha[2], self.cos_2k_alpha[2]),
+            _ => (0.0, 1.0),
+        };
+        (
+            sin_theta_o * cos + cos_theta_o * sin,
+            (cos_theta_o * cos - sin_theta_o * sin).abs(),
+        )
+    }
+
+    // Refracted azimuth and the transmittance of one pass through the fiber.
+    fn refraction(&self, sin_theta_o: f64, cos_theta_o: f64) -> (f64, Color) {
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = safe_sqrt(1.0 - sin_theta_t * sin_theta_t);
+        let eta_p = safe_sqrt(self.eta * self.eta - sin_theta_o * sin_theta_o) / cos_theta_o;
+        let sin_gamma_t = (self.h / eta_p).clamp(-1.0, 1.0);
+        let cos_gamma_t = safe_sqrt(1.0 - sin_gamma_t * sin_gamma_t);
+        let transmittance = exp(&(self.sigma_a * (-2.0 * cos_gamma_t / cos_theta_t)));
+        (sin_gamma_t.asin(), transmittance)
+    }
+
+    // How likely sampling picks each term.
+    fn term_pdfs(&self, sin_theta_o: f64, cos_theta_o: f64) -> [f64; HairBsdf::P_MAX + 1] {
+        let (_, transmittance) = self.refraction(sin_theta_o, cos_theta_o);
+        let a = a_p(cos_theta_o, self.eta, self.h, &transmittance);
+        let total: f64 = a.iter().map(luminance).sum();
+        a.map(|a| luminance(&a) / total)
+    }
+
+    fn angles(direction: &Vec3) -> (f64, f64, f64) {
+        let sin_theta = direction.x().clamp(-1.0, 1.0);
+        (
+            sin_theta,
+            safe_sqrt(1.0 - sin_theta * sin_theta),
+            direction.z().atan2(direction.y()),
+        )
+    }
+
+    // The BSDF times the cosine of `incoming` to the normal, in the local frame.
+    fn eval(&self, incoming: &Vec3) -> Color {
+        let (sin_theta_o, cos_theta_o, phi_o) = HairBsdf::angles(&self.outgoing);
+        let (sin_theta_i, cos_theta_i, phi_i) = HairBsdf::angles(incoming);
+        let (gamma_t, transmittance) = self.refraction(sin_theta_o, cos_theta_o);
+        let a = a_p(cos_theta_o, self.eta, self.h, &transmittance);
+        let phi_difference = phi_i - phi_o;
+
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        for (p, a) in a.iter().enumerate().take(HairBsdf::P_MAX) {
+            let (sin_theta_op, cos_theta_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+            sum +=
+                *a * m_p(
+                    cos_theta_i,
+                    cos_theta_op,
+                    sin_theta_i,
+                    sin_theta_op,
+                    self.v[p],
+                ) * n_p(phi_difference, p, self.s, self.gamma_o, gamma_t);
+        }
+        sum += a[HairBsdf::P_MAX]
+            * (m_p(
+                cos_theta_i,
+                cos_theta_o,
+                sin_theta_i,
+                sin_theta_o,
+                self.v[HairBsdf::P_MAX],
+            ) / (2.0 * PI));
+        sum
+    }
+
+    fn local_pdf(&self, incoming: &Vec3) -> f64 {
+        let (sin_theta_o, cos_theta_o, phi_o) = HairBsdf::angles(&self.outgoing);
+        let (sin_theta_i, cos_theta_i, phi_i) = HairBsdf::angles(incoming);
+        let (gamma_t, _) = self.refraction(sin_theta_o, cos_theta_o);
+        let term_pdfs = self.term_pdfs(sin_theta_o, cos_theta_o);
+        let phi_difference = phi_i - phi_o;
+
+        let mut pdf = 0.0;
+        for (p, term_pdf) in term_pdfs.iter().enumerate().take(HairBsdf::P_MAX) {
+            let (sin_theta_op, cos_theta_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+            pdf += term_pdf
+                * m_p(
+                    cos_theta_i,
+                    cos_theta_op,
+                    sin_theta_i,
+                    sin_theta_op,
+                    self.v[p],
+                )
+                * n_p(phi_difference, p, self.s, self.gamma_o, gamma_t);
+        }
+        pdf + term_pdfs[HairBsdf::P_MAX]
+            * m_p(
+                cos_theta_i,
+                cos_theta_o,
+                sin_theta_i,
+                sin_theta_o,
+                self.v[HairBsdf::P_MAX],
+            )
+            / (2.0 * PI)
+    }
+
+    // Picks a term by its share of the attenuation, then samples its longitudinal and
+    // azimuthal lobes.
+    fn sample(&self) -> Vec3 {
+        let (sin_theta_o, cos_theta_o, phi_o) = HairBsdf::angles(&self.outgoing);
+        let term_pdfs = self.term_pdfs(sin_theta_o, cos_theta_o);
+        let mut choice = random_float();
+        let mut p = 0;
+        while p < HairBsdf::P_MAX && choice >= term_pdfs[p] {
+            choice -= term_pdfs[p];
+            p += 1;
+        }
+
+        let (sin_theta_op, cos_theta_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+        let u = random_float().max(1e-5);
+        let v = self.v[p];
+        let cos_theta = 1.0 + v * (u + (1.0 - u) * (-2.0 / v).exp()).ln();
+        let sin_theta = safe_sqrt(1.0 - cos_theta * cos_theta);
+        let cos_phi = (2.0 * PI * random_float()).cos();
+        let sin_theta_i =
+            (-cos_theta * sin_theta_op + sin_theta * cos_phi * cos_theta_op).clamp(-1.0, 1.0);
+        let cos_theta_i = safe_sqrt(1.0 - sin_theta_i * sin_theta_i);
+
+        let (gamma_t, _) = self.refraction(sin_theta_o, cos_theta_o);
+        let dphi = if p < HairBsdf::P_MAX {
+            phi(p, self.gamma_o, gamma_t) + sample_trimmed_logistic(random_float(), self.s, -PI, PI)
+        } else {
+            2.0 * PI * random_float()
+        };
+        let phi_i = phi_o + dphi;
+        Vec3::new(
+            sin_theta_i,
+            cos_theta_i * phi_i.cos(),
+            cos_theta_i * phi_i.sin(),
+        )
+    }
+}
+
+impl Pdf for HairBsdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.local_pdf(&to_local(&self.frame, &to_unit_vector(direction)))
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.frame.local(&self.sample())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::random_in_range;
+    use crate::pdf::SpherePdf;
+
+    const NUM_SAMPLES: usize = 200_000;
+
+    fn bsdf(sigma_a: Color, beta_m: f64, beta_n: f64, outgoing: Vec3, h: f64) -> HairBsdf {
+        let material = HairMaterial {
+            sigma_a,
+            eta: 1.55,
+            beta_m,
+            beta_n,
+            alpha: 2.0,
+        };
+        let frame = Onb {
+            u: Vec3::new(1.0, 0.0, 0.0),
+            v: Vec3::new(0.0, 1.0, 0.0),
+            w: Vec3::new(0.0, 0.0, 1.0),
+        };
+        HairBsdf::new(frame, to_unit_vector(&outgoing), h, &material)
+    }
+
+    // Cells of the sphere of equal solid angle: four bands along the fiber by eight sectors
+    // around it.
+    fn cell(direction: &Vec3) -> usize {
+        let band = ((0.5 * (direction.x() + 1.0) * 4.0) as usize).min(3);
+        let sector =
+            (((direction.z().atan2(direction.y()) + PI) / (2.0 * PI) * 8.0) as usize).min(7);
+        band * 8 + sector
+    }
+
+    #[test]
+    fn samples_follow_the_pdf() {
+        for (beta_m, beta_n, outgoing, h) in [
+            (0.3, 0.3, Vec3::new(0.2, 0.3, 1.0), 0.0),
+            (0.5, 0.8, Vec3::new(-0.7, 0.0, 0.5), 0.6),
+            (0.8, 0.4, Vec3::new(0.4, -1.0, 0.1), -0.9),
+        ] {
+            let bsdf = bsdf(Color::new(0.1, 0.3, 0.8), beta_m, beta_n, outgoing, h);
+            let mut integrals = [0.0; 32];
+            let mut shares = [0.0; 32];
+            // Uniform directions stratified along the fiber and around it, as the lobes are
+            // narrow.
+            let strata = [400, 500];
+            for i in 0..strata[0] {
+                for j in 0..strata[1] {
+                    let x = 2.0 * (i as f64 + random_float()) / strata[0] as f64 - 1.0;
+                    let phi = 2.0 * PI * (j as f64 + random_float()) / strata[1] as f64;
+                    let r = safe_sqrt(1.0 - x * x);
+                    let direction = Vec3::new(x, r * phi.cos(), r * phi.sin());
+                    integrals[cell(&direction)] +=
+                        4.0 * PI * bsdf.local_pdf(&direction) / (strata[0] * strata[1]) as f64;
+                }
+            }
+            for _ in 0..NUM_SAMPLES {
+                shares[cell(&bsdf.sample())] += 1.0 / NUM_SAMPLES as f64;
+            }
+            let total: f64 = integrals.iter().sum();
+            assert!((total - 1.0).abs() < 0.03, "{}", total);
+            for (integral, share) in integrals.iter().zip(shares.iter()) {
+                assert!(
+                    (integral - share).abs() < 0.01,
+                    "integrals {:?}, shares {:?}",
+                    integrals,
+                    shares
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn clear_fibers_keep_their_energy() {
+        for _ in 0..20 {
+            let beta_m = random_in_range(0.2, 1.0);
+            let beta_n = random_in_range(0.2, 1.0);
+            let outgoing = SpherePdf.generate();
+            let h = random_in_range(-1.0, 1.0);
+            let bsdf = bsdf(Color::new(0.0, 0.0, 0.0), beta_m, beta_n, outgoing, h);
+            let reflected = (0..NUM_SAMPLES / 10)
+                .map(|_| {
+                    let incoming = bsdf.sample();
+                    bsdf.eval(&incoming).x() / bsdf.local_pdf(&incoming)
+                })
+                .sum::<f64>()
+                / (NUM_SAMPLES / 10) as f64;
+            assert!(reflected > 0.95 && reflected < 1.02, "{}", reflected);
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use crate::image::read_image;
+use crate::math::{to_unit_vector, Aabb, Point, Ray, Vec3};
+use crate::mesh::{Triangle, TriangleMesh};
+use crate::trace::{HitRecord, Hittable, Material};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// Terrain over the rectangle from `corner` spanning `size` along x and z, rising by `size.y`
+// per unit of height. Samples are the corners of a grid of cells, each split into two smoothly
+// shaded triangles, with rows running along +z. Texture coordinates put an image texture's
+// pixel centers on the samples, so the same image both shapes and colors the terrain.
+//
+// Rays descend a min-max mipmap of the cells: each level holds the range of heights over
+// blocks twice the size of the level below, and blocks a ray misses are skipped whole.
+pub struct Heightfield {
+    triangles: Vec<Triangle>,
+    cells: [usize; 2],
+    corner: Point,
+    cell_size: [f64; 2],
+    levels: Vec<HeightLevel>,
+    padding: f64,
+}
+
+struct HeightLevel {
+    width: usize,
+    depth: usize,
+    ranges: Vec<(f64, f64)>,
+}
+
+// Samples of height, `width` along x by `depth` along z, with x varying fastest.
+pub struct HeightGrid {
+    pub width: usize,
+    pub depth: usize,
+    pub heights: Vec<f64>,
+}
+
+impl HeightGrid {
+    // Reads a PNG or PPM image, taking the mean of its channels as the height in [0, 1] with
+    // the top row furthest along +z, or otherwise a headerless file of little-endian 32-bit
+    // floats with `resolution` samples along x and z and the first row at the least z.
+    pub fn load(path: &Path, resolution: Option<[usize; 2]>) -> io::Result<Self> {
+        let is_image = path.extension().is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("png") || extension.eq_ignore_ascii_case("ppm")
+        });
+        let grid = if is_image {
+            let image = read_image(path)?;
+            let mut heights = Vec::with_capacity(image.pixels.len());
+            for row in image.pixels.chunks_exact(image.width).rev() {
+                heights.extend(
+                    row.iter()
+                        .map(|pixel| (pixel.x() + pixel.y() + pixel.z()) / 3.0),
+                );
+            }
+            HeightGrid {
+                width: image.width,
+                depth: image.height,
+                heights,
+            }
+        } else {
+            let [width, depth] = resolution.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "raw height grids need a resolution",
+                )
+            })?;
+            let heights = fs::read(path)?
+                .chunks_exact(4)
+                .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64)
+                .collect();
+            HeightGrid {
+                width,
+                depth,
+                heights,
+            }
+        };
+        if grid.width < 2 || grid.depth < 2 || grid.heights.len() != grid.width * grid.depth {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{}: expected at least 2x2 heights and {} in all, found {}",
+                    path.display(),
+                    grid.width * grid.depth,
+                    grid.heights.len()
+                ),
+            ));
+        }
+        Ok(grid)
+    }
+
+    fn height(&self, x: usize, z: usize) -> f64 {
+        self.heights[z * self.width + x]
+    }
+}
+
+impl Heightfield {
+    pub fn new(
+        grid: &HeightGrid,
+        corner: &Point,
+        size: &Vec3,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        let (width, depth) = (grid.width, grid.depth);
+        let cells = [width - 1, depth - 1];
+        let cell_size = [size.x() / cells[0] as f64, size.z() / cells[1] as f64];
+
+        let mut positions = Vec::with_capacity(width * depth);
+        let mut normals = Vec::with_capacity(width * depth);
+        let mut uvs = Vec::with_capacity(width * depth);
+        for z in 0..depth {
+            for x in 0..width {
+                positions.push(Point::new(
+                    corner.x() + x as f64 * cell_size[0],
+                    corner.y() + grid.height(x, z) * size.y(),
+                    corner.z() + z as f64 * cell_size[1],
+                ));
+                // Central differences, one-sided at the edges.
+                let (x0, x1) = (x.saturating_sub(1), (x + 1).min(width - 1));
+                let (z0, z1) = (z.saturating_sub(1), (z + 1).min(depth - 1));
+                let slope_x = (grid.height(x1, z) - grid.height(x0, z)) * size.y()
+                    / ((x1 - x0) as f64 * cell_size[0]);
+                let slope_z = (grid.height(x, z1) - grid.height(x, z0)) * size.y()
+                    / ((z1 - z0) as f64 * cell_size[1]);
+                normals.push(to_unit_vector(&Vec3::new(-slope_x, 1.0, -slope_z)));
+                uvs.push((
+                    (x as f64 + 0.5) / width as f64,
+                    (z as f64 + 0.5) / depth as f64,
+                ));
+            }
+        }
+
+        // Two triangles per cell, facing up, cell (x, z) first at 2 * (z * cells[0] + x).
+        let mut indices = Vec::with_capacity(2 * cells[0] * cells[1]);
+        for z in 0..cells[1] {
+            for x in 0..cells[0] {
+                let i00 = z * width + x;
+                let (i10, i01, i11) = (i00 + 1, i00 + width, i00 + width + 1);
+                indices.push([i00, i01, i10]);
+                indices.push([i10, i01, i11]);
+            }
+        }
+        let mesh = Arc::new(TriangleMesh {
+            positions,
+            normals,
+            uvs,
+            colors: vec![],
+            indices,
+            material,
+        });
+        let triangles = (0..mesh.num_triangles())
+            .map(|index| Triangle::new(mesh.clone(), index))
+            .collect();
+
+        let mut levels = vec![HeightLevel {
+            width: cells[0],
+            depth: cells[1],
+            ranges: (0..cells[1])
+                .flat_map(|z| (0..cells[0]).map(move |x| (x, z)))
+                .map(|(x, z)| {
+                    let corners = [
+                        grid.height(x, z),
+                        grid.height(x + 1, z),
+                        grid.height(x, z + 1),
+                        grid.height(x + 1, z + 1),
+                    ];
+                    let (low, high) = corners
+                        .iter()
+                        .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), &h| {
+                            (low.min(h), high.max(h))
+                        });
+                    let (low, high) = (corner.y() + low * size.y(), corner.y() + high * size.y());
+                    (low.min(high), low.max(high))
+                })
+                .collect(),
+        }];
+        while levels
+            .last()
+            .is_some_and(|level| level.width > 1 || level.depth > 1)
+        {
+            let below = levels.last().unwrap();
+            let (width, depth) = (below.width.div_ceil(2), below.depth.div_ceil(2));
+            let mut ranges = vec![(f64::INFINITY, f64::NEG_INFINITY); width * depth];
+            for z in 0..below.depth {
+                for x in 0..below.width {
+                    let (low, high) = below.ranges[z * below.width + x];
+                    let range = &mut ranges[(z / 2) * width + x / 2];
+                    *range = (range.0.min(low), range.1.max(high));
+                }
+            }
+            levels.push(HeightLevel {
+                width,
+                depth,
+                ranges,
+            });
+        }
+
+        Heightfield {
+            triangles,
+            cells,
+            corner: *corner,
+            cell_size,
+            levels,
+            padding: 1e-7 * size.x().abs().max(size.y().abs()).max(size.z().abs()),
+        }
+    }
+
+    // Bounds of block (x, z) of `level`, padded so flat blocks still have some thickness.
+    fn block_bounds(&self, level: usize, x: usize, z: usize) -> Aabb {
+        let cells_per_block = 1 << level;
+        let (x0, z0) = (x * cells_per_block, z * cells_per_block);
+        let x1 = (x0 + cells_per_block).min(self.cells[0]);
+        let z1 = (z0 + cells_per_block).min(self.cells[1]);
+        let layer = &self.levels[level];
+        let (low, high) = layer.ranges[z * layer.width + x];
+        let padding = Vec3::new(self.padding, self.padding, self.padding);
+        Aabb::new(
+            Point::new(
+                self.corner.x() + x0 as f64 * self.cell_size[0],
+                low,
+                self.corner.z() + z0 as f64 * self.cell_size[1],
+            ) - padding,
+            Point::new(
+                self.corner.x() + x1 as f64 * self.cell_size[0],
+                high,
+                self.corner.z() + z1 as f64 * self.cell_size[1],
+            ) + padding,
+        )
+    }
+
+    // Visits the children of a block the ray enters nearest first, so the search can stop at
+    // the first hit.
+    fn hit_block(
+        &self,
+        level: usize,
+        x: usize,
+        z: usize,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord> {
+        if level == 0 {
+            let first = 2 * (z * self.cells[0] + x);
+            let hit = self.triangles[first].hit(ray, t_min, t_max);
+            let t_max = hit.as_ref().map_or(t_max, |hit| hit.t);
+            return self.triangles[first + 1].hit(ray, t_min, t_max).or(hit);
+        }
+
+        let below = &self.levels[level - 1];
+        let mut children = Vec::with_capacity(4);
+        for (dx, dz) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let (child_x, child_z) = (2 * x + dx, 2 * z + dz);
+            if child_x < below.width && child_z < below.depth {
+                let bounds = self.block_bounds(level - 1, child_x, child_z);
+                if let Some((entry, _)) = bounds.clip(ray, t_min, t_max) {
+                    children.push((entry, child_x, child_z));
+                }
+            }
+        }
+        children.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut t_max = t_max;
+        let mut closest_hit = None;
+        for (entry, child_x, child_z) in children {
+            if entry > t_max {
+                break;
+            }
+            if let Some(hit) = self.hit_block(level - 1, child_x, child_z, ray, t_min, t_max) {
+                t_max = hit.t;
+                closest_hit = Some(hit);
+            }
+        }
+        closest_hit
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let top = self.levels.len() - 1;
+        self.block_bounds(top, 0, 0).clip(ray, t_min, t_max)?;
+        self.hit_block(top, 0, 0, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.block_bounds(self.levels.len() - 1, 0, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{random_float, Color};
+    use crate::test_util::error_message;
+    use crate::trace::LambertianMaterial;
+
+    fn heightfield(width: usize, depth: usize) -> Heightfield {
+        let grid = HeightGrid {
+            width,
+            depth,
+            heights: (0..width * depth).map(|_| random_float()).collect(),
+        };
+        Heightfield::new(
+            &grid,
+            &Point::new(-2.0, -0.5, -1.5),
+            &Vec3::new(4.0, 1.5, 3.0),
+            Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5))),
+        )
+    }
+
+    fn brute_force_hit(heightfield: &Heightfield, ray: &Ray) -> Option<HitRecord> {
+        let mut t_max = f64::INFINITY;
+        let mut closest_hit = None;
+        for triangle in &heightfield.triangles {
+            if let Some(hit) = triangle.hit(ray, 0.001, t_max) {
+                t_max = hit.t;
+                closest_hit = Some(hit);
+            }
+        }
+        closest_hit
+    }
+
+    #[test]
+    fn hits_match_a_brute_force_search() {
+        // Odd cell counts leave blocks at the far edges clipped at every level.
+        for (width, depth) in [(2, 2), (8, 5), (6, 11), (18, 3)] {
+            let heightfield = heightfield(width, depth);
+            let mut num_hits = 0;
+            for _ in 0..2000 {
+                let ray = Ray {
+                    origin: Vec3::random_in_range(-3.0, 3.0),
+                    direction: Vec3::random_in_range(-1.0, 1.0),
+                    time: 0.0,
+                };
+                let hit = heightfield.hit(&ray, 0.001, f64::INFINITY);
+                let expected = brute_force_hit(&heightfield, &ray);
+                assert_eq!(hit.is_some(), expected.is_some());
+                if let (Some(hit), Some(expected)) = (hit, expected) {
+                    num_hits += 1;
+                    assert!((hit.t - expected.t).abs() < 1e-9, "{}", hit.t);
+                }
+            }
+            assert!(num_hits > 100, "{}", num_hits);
+        }
+    }
+
+    #[test]
+    fn bounding_box_encloses_every_triangle() {
+        let heightfield = heightfield(9, 6);
+        let bounds = heightfield.bounding_box().unwrap();
+        for triangle in &heightfield.triangles {
+            let triangle_bounds = triangle.bounding_box().unwrap();
+            assert!(
+                (0..3).all(|axis| bounds.min.e[axis] <= triangle_bounds.min.e[axis]
+                    && triangle_bounds.max.e[axis] <= bounds.max.e[axis])
+            );
+        }
+    }
+
+    #[test]
+    fn raw_files_must_hold_every_height() {
+        let path =
+            std::env::temp_dir().join(format!("rtiow-heightfield-{}.raw", std::process::id()));
+        let bytes: Vec<u8> = [0.0f32, 0.5, 1.0, 0.25, 0.75]
+            .iter()
+            .flat_map(|height| height.to_le_bytes())
+            .collect();
+        fs::write(&path, bytes).unwrap();
+
+        error_message(HeightGrid::load(&path, Some([5, 1])));
+        let message = error_message(HeightGrid::load(&path, Some([2, 2])));
+        assert!(message.ends_with("expected at least 2x2 heights and 4 in all, found 5"));
+        let err = HeightGrid::load(&path, None).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        fs::write(&path, [0; 16]).unwrap();
+        let grid = HeightGrid::load(&path, Some([2, 2])).unwrap();
+        assert_eq!(grid.heights, vec![0.0; 4]);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,704 @@
+use crate::math::{clamp, Color};
+use crate::render::FrameBuffer;
+use crate::tonemap::ToneMapping;
+use exr::prelude::{f16, write_rgb_file, write_rgba_file, IntoSample};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum ImageFormat {
+    /// Binary PPM (P6)
+    Ppm,
+    /// ASCII PPM (P3)
+    PpmAscii,
+    /// 8-bit PNG
+    Png,
+    /// 16-bit PNG
+    Png16,
+    /// Linear half float OpenEXR
+    Exr,
+    /// Linear 32-bit float OpenEXR
+    ExrFloat,
+    /// Linear 32-bit float PFM
+    Pfm,
+}
+
+// A decoded image file. Channels are in [0, 1] exactly as stored, without any transfer function
+// removed, and rows run top to bottom.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl ImageFormat {
+    // Picks a format from the file extension; `.ppm` is binary, `.png` is 8-bit and `.exr` is
+    // half float.
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            "exr" => Some(ImageFormat::Exr),
+            "pfm" => Some(ImageFormat::Pfm),
+            _ => None,
+        }
+    }
+
+    // Resolves the format to write `path` with, inferring it from the extension when none is
+    // given, and checks that it can hold an alpha channel if one was asked for.
+    pub fn resolve(
+        path: &Path,
+        format: Option<ImageFormat>,
+        alpha: bool,
+    ) -> io::Result<ImageFormat> {
+        let format = format
+            .or_else(|| ImageFormat::from_path(path))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{}: cannot infer image format from extension, use --format",
+                        path.display()
+                    ),
+                )
+            })?;
+        if alpha && !format.supports_alpha() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{}: {:?} images have no alpha channel",
+                    path.display(),
+                    format
+                ),
+            ));
+        }
+        Ok(format)
+    }
+
+    pub fn supports_alpha(&self) -> bool {
+        matches!(self, ImageFormat::Exr | ImageFormat::ExrFloat)
+    }
+
+    // EXR and PFM keep linear, unclamped radiance.
+    pub fn is_hdr(&self) -> bool {
+        matches!(
+            self,
+            ImageFormat::Exr | ImageFormat::ExrFloat | ImageFormat::Pfm
+        )
+    }
+}
+
+// Low dynamic range formats go through `tone_mapping` first, so their encoders only have to
+// quantize display values. HDR formats get the frame buffer's radiance as it is.
+pub fn write_image(
+    path: &Path,
+    format: ImageFormat,
+    alpha: bool,
+    tone_mapping: &ToneMapping,
+    frame_buffer: &FrameBuffer,
+) -> io::Result<()> {
+    let tone_mapped;
+    let frame_buffer = if format.is_hdr() {
+        frame_buffer
+    } else {
+        tone_mapped = tone_mapping.apply(frame_buffer);
+        &tone_mapped
+    };
+    match format {
+        ImageFormat::Ppm => write_file(path, |out| write_ppm(out, frame_buffer)),
+        ImageFormat::PpmAscii => write_file(path, |out| write_ppm_ascii(out, frame_buffer)),
+        ImageFormat::Png => write_file(path, |out| {
+            write_png(out, frame_buffer, png::BitDepth::Eight)
+        }),
+        ImageFormat::Png16 => write_file(path, |out| {
+            write_png(out, frame_buffer, png::BitDepth::Sixteen)
+        }),
+        ImageFormat::Exr => write_exr(path, frame_buffer, alpha, f16::from_f64),
+        ImageFormat::ExrFloat => write_exr(path, frame_buffer, alpha, |c| c as f32),
+        ImageFormat::Pfm => write_file(path, |out| write_pfm(out, frame_buffer)),
+    }
+}
+
+fn write_file(path: &Path, write: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> io::Result<()> {
+    let mut output = BufWriter::new(File::create(path)?);
+    write(&mut output)?;
+    output.flush()
+}
+
+fn write_ppm_ascii(out: &mut dyn Write, frame_buffer: &FrameBuffer) -> io::Result<()> {
+    let (image_width, image_height) = (frame_buffer.width(), frame_buffer.height());
+    writeln!(out, "P3\n{} {}\n255", image_width, image_height)?;
+    for row in frame_buffer.color.iter().rev() {
+        for pixel_color in row.iter() {
+            write_pixel(out, pixel_color)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_ppm(out: &mut dyn Write, frame_buffer: &FrameBuffer) -> io::Result<()> {
+    let (image_width, image_height) = (frame_buffer.width(), frame_buffer.height());
+    write!(out, "P6\n{} {}\n255\n", image_width, image_height)?;
+    let mut data = Vec::with_capacity(image_width * image_height * 3);
+    for row in frame_buffer.color.iter().rev() {
+        for pixel_color in row.iter() {
+            data.extend_from_slice(&to_color_bytes(pixel_color));
+        }
+    }
+    out.write_all(&data)
+}
+
+fn write_png(
+    out: &mut dyn Write,
+    frame_buffer: &FrameBuffer,
+    bit_depth: png::BitDepth,
+) -> io::Result<()> {
+    let (image_width, image_height) = (frame_buffer.width(), frame_buffer.height());
+    let mut encoder = png::Encoder::new(out, image_width as u32, image_height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(bit_depth);
+
+    let bytes_per_sample = if bit_depth == png::BitDepth::Sixteen {
+        2
+    } else {
+        1
+    };
+    let mut data = Vec::with_capacity(image_width * image_height * 3 * bytes_per_sample);
+    for row in frame_buffer.color.iter().rev() {
+        for pixel_color in row.iter() {
+            if bit_depth == png::BitDepth::Sixteen {
+                // PNG samples are big-endian.
+                for word in to_color_words(pixel_color).iter() {
+                    data.extend_from_slice(&word.to_be_bytes());
+                }
+            } else {
+                data.extend_from_slice(&to_color_bytes(pixel_color));
+            }
+        }
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+// PFM stores rows bottom to top like the frame buffer; a negative scale marks little-endian
+// samples.
+fn write_pfm(out: &mut dyn Write, frame_buffer: &FrameBuffer) -> io::Result<()> {
+    let (image_width, image_height) = (frame_buffer.width(), frame_buffer.height());
+    write!(out, "PF\n{} {}\n-1.0\n", image_width, image_height)?;
+    let mut data = Vec::with_capacity(image_width * image_height * 3 * 4);
+    for row in frame_buffer.color.iter() {
+        for pixel_color in row.iter() {
+            for c in pixel_color.e.iter() {
+                data.extend_from_slice(&(*c as f32).to_le_bytes());
+            }
+        }
+    }
+    out.write_all(&data)
+}
+
+// `to_sample` picks the EXR channel type, half or full float.
+fn write_exr<T: IntoSample>(
+    path: &Path,
+    frame_buffer: &FrameBuffer,
+    alpha: bool,
+    to_sample: fn(f64) -> T,
+) -> io::Result<()> {
+    let (image_width, image_height) = (frame_buffer.width(), frame_buffer.height());
+    // EXR rows run top to bottom.
+    let color = |x: usize, y: usize| {
+        let pixel_color = frame_buffer.color[image_height - 1 - y][x];
+        (
+            to_sample(pixel_color.x()),
+            to_sample(pixel_color.y()),
+            to_sample(pixel_color.z()),
+        )
+    };
+    let result = if alpha {
+        write_rgba_file(path, image_width, image_height, |x, y| {
+            let (r, g, b) = color(x, y);
+            let a = to_sample(frame_buffer.alpha[image_height - 1 - y][x]);
+            (r, g, b, a)
+        })
+    } else {
+        write_rgb_file(path, image_width, image_height, color)
+    };
+    result.map_err(|err| io::Error::other(format!("{}: {}", path.display(), err)))
+}
+
+fn write_pixel(out: &mut dyn Write, pixel_color: &Color) -> io::Result<()> {
+    let [r, g, b] = to_color_bytes(pixel_color);
+    writeln!(out, "{} {} {}", r, g, b)
+}
+
+fn to_color_bytes(pixel_color: &Color) -> [u8; 3] {
+    [
+        to_color_byte(pixel_color.x()),
+        to_color_byte(pixel_color.y()),
+        to_color_byte(pixel_color.z()),
+    ]
+}
+
+fn to_color_words(pixel_color: &Color) -> [u16; 3] {
+    [
+        to_color_word(pixel_color.x()),
+        to_color_word(pixel_color.y()),
+        to_color_word(pixel_color.z()),
+    ]
+}
+
+fn to_color_byte(c: f64) -> u8 {
+    ((256.0) * clamp(c, 0.0, 0.999)) as u8
+}
+
+fn to_color_word(c: f64) -> u16 {
+    ((65536.0) * clamp(c, 0.0, 0.99999)) as u16
+}
+
+// Reads a PNG or PPM (P3 or P6) file, picked by extension.
+pub fn read_image(path: &Path) -> io::Result<Image> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    let result = match extension.as_deref() {
+        Some("png") => read_png(path),
+        Some("ppm") => read_ppm(path),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "unsupported image format, expected .png or .ppm",
+        )),
+    };
+    result.map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))
+}
+
+fn read_png(path: &Path) -> io::Result<Image> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    // Expands palettes and low bit depths and strips 16-bit samples to 8.
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data)?;
+    let channels = info.color_type.samples();
+
+    let pixels = data[..info.buffer_size()]
+        .chunks_exact(channels)
+        .map(|sample| {
+            let channel = |idx: usize| sample[idx] as f64 / 255.0;
+            if channels < 3 {
+                Color::new(channel(0), channel(0), channel(0))
+            } else {
+                Color::new(channel(0), channel(1), channel(2))
+            }
+        })
+        .collect();
+    Ok(Image {
+        width: info.width as usize,
+        height: info.height as usize,
+        pixels,
+    })
+}
+
+fn read_ppm(path: &Path) -> io::Result<Image> {
+    decode_ppm(&fs::read(path)?)
+}
+
+fn decode_ppm(data: &[u8]) -> io::Result<Image> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+    let mut pos = 0;
+    let next_number = |pos: &mut usize| -> io::Result<usize> {
+        next_ppm_token(data, pos)?
+            .parse()
+            .map_err(|_| invalid("expected a number"))
+    };
+
+    let magic = next_ppm_token(data, &mut pos)?;
+    if magic != "P3" && magic != "P6" {
+        return Err(invalid("expected a P3 or P6 header"));
+    }
+    let width = next_number(&mut pos)?;
+    let height = next_number(&mut pos)?;
+    let max_value = next_number(&mut pos)?;
+    if width == 0 || height == 0 {
+        return Err(invalid("image is empty"));
+    }
+    if max_value == 0 || max_value > 65535 {
+        return Err(invalid("maximum value must be between 1 and 65535"));
+    }
+
+    // Sizes read from a header may be large enough to overflow.
+    let num_samples = width
+        .checked_mul(height)
+        .and_then(|num_pixels| num_pixels.checked_mul(3))
+        .ok_or_else(|| invalid("image is too large"))?;
+    let samples: Vec<usize> = if magic == "P3" {
+        (0..num_samples)
+            .map(|_| next_number(&mut pos))
+            .collect::<io::Result<_>>()?
+    } else {
+        // A single whitespace byte separates the header from the samples, which are big-endian
+        // words when the maximum value needs them.
+        let start = pos + 1;
+        let bytes_per_sample = if max_value > 255 { 2 } else { 1 };
+        let end = num_samples
+            .checked_mul(bytes_per_sample)
+            .and_then(|size| size.checked_add(start))
+            .ok_or_else(|| invalid("image is too large"))?;
+        let raw = data
+            .get(start..end)
+            .ok_or_else(|| invalid("unexpected end of file"))?;
+        raw.chunks_exact(bytes_per_sample)
+            .map(|bytes| bytes.iter().fold(0, |acc, byte| acc * 256 + *byte as usize))
+            .collect()
+    };
+
+    let pixels = samples
+        .chunks_exact(3)
+        .map(|rgb| {
+            let channel = |c: usize| c.min(max_value) as f64 / max_value as f64;
+            Color::new(channel(rgb[0]), channel(rgb[1]), channel(rgb[2]))
+        })
+        .collect();
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+// Header fields are separated by whitespace, and '#' starts a comment running to the end of the
+// line.
+fn next_ppm_token(data: &[u8], pos: &mut usize) -> io::Result<String> {
+    loop {
+        while *pos < data.len() && data[*pos].is_ascii_whitespace() {
+            *pos += 1;
+        }
+        if *pos < data.len() && data[*pos] == b'#' {
+            while *pos < data.len() && data[*pos] != b'\n' {
+                *pos += 1;
+            }
+        } else {
+            break;
+        }
+    }
+    let start = *pos;
+    while *pos < data.len() && !data[*pos].is_ascii_whitespace() {
+        *pos += 1;
+    }
+    if start == *pos {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected end of file",
+        ));
+    }
+    Ok(String::from_utf8_lossy(&data[start..*pos]).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::error_message;
+    use crate::tonemap::srgb_decode;
+    use std::path::PathBuf;
+
+    // A fresh directory per test, as tests run in parallel.
+    fn scratch_directory(test: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("rtiow-image-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    // The bottom row, which the frame buffer stores first, is red then green; the top row is
+    // blue then a gray that encodes to sRGB 0.5. Red is brighter than the display can show.
+    fn frame_buffer() -> FrameBuffer {
+        let gray = srgb_decode(0.5);
+        FrameBuffer {
+            color: vec![
+                vec![Color::new(4.0, 0.0, 0.0), Color::new(0.0, 1.0, 0.0)],
+                vec![Color::new(0.0, 0.0, 1.0), Color::new(gray, gray, gray)],
+            ],
+            alpha: vec![vec![1.0, 0.5], vec![0.25, 0.0]],
+        }
+    }
+
+    fn write(directory: &Path, name: &str, format: ImageFormat, alpha: bool) -> PathBuf {
+        let path = directory.join(name);
+        write_image(
+            &path,
+            format,
+            alpha,
+            &ToneMapping::default(),
+            &frame_buffer(),
+        )
+        .unwrap();
+        path
+    }
+
+    #[test]
+    fn ascii_ppm() {
+        let image = decode_ppm(b"P3\n2 1\n255\n255 0 0\n0 51 255\n").unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(
+            image.pixels,
+            vec![Color::new(1.0, 0.0, 0.0), Color::new(0.0, 0.2, 1.0)]
+        );
+    }
+
+    #[test]
+    fn binary_ppm_rows_run_top_to_bottom() {
+        let mut data = b"P6\n1 2\n255\n".to_vec();
+        data.extend_from_slice(&[255, 255, 255, 0, 0, 0]);
+        let image = decode_ppm(&data).unwrap();
+        assert_eq!((image.width, image.height), (1, 2));
+        assert_eq!(
+            image.pixels,
+            vec![Color::new(1.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0)]
+        );
+    }
+
+    #[test]
+    fn header_comments_are_skipped() {
+        let image =
+            decode_ppm(b"P3 # magic\n# a whole line\n1 1 # size\n10\n# before data\n0 5 10\n")
+                .unwrap();
+        assert_eq!(image.pixels, vec![Color::new(0.0, 0.5, 1.0)]);
+    }
+
+    #[test]
+    fn binary_samples_after_a_comment_start_one_byte_after_maxval() {
+        // The sample bytes are whitespace and '#', which must not be read as header.
+        let mut data = b"P6\n# comment\n1 1\n255\n".to_vec();
+        data.extend_from_slice(b" #\n");
+        let image = decode_ppm(&data).unwrap();
+        let expected = Color::new(32.0 / 255.0, 35.0 / 255.0, 10.0 / 255.0);
+        assert_eq!(image.pixels, vec![expected]);
+    }
+
+    #[test]
+    fn sixteen_bit_samples_are_big_endian() {
+        let mut data = b"P6\n1 1\n65535\n".to_vec();
+        data.extend_from_slice(&[0xff, 0xff, 0x80, 0x00, 0x00, 0x00]);
+        let image = decode_ppm(&data).unwrap();
+        assert_eq!(image.pixels[0].x(), 1.0);
+        assert_eq!(image.pixels[0].y(), 32768.0 / 65535.0);
+        assert_eq!(image.pixels[0].z(), 0.0);
+
+        let image = decode_ppm(b"P3\n1 1\n1000\n1000 500 2000\n").unwrap();
+        assert_eq!(image.pixels, vec![Color::new(1.0, 0.5, 1.0)]);
+    }
+
+    #[test]
+    fn truncated_data_is_an_error() {
+        let mut data = b"P6\n2 2\n255\n".to_vec();
+        data.extend_from_slice(&[0; 11]);
+        assert_eq!(error_message(decode_ppm(&data)), "unexpected end of file");
+        assert_eq!(
+            error_message(decode_ppm(b"P3\n2 1\n255\n1 2 3 4 5\n")),
+            "unexpected end of file"
+        );
+        assert_eq!(
+            error_message(decode_ppm(b"P3\n2")),
+            "unexpected end of file"
+        );
+    }
+
+    #[test]
+    fn huge_sizes_are_errors() {
+        assert_eq!(
+            error_message(decode_ppm(b"P6\n18446744073709551615 2\n255\n")),
+            "image is too large"
+        );
+        // The sample count fits, but not its size in bytes.
+        assert_eq!(
+            error_message(decode_ppm(b"P6\n4611686018427387904 1\n65535\n")),
+            "image is too large"
+        );
+        assert_eq!(
+            error_message(decode_ppm(b"P6\n4611686018427387904 1\n255\n")),
+            "unexpected end of file"
+        );
+    }
+
+    #[test]
+    fn bad_headers_are_errors() {
+        assert_eq!(
+            error_message(decode_ppm(b"P5\n1 1\n255\n0")),
+            "expected a P3 or P6 header"
+        );
+        assert_eq!(
+            error_message(decode_ppm(b"P3\n0 1\n255\n")),
+            "image is empty"
+        );
+        assert_eq!(
+            error_message(decode_ppm(b"P3\n1 x\n255\n")),
+            "expected a number"
+        );
+        assert_eq!(
+            error_message(decode_ppm(b"P3\n1 1\n65536\n0 0 0")),
+            "maximum value must be between 1 and 65535"
+        );
+    }
+
+    #[test]
+    fn read_image_picks_the_format_and_names_the_file() {
+        let directory = scratch_directory("read_image");
+        let path = directory.join("pixel.ppm");
+        fs::write(&path, b"P3 1 1 255 0 255 0").unwrap();
+        let image = read_image(&path).unwrap();
+        assert_eq!(image.pixels, vec![Color::new(0.0, 1.0, 0.0)]);
+
+        fs::write(&path, b"P3 1 1 255 0 255").unwrap();
+        let message = read_image(&path).err().unwrap().to_string();
+        assert_eq!(
+            message,
+            format!("{}: unexpected end of file", path.display())
+        );
+
+        let err = read_image(&directory.join("pixel.tga")).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn ppm_output_reads_back_top_row_first() {
+        let directory = scratch_directory("ppm_output");
+        for format in [ImageFormat::Ppm, ImageFormat::PpmAscii] {
+            let image = read_image(&write(&directory, "out.ppm", format, false)).unwrap();
+            assert_eq!((image.width, image.height), (2, 2));
+            let expected: Vec<Color> = [
+                [0.0, 0.0, 255.0],
+                [128.0, 128.0, 128.0],
+                [255.0, 0.0, 0.0],
+                [0.0, 255.0, 0.0],
+            ]
+            .iter()
+            .map(|&[r, g, b]| Color::new(r, g, b) / 255.0)
+            .collect();
+            assert_eq!(image.pixels, expected, "{:?}", format);
+        }
+        let header =
+            fs::read(write(&directory, "ascii.ppm", ImageFormat::PpmAscii, false)).unwrap();
+        assert!(header.starts_with(b"P3\n2 2\n255\n0 0 255\n"));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn png_output_reads_back_top_row_first() {
+        let directory = scratch_directory("png_output");
+        let decode = |path: &Path| {
+            let decoder = png::Decoder::new(BufReader::new(File::open(path).unwrap()));
+            let mut reader = decoder.read_info().unwrap();
+            let mut data = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut data).unwrap();
+            assert_eq!((info.width, info.height), (2, 2));
+            assert_eq!(info.color_type, png::ColorType::Rgb);
+            (info.bit_depth, data)
+        };
+
+        let (depth, data) = decode(&write(&directory, "8.png", ImageFormat::Png, false));
+        assert_eq!(depth, png::BitDepth::Eight);
+        assert_eq!(data, vec![0, 0, 255, 128, 128, 128, 255, 0, 0, 0, 255, 0]);
+
+        let (depth, data) = decode(&write(&directory, "16.png", ImageFormat::Png16, false));
+        assert_eq!(depth, png::BitDepth::Sixteen);
+        let words: Vec<u16> = data
+            .chunks_exact(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+            .collect();
+        assert_eq!(words[..3], [0, 0, 65535]);
+        assert!(words[3..6].iter().all(|&word| word.abs_diff(32768) <= 1));
+        assert_eq!(words[6..], [65535, 0, 0, 0, 65535, 0]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn hdr_output_ignores_exposure() {
+        let directory = scratch_directory("hdr_exposure");
+        let path = directory.join("out.pfm");
+        let tone_mapping = ToneMapping {
+            exposure: 3.0,
+            ..ToneMapping::default()
+        };
+        write_image(
+            &path,
+            ImageFormat::Pfm,
+            false,
+            &tone_mapping,
+            &frame_buffer(),
+        )
+        .unwrap();
+        let data = fs::read(&path).unwrap();
+        let red = f32::from_le_bytes([data[12], data[13], data[14], data[15]]);
+        assert_eq!(red, 4.0);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn pfm_output_is_linear_and_bottom_row_first() {
+        let directory = scratch_directory("pfm_output");
+        let data = fs::read(write(&directory, "out.pfm", ImageFormat::Pfm, false)).unwrap();
+        let header = b"PF\n2 2\n-1.0\n";
+        assert!(data.starts_with(header));
+        let samples: Vec<f32> = data[header.len()..]
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        let gray = srgb_decode(0.5) as f32;
+        assert_eq!(
+            samples,
+            vec![4.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, gray, gray, gray]
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn exr_output_is_linear_and_top_row_first() {
+        let directory = scratch_directory("exr_output");
+        let gray = srgb_decode(0.5) as f32;
+        for (format, alpha) in [
+            (ImageFormat::Exr, false),
+            (ImageFormat::Exr, true),
+            (ImageFormat::ExrFloat, true),
+        ] {
+            let path = write(&directory, "out.exr", format, alpha);
+            let image = exr::prelude::read_first_rgba_layer_from_file(
+                &path,
+                |resolution, _| vec![[0.0f32; 4]; resolution.width() * resolution.height()],
+                |pixels: &mut Vec<[f32; 4]>, position, (r, g, b, a): (f32, f32, f32, f32)| {
+                    pixels[position.y() * 2 + position.x()] = [r, g, b, a];
+                },
+            )
+            .unwrap();
+            let pixels = image.layer_data.channel_data.pixels;
+            let expected_alpha = if alpha {
+                [0.25, 0.0, 1.0, 0.5]
+            } else {
+                [1.0; 4]
+            };
+            let expected = [
+                [0.0, 0.0, 1.0, expected_alpha[0]],
+                [gray, gray, gray, expected_alpha[1]],
+                [4.0, 0.0, 0.0, expected_alpha[2]],
+                [0.0, 1.0, 0.0, expected_alpha[3]],
+            ];
+            for (pixel, expected) in pixels.iter().zip(expected.iter()) {
+                for (c, e) in pixel.iter().zip(expected.iter()) {
+                    assert!(
+                        (c - e).abs() < 1e-3,
+                        "{:?}: {:?} != {:?}",
+                        format,
+                        pixel,
+                        expected
+                    );
+                }
+            }
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,213 @@
+use crate::math::{to_unit_vector, Aabb, Point, Ray, Transform, Vec3};
+use crate::trace::{HitRecord, Hittable};
+use std::sync::Arc;
+
+// Places a shared hittable in the world with `transform`, which maps the hittable's own space
+// to world space. Any number of instances can share one hittable.
+pub struct Instance {
+    pub hittable: Arc<dyn Hittable + Send + Sync>,
+    pub transform: Transform,
+}
+
+impl Instance {
+    pub fn new(hittable: Arc<dyn Hittable + Send + Sync>, transform: Transform) -> Self {
+        Instance {
+            hittable,
+            transform,
+        }
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // The object space direction is left unnormalized, so distances along it are the same
+        // as in world space.
+        let object_ray = self.transform.inverse().ray(ray);
+        let mut hit = self.hittable.hit(&object_ray, t_min, t_max)?;
+        hit.point = self.transform.point(&hit.point);
+        hit.normal = to_unit_vector(&self.transform.normal(&hit.normal));
+        // Medium events have no geometric normal to carry over.
+        if !hit.is_medium_event() {
+            hit.geometric_normal = to_unit_vector(&self.transform.normal(&hit.geometric_normal));
+        }
+        hit.dpdu = self.transform.vector(&hit.dpdu);
+        hit.dpdv = self.transform.vector(&hit.dpdv);
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let bbox = self.hittable.bounding_box()?;
+        Some(self.transform.aabb(&bbox))
+    }
+
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f64 {
+        let to_object = self.transform.inverse();
+        let object_direction = to_object.vector(&to_unit_vector(direction));
+        let pdf = self
+            .hittable
+            .pdf_value(&to_object.point(origin), &to_unit_vector(&object_direction));
+        // Change of variables between unit directions in the two spaces.
+        let stretch = object_direction.length();
+        pdf * to_object.matrix.determinant3().abs() / (stretch * stretch * stretch)
+    }
+
+    fn random(&self, origin: &Point) -> Vec3 {
+        let object_origin = self.transform.inverse().point(origin);
+        self.transform.vector(&self.hittable.random(&object_origin))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.hittable.is_emissive()
+    }
+
+    fn is_medium(&self) -> bool {
+        self.hittable.is_medium()
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.hittable
+            .transmittance(&self.transform.inverse().ray(ray), t_min, t_max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{dot_product, random_in_range, Color};
+    use crate::trace::{LambertianMaterial, Sphere};
+    use std::f64::consts::PI;
+
+    fn unit_sphere() -> Arc<dyn Hittable + Send + Sync> {
+        let material = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+        Arc::new(Sphere::new(&Point::new(0.0, 0.0, 0.0), 1.0, material))
+    }
+
+    // A ray aimed somewhere near `target`.
+    fn random_ray(target: &Point) -> Ray {
+        let origin = Vec3::random_in_range(-6.0, 6.0);
+        Ray {
+            origin,
+            direction: *target + Vec3::random_in_range(-2.5, 2.5) - origin,
+            time: 0.0,
+        }
+    }
+
+    fn assert_vec_close(a: &Vec3, b: &Vec3) {
+        assert!((*a - *b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn instanced_sphere_matches_a_world_sphere() {
+        // A unit sphere scaled by 2, spun and moved, is the sphere of radius 2 at its new center.
+        let center = Point::new(1.0, -2.0, 0.5);
+        let instance = Instance::new(
+            unit_sphere(),
+            Transform::scale(&Vec3::new(2.0, 2.0, 2.0))
+                .then(&Transform::rotate(&Vec3::new(1.0, 2.0, 3.0), 70.0))
+                .then(&Transform::translate(&center)),
+        );
+        let material = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Sphere::new(&center, 2.0, material);
+
+        let mut num_hits = 0;
+        for _ in 0..1000 {
+            let ray = random_ray(&center);
+            match (
+                instance.hit(&ray, 0.001, 100.0),
+                sphere.hit(&ray, 0.001, 100.0),
+            ) {
+                (Some(actual), Some(expected)) => {
+                    assert!((actual.t - expected.t).abs() < 1e-9);
+                    assert_vec_close(&actual.point, &expected.point);
+                    assert_vec_close(&actual.normal, &expected.normal);
+                    assert_vec_close(&actual.geometric_normal, &expected.geometric_normal);
+                    assert_eq!(actual.front_face, expected.front_face);
+                    num_hits += 1;
+                }
+                (None, None) => {}
+                _ => panic!("instance and sphere disagree on {:?}", ray),
+            }
+            // Outside the sphere, both see it in the same cone of directions.
+            if (ray.origin - center).length() > 2.0 {
+                let actual = instance.pdf_value(&ray.origin, &ray.direction);
+                let expected = sphere.pdf_value(&ray.origin, &ray.direction);
+                assert!((actual - expected).abs() < 1e-9 * expected.max(1.0));
+            }
+        }
+        assert!(num_hits > 100);
+
+        let bbox = instance.bounding_box().unwrap();
+        for i in 0..3 {
+            assert!(bbox.min.e[i] <= center.e[i] - 2.0 + 1e-9);
+            assert!(bbox.max.e[i] >= center.e[i] + 2.0 - 1e-9);
+        }
+    }
+
+    #[test]
+    fn normals_under_non_uniform_scale() {
+        // The ellipsoid x^2 / 4 + y^2 + z^2 = 1, whose normal follows (x / 4, y, z).
+        let instance = Instance::new(unit_sphere(), Transform::scale(&Vec3::new(2.0, 1.0, 1.0)));
+        let x = 2f64.sqrt();
+        let z = 0.5f64.sqrt();
+        let ray = Ray {
+            origin: Point::new(x, 0.0, 5.0),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let hit = instance.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - (5.0 - z)).abs() < 1e-9);
+        assert_vec_close(&hit.point, &Point::new(x, 0.0, z));
+        let expected = to_unit_vector(&Vec3::new(x / 4.0, 0.0, z));
+        assert_vec_close(&hit.normal, &expected);
+        assert_vec_close(&hit.geometric_normal, &expected);
+        // Tangents stay in the surface.
+        assert!(dot_product(&hit.dpdu, &hit.normal).abs() < 1e-9);
+        assert!(dot_product(&hit.dpdv, &hit.normal).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pdf_under_non_uniform_scale_matches_random() {
+        // Each sampled direction covers 1 / pdf of solid angle, so their average is the solid
+        // angle of the ellipsoid, which uniform directions measure independently.
+        let instance = Instance::new(
+            unit_sphere(),
+            Transform::scale(&Vec3::new(3.0, 1.0, 0.5))
+                .then(&Transform::rotate(&Vec3::new(0.0, 1.0, 1.0), 25.0))
+                .then(&Transform::translate(&Vec3::new(0.0, 0.0, 4.0))),
+        );
+        let origin = Point::new(0.5, -0.5, 0.0);
+        const NUM_SAMPLES: usize = 100_000;
+        let mut sampled_solid_angle = 0.0;
+        let mut num_uniform_hits = 0;
+        for _ in 0..NUM_SAMPLES {
+            let direction = instance.random(&origin);
+            let ray = Ray {
+                origin,
+                direction,
+                time: 0.0,
+            };
+            assert!(instance.hit(&ray, 0.001, f64::INFINITY).is_some());
+            sampled_solid_angle += 1.0 / instance.pdf_value(&origin, &direction);
+
+            let z = random_in_range(-1.0, 1.0);
+            let phi = random_in_range(0.0, 2.0 * PI);
+            let r = (1.0 - z * z).sqrt();
+            let uniform = Ray {
+                origin,
+                direction: Vec3::new(r * phi.cos(), r * phi.sin(), z),
+                time: 0.0,
+            };
+            if instance.hit(&uniform, 0.001, f64::INFINITY).is_some() {
+                num_uniform_hits += 1;
+            }
+        }
+        let sampled_solid_angle = sampled_solid_angle / NUM_SAMPLES as f64;
+        let solid_angle = 4.0 * PI * num_uniform_hits as f64 / NUM_SAMPLES as f64;
+        assert!(
+            (sampled_solid_angle - solid_angle).abs() < 0.03 * solid_angle,
+            "{} != {}",
+            sampled_solid_angle,
+            solid_angle
+        );
+    }
+}
@@ -1,133 +1,205 @@
+mod bump;
+mod bvh;
+mod cli;
+mod csg;
+mod curve;
+mod gltf_import;
+mod hair;
+mod heightfield;
+mod image;
+mod instance;
 mod math;
+mod medium;
+mod mesh;
+mod pbr;
+mod pdf;
+mod planar;
+mod ply;
+mod quadric;
+mod render;
+mod scene;
+mod sdf;
+mod stl;
+#[cfg(test)]
+mod test_util;
+mod texture;
+mod tonemap;
 mod trace;
+mod volume;
 
+use crate::cli::{
+    BenchCommand, Cli, Command, RenderArgs, RenderCommand, SceneArgs, TurntableCommand,
+};
+use crate::image::{write_image, ImageFormat};
 use crate::math::{linspace, random_float, random_in_range, Color, Point, Vec3};
+use crate::planar::Plane;
+use crate::render::{render, RenderSettings};
+use crate::scene::{load_scene, CameraSettings, SceneFile};
+use crate::texture::{CheckerTexture, SolidColor};
 use crate::trace::{
-    get_ray_color, Camera, DiaelectriMaterial, HittableCollection, LambertianMaterial,
-    MetalMaterial, Sphere, BLACK,
+    Background, DiaelectriMaterial, Hittable, HittableCollection, LambertianMaterial,
+    MetalMaterial, Scene, Sphere,
 };
-use rayon::prelude::*;
+use clap::Parser;
 use std::f64::consts::PI;
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
-use trace::write_pixel;
 
-const ASPECT_RATIO: f64 = 16.0 / 9.0;
-const IMAGE_WIDTH: u32 = 1280;
-const IMAGE_HEIGHT: u32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as u32;
-const TILE_WIDTH: u32 = 16;
-const TILE_HEIGHT: u32 = (TILE_WIDTH as f64 / ASPECT_RATIO) as u32;
-const SAMPLES_PER_PIXEL: u32 = 500;
-const MAX_DEPTH: u32 = 20;
+fn main() {
+    let cli = Cli::parse();
+    let result = match &cli.command {
+        Command::Render(command) => run_render(command),
+        Command::Turntable(command) => run_turntable(command),
+        Command::Info(args) => run_info(args),
+        Command::Bench(command) => run_bench(command),
+    };
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn load(args: &SceneArgs) -> io::Result<SceneFile> {
+    match &args.scene {
+        Some(path) => load_scene(path),
+        None => Ok(SceneFile {
+            scene: Scene::new(generate_world(), Background::Sky),
+            camera: CameraSettings {
+                look_from: Point::new(13.34, 7.0, 0.0),
+                look_at: Point::new(0.0, 0.0, 0.0),
+                vup: Vec3::new(0.0, 1.0, 0.0),
+                vfov: 20.0,
+                aperture: 0.1,
+                focus_distance: Some(10.0),
+                shutter_open: 0.0,
+                shutter_close: 0.0,
+            },
+            settings: RenderSettings::default(),
+        }),
+    }
+}
 
-fn main() -> std::io::Result<()> {
-    let world = Arc::new(generate_world());
-    let camera_locus_radius = 13.34;
+fn configure(args: &RenderArgs, settings: &RenderSettings) -> io::Result<RenderSettings> {
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(|err| io::Error::other(err.to_string()))?;
+    }
+
+    let settings = args.apply(settings);
+    settings
+        .validate()
+        .map_err(|msg| io::Error::new(io::ErrorKind::InvalidInput, msg))?;
+    Ok(settings)
+}
+
+fn run_render(command: &RenderCommand) -> io::Result<()> {
+    let format = ImageFormat::resolve(&command.output, command.format, command.alpha)?;
+    let scene_file = load(&command.scene)?;
+    let settings = configure(&command.render, &scene_file.settings)?;
+    let scene = Arc::new(scene_file.scene);
+    let camera = Arc::new(scene_file.camera.build(settings.aspect_ratio()));
+
+    let render_timer = Instant::now();
+    let frame_buffer = render(&scene, &camera, &settings);
+    eprintln!("Done in {} ms", render_timer.elapsed().as_millis());
+
+    write_image(
+        &command.output,
+        format,
+        command.alpha,
+        &settings.tone_mapping,
+        &frame_buffer,
+    )
+}
+
+fn run_turntable(command: &TurntableCommand) -> io::Result<()> {
+    if command.frames < 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a turntable needs at least 2 frames",
+        ));
+    }
+    let end_frame = command
+        .end_frame
+        .unwrap_or(command.frames)
+        .min(command.frames);
+    if command.start_frame >= end_frame {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "frame range {}..{} is empty",
+                command.start_frame, end_frame
+            ),
+        ));
+    }
+
+    let format = ImageFormat::resolve(Path::new(&command.output), command.format, command.alpha)?;
+
+    let scene_file = load(&command.scene)?;
+    let settings = configure(&command.render, &scene_file.settings)?;
+    let scene = Arc::new(scene_file.scene);
+    let angles = linspace(0.0, 2.0 * PI, command.frames);
+    let angle_step = angles[1] - angles[0];
+    let camera_settings = match command.shutter {
+        Some(shutter) => CameraSettings {
+            shutter_open: 0.0,
+            shutter_close: shutter,
+            ..scene_file.camera
+        },
+        None => scene_file.camera,
+    };
 
     let mut render_stats = vec![];
 
     let mut total_render_time = 0u128;
-    let num_steps = 240;
-    for (step_idx, camera_locus_angle) in linspace(0.0, 2.0 * PI, num_steps).into_iter().enumerate()
-    {
+    for (step_idx, frame_idx) in (command.start_frame..end_frame).enumerate() {
         let render_timer = Instant::now();
-        let look_from = Point::new(
-            camera_locus_radius * camera_locus_angle.cos(),
-            7.0,
-            camera_locus_radius * camera_locus_angle.sin(),
+        // The camera keeps orbiting while the shutter is open, up to where the next frame
+        // starts.
+        let angle = angles[frame_idx as usize];
+        let camera = Arc::new(
+            camera_settings
+                .orbit(angle)
+                .build(settings.aspect_ratio())
+                .with_motion(
+                    camera_settings
+                        .orbit(angle + angle_step)
+                        .build(settings.aspect_ratio()),
+                ),
         );
-        let look_at = Point::new(0.0, 0.0, 0.0);
-        let vup = Vec3::new(0.0, 1.0, 0.0);
-        let distance_to_focus = 10.0;
-        let aperture = 0.1;
-
-        let camera = Arc::new(Camera::new(
-            &look_from,
-            &look_at,
-            &vup,
-            20.0,
-            ASPECT_RATIO,
-            aperture,
-            distance_to_focus,
-        ));
-        let world = world.clone();
-
-        let num_tiles = (IMAGE_WIDTH * IMAGE_HEIGHT) / (TILE_WIDTH * TILE_HEIGHT);
-        let tiles_per_row = IMAGE_WIDTH / TILE_WIDTH;
-
-        let tile_results = (0..num_tiles)
-            .into_par_iter()
-            .map(move |tile_idx| {
-                let col_start = (tile_idx % tiles_per_row) * TILE_WIDTH;
-                let col_end = col_start + TILE_WIDTH;
-                let row_start = (tile_idx / tiles_per_row) * TILE_HEIGHT;
-                let row_end = row_start + TILE_HEIGHT;
-
-                let mut tile_buffer = vec![vec![BLACK; TILE_WIDTH as usize]; TILE_HEIGHT as usize];
-
-                for j in row_start..row_end {
-                    for i in col_start..col_end {
-                        let mut pixel_color = BLACK;
-
-                        for _s in 0..SAMPLES_PER_PIXEL {
-                            let u = (i as f64 + random_float()) / (IMAGE_WIDTH - 1) as f64;
-                            let v = (j as f64 + random_float()) / (IMAGE_HEIGHT - 1) as f64;
-                            let ray = camera.get_ray(u, v);
-                            pixel_color += get_ray_color(&ray, &world, MAX_DEPTH);
-                        }
-                        pixel_color /= SAMPLES_PER_PIXEL as f64;
-                        let tile_j = j - row_start;
-                        let tile_i = i - col_start;
-                        tile_buffer[tile_j as usize][tile_i as usize] = pixel_color;
-                    }
-                }
-
-                (tile_idx, tile_buffer)
-            })
-            .collect::<Vec<_>>();
-
-        let mut frame_buffer = vec![vec![BLACK; IMAGE_WIDTH as usize]; IMAGE_HEIGHT as usize];
-        for (tile_idx, tile_buffer) in tile_results {
-            for j in 0..TILE_HEIGHT {
-                for i in 0..TILE_WIDTH {
-                    let frame_buffer_i = (tile_idx % tiles_per_row) * TILE_WIDTH + i;
-                    let frame_buffer_j = (tile_idx / tiles_per_row) * TILE_HEIGHT + j;
-                    frame_buffer[frame_buffer_j as usize][frame_buffer_i as usize] =
-                        tile_buffer[j as usize][i as usize];
-                }
-            }
-        }
+
+        let frame_buffer = render(&scene, &camera, &settings);
 
         let render_time = render_timer.elapsed().as_millis();
-        render_stats.push((step_idx, render_time));
+        render_stats.push((frame_idx, render_time));
         total_render_time += render_time;
         let avg_render_time = total_render_time / (step_idx as u128 + 1);
-        let frames_left = num_steps - step_idx as u32 - 1;
+        let frames_left = end_frame - frame_idx - 1;
         let est_time_left = avg_render_time * frames_left as u128;
         let est_time_left_mins = est_time_left / 60000;
         eprintln!(
             "Step {:03} done in {} ms. Est time left = {} mins",
-            step_idx + 1,
+            frame_idx + 1,
             render_time,
             est_time_left_mins
         );
 
-        let file_name = format!("output_{:03}.ppm", step_idx);
-        let mut output = BufWriter::new(File::create(&Path::new(&file_name))?);
-        writeln!(&mut output, "P3\n{} {}\n255", IMAGE_WIDTH, IMAGE_HEIGHT)?;
-        for j in (0..IMAGE_HEIGHT).rev() {
-            for i in 0..IMAGE_WIDTH {
-                write_pixel(&mut output, &frame_buffer[j as usize][i as usize])?;
-            }
-        }
-        output.flush()?;
+        let file_name = frame_path(&command.output, frame_idx)?;
+        write_image(
+            &file_name,
+            format,
+            command.alpha,
+            &settings.tone_mapping,
+            &frame_buffer,
+        )?;
     }
 
-    let mut stats_writer = csv::Writer::from_path(&Path::new("output_stats.csv"))?;
-    stats_writer.write_record(&["Step_Idx", "Time_In_Ms"])?;
+    let mut stats_writer = csv::Writer::from_path(&command.stats)?;
+    stats_writer.write_record(["Step_Idx", "Time_In_Ms"])?;
     for (i, time) in render_stats.into_iter() {
         stats_writer.write_record(&[format!("{}", i), format!("{}", time)])?;
     }
@@ -136,15 +208,147 @@ fn main() -> std::io::Result<()> {
     Ok(())
 }
 
+// Replaces the first run of '#' in `template` with `frame_idx`, zero-padded to the run's length.
+fn frame_path(template: &str, frame_idx: u32) -> io::Result<PathBuf> {
+    let start = template.find('#').ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "output template '{}' has no '#' for the frame number",
+                template
+            ),
+        )
+    })?;
+    let width = template[start..].chars().take_while(|&c| c == '#').count();
+    Ok(PathBuf::from(format!(
+        "{}{:0width$}{}",
+        &template[..start],
+        frame_idx,
+        &template[start + width..],
+        width = width
+    )))
+}
+
+fn run_info(args: &SceneArgs) -> io::Result<()> {
+    let load_timer = Instant::now();
+    let scene_file = load(args)?;
+    let load_time = load_timer.elapsed().as_millis();
+
+    let scene = &scene_file.scene;
+    let camera = &scene_file.camera;
+    let settings = &scene_file.settings;
+    let name = args.scene.as_ref().map_or_else(
+        || String::from("built-in random spheres"),
+        |path| path.display().to_string(),
+    );
+
+    println!("Scene:       {}", name);
+    println!("Load time:   {} ms", load_time);
+    println!("Objects:     {}", scene.world.num_hittables());
+    println!("Lights:      {}", scene.lights.hittables.len());
+    println!(
+        "BVH:         {} nodes, depth {}",
+        scene.world.num_nodes(),
+        scene.world.depth()
+    );
+    match scene.world.bounding_box() {
+        Some(bbox) => println!(
+            "Bounds:      {} to {}",
+            format_vec3(&bbox.min),
+            format_vec3(&bbox.max)
+        ),
+        None => println!("Bounds:      unbounded"),
+    }
+    println!(
+        "Camera:      from {} at {}, vfov {}, aperture {}",
+        format_vec3(&camera.look_from),
+        format_vec3(&camera.look_at),
+        camera.vfov,
+        camera.aperture
+    );
+    println!(
+        "Render:      {}x{}, {} spp, max depth {}",
+        settings.image_width, settings.image_height, settings.samples_per_pixel, settings.max_depth
+    );
+    Ok(())
+}
+
+fn run_bench(command: &BenchCommand) -> io::Result<()> {
+    let scene_file = load(&command.scene)?;
+    let settings = configure(&command.render, &scene_file.settings)?;
+    let scene = Arc::new(scene_file.scene);
+    let camera = Arc::new(scene_file.camera.build(settings.aspect_ratio()));
+    let samples_per_frame = settings.image_width as f64
+        * settings.image_height as f64
+        * settings.samples_per_pixel as f64;
+
+    eprintln!(
+        "Benchmarking {}x{} at {} spp, {} threads",
+        settings.image_width,
+        settings.image_height,
+        settings.samples_per_pixel,
+        rayon::current_num_threads()
+    );
+
+    let mut render_times = vec![];
+    for iteration in 0..command.iterations {
+        let render_timer = Instant::now();
+        render(&scene, &camera, &settings);
+        let render_time = render_timer.elapsed().as_secs_f64() * 1000.0;
+        eprintln!(
+            "Iteration {:03}: {:.1} ms, {:.2} Msamples/s",
+            iteration + 1,
+            render_time,
+            samples_per_frame / (render_time * 1000.0)
+        );
+        render_times.push(render_time);
+    }
+
+    if !render_times.is_empty() {
+        let mean = render_times.iter().sum::<f64>() / render_times.len() as f64;
+        let min = render_times.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = render_times
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+        println!(
+            "mean {:.1} ms, min {:.1} ms, max {:.1} ms, {:.2} Msamples/s",
+            mean,
+            min,
+            max,
+            samples_per_frame / (mean * 1000.0)
+        );
+    }
+
+    if let Some(stats_path) = &command.stats {
+        let mut stats_writer = csv::Writer::from_path(stats_path)?;
+        stats_writer.write_record(["Iteration", "Time_In_Ms"])?;
+        for (i, time) in render_times.iter().enumerate() {
+            stats_writer.write_record(&[format!("{}", i), format!("{:.3}", time)])?;
+        }
+        stats_writer.flush()?;
+    }
+
+    Ok(())
+}
+
+fn format_vec3(v: &Vec3) -> String {
+    format!("({:.3}, {:.3}, {:.3})", v.x(), v.y(), v.z())
+}
+
 fn generate_world() -> HittableCollection {
     let mut world = HittableCollection::new();
 
     let ground_material = Arc::new(LambertianMaterial {
-        albedo: Color::new(0.5, 0.5, 0.5),
+        albedo: Arc::new(CheckerTexture {
+            even: Arc::new(SolidColor::new(Color::new(0.2, 0.3, 0.1))),
+            odd: Arc::new(SolidColor::new(Color::new(0.9, 0.9, 0.9))),
+            scale: 0.5,
+        }),
     });
-    world.add(Box::new(Sphere::new(
-        &Point::new(0.0, -1000.0, 0.0),
-        1000.0,
+    world.add(Arc::new(Plane::new(
+        &Point::new(0.0, 0.0, 0.0),
+        &Vec3::new(0.0, 1.0, 0.0),
         ground_material,
     )));
 
@@ -165,44 +369,39 @@ fn generate_world() -> HittableCollection {
             if material_choice < 0.8 {
                 // Diffuse
                 let albedo = Color::random() * Color::random();
-                let material = Arc::new(LambertianMaterial { albedo });
-                let sphere = Box::new(Sphere::new(&center, 0.2, material));
+                let material = Arc::new(LambertianMaterial::new(albedo));
+                let sphere = Arc::new(Sphere::new(&center, 0.2, material));
                 world.add(sphere);
             } else if material_choice < 0.95 {
                 // Metal
                 let albedo = Color::random_in_range(0.5, 1.0);
                 let fuzziness = random_in_range(0.0, 0.5);
-                let material = Arc::new(MetalMaterial { albedo, fuzziness });
-                let sphere = Box::new(Sphere::new(&center, 0.2, material));
+                let material = Arc::new(MetalMaterial::new(albedo, fuzziness));
+                let sphere = Arc::new(Sphere::new(&center, 0.2, material));
                 world.add(sphere);
             } else {
                 // Glass
                 let material = Arc::new(DiaelectriMaterial::new(1.5));
-                let sphere = Box::new(Sphere::new(&center, 0.2, material));
+                let sphere = Arc::new(Sphere::new(&center, 0.2, material));
                 world.add(sphere);
             }
         }
     }
 
-    world.add(Box::new(Sphere::new(
+    world.add(Arc::new(Sphere::new(
         &Point::new(0.0, 1.0, 0.0),
         1.0,
         Arc::new(DiaelectriMaterial::new(1.5)),
     )));
-    world.add(Box::new(Sphere::new(
+    world.add(Arc::new(Sphere::new(
         &Point::new(-4.0, 1.0, 0.0),
         1.0,
-        Arc::new(LambertianMaterial {
-            albedo: Color::new(0.4, 0.2, 0.1),
-        }),
+        Arc::new(LambertianMaterial::new(Color::new(0.4, 0.2, 0.1))),
     )));
-    world.add(Box::new(Sphere::new(
+    world.add(Arc::new(Sphere::new(
         &Point::new(4.0, 1.0, 0.0),
         1.0,
-        Arc::new(MetalMaterial {
-            albedo: Color::new(0.7, 0.6, 0.5),
-            fuzziness: 0.0,
-        }),
+        Arc::new(MetalMaterial::new(Color::new(0.7, 0.6, 0.5), 0.0)),
     )));
 
     world
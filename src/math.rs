@@ -1,9 +1,11 @@
 use num::{Float, FromPrimitive};
 use rand::Rng;
+use serde::Deserialize;
 use std::f64::consts::PI;
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(from = "[f64; 3]")]
 pub struct Vec3 {
     pub e: [f64; 3],
 }
@@ -11,10 +13,39 @@ pub struct Vec3 {
 pub type Point = Vec3;
 pub type Color = Vec3;
 
+// `time` runs from 0 to 1 over a frame; moving geometry is placed where it is at that time.
 #[derive(Debug, Copy, Clone)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    pub time: f64,
+}
+
+// Orthonormal basis with `w` as the "up" axis, for sampling directions around a normal.
+#[derive(Debug, Copy, Clone)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+// Row-major 4x4 matrix acting on column vectors.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+// An affine transform kept together with its inverse, which rays and normals are mapped by.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    pub matrix: Mat4,
+    pub inverse: Mat4,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    pub min: Point,
+    pub max: Point,
 }
 
 impl Vec3 {
@@ -55,6 +86,12 @@ impl Vec3 {
     }
 }
 
+impl From<[f64; 3]> for Vec3 {
+    fn from(e: [f64; 3]) -> Self {
+        Vec3 { e }
+    }
+}
+
 impl Add for Vec3 {
     type Output = Self;
 
@@ -143,6 +180,380 @@ impl Ray {
     }
 }
 
+impl Onb {
+    pub fn from_w(n: &Vec3) -> Self {
+        let w = to_unit_vector(n);
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = to_unit_vector(&cross_product(&w, &a));
+        let u = cross_product(&w, &v);
+        Onb { u, v, w }
+    }
+
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        self.u * a.x() + self.v * a.y() + self.w * a.z()
+    }
+}
+
+impl Mat4 {
+    pub const fn new(m: [[f64; 4]; 4]) -> Self {
+        Mat4 { m }
+    }
+
+    pub const fn identity() -> Self {
+        Mat4::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Mat4::new(m)
+    }
+
+    // Determinant of the upper-left 3x3 block, which is how much the transform scales volumes.
+    pub fn determinant3(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    // Gauss-Jordan elimination with partial pivoting; None if the matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let mut m = self.m;
+        let mut inverse = Mat4::identity().m;
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&a, &b| m[a][column].abs().total_cmp(&m[b][column].abs()))
+                .unwrap();
+            if m[pivot][column] == 0.0 {
+                return None;
+            }
+            m.swap(column, pivot);
+            inverse.swap(column, pivot);
+            let scale = 1.0 / m[column][column];
+            m[column].iter_mut().for_each(|value| *value *= scale);
+            inverse[column].iter_mut().for_each(|value| *value *= scale);
+
+            let (pivot_row, pivot_inverse) = (m[column], inverse[column]);
+            for (row, (m_row, inverse_row)) in m.iter_mut().zip(inverse.iter_mut()).enumerate() {
+                let factor = m_row[column];
+                if row == column || factor == 0.0 {
+                    continue;
+                }
+                for (value, pivot) in m_row.iter_mut().zip(pivot_row) {
+                    *value -= factor * pivot;
+                }
+                for (value, pivot) in inverse_row.iter_mut().zip(pivot_inverse) {
+                    *value -= factor * pivot;
+                }
+            }
+        }
+        Some(Mat4::new(inverse))
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Mat4::new(m)
+    }
+}
+
+impl Transform {
+    pub const fn identity() -> Self {
+        Transform {
+            matrix: Mat4::identity(),
+            inverse: Mat4::identity(),
+        }
+    }
+
+    pub fn translate(offset: &Vec3) -> Self {
+        let (x, y, z) = (offset.x(), offset.y(), offset.z());
+        Transform {
+            matrix: Mat4::new([
+                [1.0, 0.0, 0.0, x],
+                [0.0, 1.0, 0.0, y],
+                [0.0, 0.0, 1.0, z],
+                [0.0, 0.0, 0.0, 1.0],
+            ]),
+            inverse: Mat4::new([
+                [1.0, 0.0, 0.0, -x],
+                [0.0, 1.0, 0.0, -y],
+                [0.0, 0.0, 1.0, -z],
+                [0.0, 0.0, 0.0, 1.0],
+            ]),
+        }
+    }
+
+    // Any invertible affine matrix, such as one read from a scene file.
+    pub fn from_matrix(matrix: Mat4) -> Option<Self> {
+        Some(Transform {
+            matrix,
+            inverse: matrix.inverse()?,
+        })
+    }
+
+    // Scale factors must be non-zero.
+    pub fn scale(factors: &Vec3) -> Self {
+        let (x, y, z) = (factors.x(), factors.y(), factors.z());
+        Transform {
+            matrix: Mat4::new([
+                [x, 0.0, 0.0, 0.0],
+                [0.0, y, 0.0, 0.0],
+                [0.0, 0.0, z, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ]),
+            inverse: Mat4::new([
+                [1.0 / x, 0.0, 0.0, 0.0],
+                [0.0, 1.0 / y, 0.0, 0.0],
+                [0.0, 0.0, 1.0 / z, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ]),
+        }
+    }
+
+    // Counter-clockwise rotation by `degrees` looking down `axis` towards the origin.
+    pub fn rotate(axis: &Vec3, degrees: f64) -> Self {
+        let a = to_unit_vector(axis);
+        let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let matrix = Mat4::new([
+            [
+                x * x + (1.0 - x * x) * cos,
+                x * y * (1.0 - cos) - z * sin,
+                x * z * (1.0 - cos) + y * sin,
+                0.0,
+            ],
+            [
+                x * y * (1.0 - cos) + z * sin,
+                y * y + (1.0 - y * y) * cos,
+                y * z * (1.0 - cos) - x * sin,
+                0.0,
+            ],
+            [
+                x * z * (1.0 - cos) - y * sin,
+                y * z * (1.0 - cos) + x * sin,
+                z * z + (1.0 - z * z) * cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        // Rotations are orthogonal.
+        Transform {
+            matrix,
+            inverse: matrix.transpose(),
+        }
+    }
+
+    // Applies `self` and then `next`.
+    pub fn then(&self, next: &Transform) -> Self {
+        Transform {
+            matrix: next.matrix * self.matrix,
+            inverse: self.inverse * next.inverse,
+        }
+    }
+
+    pub fn inverse(&self) -> Self {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    pub fn point(&self, p: &Point) -> Point {
+        let m = &self.matrix.m;
+        Point::new(
+            m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3],
+            m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3],
+            m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3],
+        )
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.matrix.m;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+
+    // Normals are carried by the inverse transpose so they stay perpendicular to the surface
+    // under non-uniform scales. The result is not normalized.
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        let m = &self.inverse.m;
+        Vec3::new(
+            m[0][0] * n.x() + m[1][0] * n.y() + m[2][0] * n.z(),
+            m[0][1] * n.x() + m[1][1] * n.y() + m[2][1] * n.z(),
+            m[0][2] * n.x() + m[1][2] * n.y() + m[2][2] * n.z(),
+        )
+    }
+
+    pub fn ray(&self, ray: &Ray) -> Ray {
+        Ray {
+            origin: self.point(&ray.origin),
+            direction: self.vector(&ray.direction),
+            time: ray.time,
+        }
+    }
+
+    // Box around the transformed corners of `bbox`.
+    pub fn aabb(&self, bbox: &Aabb) -> Aabb {
+        let mut result = Aabb::empty();
+        for corner in 0..8 {
+            let p = Point::new(
+                if corner & 1 == 0 {
+                    bbox.min.x()
+                } else {
+                    bbox.max.x()
+                },
+                if corner & 2 == 0 {
+                    bbox.min.y()
+                } else {
+                    bbox.max.y()
+                },
+                if corner & 4 == 0 {
+                    bbox.min.z()
+                } else {
+                    bbox.max.z()
+                },
+            );
+            result = result.grow(&self.point(&p));
+        }
+        result
+    }
+}
+
+impl Aabb {
+    pub const fn new(min: Point, max: Point) -> Self {
+        Aabb { min, max }
+    }
+
+    pub fn empty() -> Self {
+        Aabb::new(
+            Point::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            Point::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        )
+    }
+
+    pub fn surrounding(a: &Aabb, b: &Aabb) -> Self {
+        Aabb::new(
+            Point::new(
+                a.min.e[0].min(b.min.e[0]),
+                a.min.e[1].min(b.min.e[1]),
+                a.min.e[2].min(b.min.e[2]),
+            ),
+            Point::new(
+                a.max.e[0].max(b.max.e[0]),
+                a.max.e[1].max(b.max.e[1]),
+                a.max.e[2].max(b.max.e[2]),
+            ),
+        )
+    }
+
+    // Empty, with min above max, if the boxes are disjoint.
+    pub fn overlap(a: &Aabb, b: &Aabb) -> Self {
+        Aabb::new(
+            Point::new(
+                a.min.e[0].max(b.min.e[0]),
+                a.min.e[1].max(b.min.e[1]),
+                a.min.e[2].max(b.min.e[2]),
+            ),
+            Point::new(
+                a.max.e[0].min(b.max.e[0]),
+                a.max.e[1].min(b.max.e[1]),
+                a.max.e[2].min(b.max.e[2]),
+            ),
+        )
+    }
+
+    pub fn grow(&self, p: &Point) -> Self {
+        Aabb::surrounding(self, &Aabb::new(*p, *p))
+    }
+
+    pub fn centroid(&self) -> Point {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn diagonal(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.diagonal();
+        if d.e[0] < 0.0 || d.e[1] < 0.0 || d.e[2] < 0.0 {
+            return 0.0;
+        }
+        2.0 * (d.e[0] * d.e[1] + d.e[1] * d.e[2] + d.e[2] * d.e[0])
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let d = self.diagonal();
+        if d.e[0] > d.e[1] && d.e[0] > d.e[2] {
+            0
+        } else if d.e[1] > d.e[2] {
+            1
+        } else {
+            2
+        }
+    }
+
+    // Slab test; `inv_direction` is passed in so BVH traversal computes it once per ray.
+    pub fn hit(&self, ray: &Ray, inv_direction: &Vec3, t_min: f64, t_max: f64) -> bool {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for axis in 0..3 {
+            let t0 = (self.min.e[axis] - ray.origin.e[axis]) * inv_direction.e[axis];
+            let t1 = (self.max.e[axis] - ray.origin.e[axis]) * inv_direction.e[axis];
+            let (t0, t1) = if t0 > t1 { (t1, t0) } else { (t0, t1) };
+            t_min = t0.max(t_min);
+            t_max = t1.min(t_max);
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+
+    // The part of the ray between `t_min` and `t_max` that is inside the box, if any.
+    pub fn clip(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let (mut t_min, mut t_max) = (t_min, t_max);
+        for axis in 0..3 {
+            let inv_direction = 1.0 / ray.direction.e[axis];
+            let t0 = (self.min.e[axis] - ray.origin.e[axis]) * inv_direction;
+            let t1 = (self.max.e[axis] - ray.origin.e[axis]) * inv_direction;
+            let (t0, t1) = if t0 > t1 { (t1, t0) } else { (t0, t1) };
+            t_min = t0.max(t_min);
+            t_max = t1.min(t_max);
+        }
+        if t_min < t_max {
+            Some((t_min, t_max))
+        } else {
+            None
+        }
+    }
+}
+
 pub fn to_unit_vector(v: &Vec3) -> Vec3 {
     *v / v.length()
 }
@@ -237,3 +648,313 @@ where
         .map(|i| start + T::from_u32(i).expect("out of range") * delta)
         .collect()
 }
+
+// Real roots of a x^2 + b x + c in increasing order, avoiding the cancellation in the
+// textbook formula.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if a == 0.0 {
+        if b == 0.0 {
+            return None;
+        }
+        let root = -c / b;
+        return Some((root, root));
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (r0, r1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    Some((r0.min(r1), r0.max(r1)))
+}
+
+// Real roots of x^3 + a x^2 + b x + c, unordered (Cardano, or the trigonometric form when
+// there are three).
+fn solve_normalized_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let p = (b - a * a / 3.0) / 3.0;
+    let q = (2.0 * a * a * a / 27.0 - a * b / 3.0 + c) / 2.0;
+    let discriminant = q * q + p * p * p;
+    let shift = a / 3.0;
+    if discriminant.abs() < 1e-14 {
+        if q.abs() < 1e-14 {
+            vec![-shift]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u - shift, -u - shift]
+        }
+    } else if discriminant < 0.0 {
+        let phi = (-q / (-p * p * p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![
+            t * phi.cos() - shift,
+            -t * (phi + PI / 3.0).cos() - shift,
+            -t * (phi - PI / 3.0).cos() - shift,
+        ]
+    } else {
+        let root = discriminant.sqrt();
+        vec![(root - q).cbrt() - (root + q).cbrt() - shift]
+    }
+}
+
+// Real roots of c[4] x^4 + c[3] x^3 + c[2] x^2 + c[1] x + c[0] in increasing order, by
+// Ferrari's method. Each root is polished with a few Newton steps on the original polynomial,
+// since the closed form loses precision.
+pub fn solve_quartic(c: &[f64; 5]) -> Vec<f64> {
+    let (a, b, cc, d) = (c[3] / c[4], c[2] / c[4], c[1] / c[4], c[0] / c[4]);
+
+    // Substituting x = y - a/4 leaves y^4 + p y^2 + q y + r.
+    let a2 = a * a;
+    let p = -3.0 / 8.0 * a2 + b;
+    let q = a2 * a / 8.0 - a * b / 2.0 + cc;
+    let r = -3.0 / 256.0 * a2 * a2 + a2 * b / 16.0 - a * cc / 4.0 + d;
+
+    let mut roots = vec![];
+    if r.abs() < 1e-14 {
+        roots.push(0.0);
+        roots.extend(solve_normalized_cubic(0.0, p, q));
+    } else {
+        // Any root of the resolvent cubic works in exact arithmetic, but only the largest is
+        // sure to leave u and v non-negative when the quartic has real roots.
+        let z = solve_normalized_cubic(-p / 2.0, -r, r * p / 2.0 - q * q / 8.0)
+            .into_iter()
+            .fold(f64::NEG_INFINITY, f64::max);
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        if u < -1e-14 || v < -1e-14 {
+            return vec![];
+        }
+        let (u, v) = (u.max(0.0).sqrt(), v.max(0.0).sqrt());
+        let v = if q < 0.0 { -v } else { v };
+        for (linear, constant) in [(v, z - u), (-v, z + u)] {
+            if let Some((r0, r1)) = solve_quadratic(1.0, linear, constant) {
+                roots.push(r0);
+                roots.push(r1);
+            }
+        }
+    }
+
+    let polynomial = |x: f64| (((c[4] * x + c[3]) * x + c[2]) * x + c[1]) * x + c[0];
+    let derivative = |x: f64| ((4.0 * c[4] * x + 3.0 * c[3]) * x + 2.0 * c[2]) * x + c[1];
+    for root in roots.iter_mut() {
+        *root -= a / 4.0;
+        for _ in 0..3 {
+            let slope = derivative(*root);
+            if slope == 0.0 {
+                break;
+            }
+            *root -= polynomial(*root) / slope;
+        }
+    }
+    roots.sort_by(|x, y| x.partial_cmp(y).unwrap_or(std::cmp::Ordering::Equal));
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Coefficients of the monic polynomial with `roots`, lowest degree first.
+    fn quartic_with_roots(roots: [f64; 4]) -> [f64; 5] {
+        let mut c = [1.0, 0.0, 0.0, 0.0, 0.0];
+        for (degree, root) in roots.iter().enumerate() {
+            for i in (1..=degree + 1).rev() {
+                c[i] = c[i - 1] - root * c[i];
+            }
+            c[0] *= -root;
+        }
+        c
+    }
+
+    fn assert_roots(c: &[f64; 5], expected: &[f64], tolerance: f64) {
+        let roots = solve_quartic(c);
+        assert_eq!(roots.len(), expected.len(), "{:?}: {:?}", c, roots);
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!(
+                (root - expected).abs() < tolerance,
+                "{:?}: {:?} != {:?}",
+                c,
+                roots,
+                expected
+            );
+        }
+    }
+
+    fn assert_matrix_close(a: &Mat4, b: &Mat4) {
+        for (row_a, row_b) in a.m.iter().zip(b.m.iter()) {
+            for (x, y) in row_a.iter().zip(row_b.iter()) {
+                assert!((x - y).abs() < 1e-9, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn quartic_with_known_roots() {
+        let c = quartic_with_roots([1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(c, [24.0, -50.0, 35.0, -10.0, 1.0]);
+        assert_roots(&c, &[1.0, 2.0, 3.0, 4.0], 1e-9);
+
+        assert_roots(
+            &quartic_with_roots([-2.5, -0.1, 0.7, 12.0]),
+            &[-2.5, -0.1, 0.7, 12.0],
+            1e-9,
+        );
+        // Not monic, and with a root at zero.
+        let c = quartic_with_roots([0.0, 1.0, -1.0, 5.0]).map(|c| c * -3.0);
+        assert_roots(&c, &[-1.0, 0.0, 1.0, 5.0], 1e-9);
+    }
+
+    #[test]
+    fn quartic_with_two_real_roots() {
+        // (x^2 + 1)(x - 2)(x + 3)
+        assert_roots(&[-6.0, 1.0, -5.0, 1.0, 1.0], &[-3.0, 2.0], 1e-9);
+    }
+
+    #[test]
+    fn quartic_with_double_roots() {
+        // The resolvent cubic has a double root above its single one, and taking the single
+        // root leaves v a rounding error below zero.
+        let roots = solve_quartic(&quartic_with_roots([-2.7, -2.7, -4.1, -1.3]));
+        assert!(
+            roots.iter().any(|root| (root + 4.1).abs() < 1e-9),
+            "{:?}",
+            roots
+        );
+        assert!(
+            roots.iter().any(|root| (root + 1.3).abs() < 1e-9),
+            "{:?}",
+            roots
+        );
+        assert!(roots.iter().all(|root| [-4.1, -2.7, -1.3]
+            .iter()
+            .any(|expected| (root - expected).abs() < 1e-4)));
+
+        let roots = solve_quartic(&quartic_with_roots([-3.0, -3.0, 2.0, 2.0]));
+        assert!(!roots.is_empty());
+        for root in roots.iter() {
+            assert!(
+                (root + 3.0).abs() < 1e-4 || (root - 2.0).abs() < 1e-4,
+                "{:?}",
+                roots
+            );
+        }
+    }
+
+    #[test]
+    fn quartic_without_real_roots() {
+        assert_roots(&[1.0, 0.0, 0.0, 0.0, 1.0], &[], 0.0);
+        // (x^2 + 1)(x^2 + 4)
+        assert_roots(&[4.0, 0.0, 5.0, 0.0, 1.0], &[], 0.0);
+        // (x^2 - 2x + 2)(x^2 + 4x + 5)
+        assert_roots(&[10.0, -2.0, 1.0, 2.0, 1.0], &[], 0.0);
+    }
+
+    #[test]
+    fn quadratic_roots_are_ordered() {
+        assert_eq!(solve_quadratic(1.0, -3.0, 2.0), Some((1.0, 2.0)));
+        assert_eq!(solve_quadratic(-1.0, 3.0, -2.0), Some((1.0, 2.0)));
+        assert_eq!(solve_quadratic(0.0, 2.0, -4.0), Some((2.0, 2.0)));
+        assert_eq!(solve_quadratic(1.0, 0.0, 1.0), None);
+    }
+
+    #[test]
+    fn inverse_round_trips_to_identity() {
+        let transform = Transform::scale(&Vec3::new(2.0, -0.5, 3.0))
+            .then(&Transform::rotate(&Vec3::new(1.0, 2.0, 3.0), 40.0))
+            .then(&Transform::translate(&Vec3::new(-1.0, 5.0, 2.0)));
+        let matrix = transform.matrix;
+        let inverse = matrix.inverse().unwrap();
+        assert_matrix_close(&(matrix * inverse), &Mat4::identity());
+        assert_matrix_close(&(inverse * matrix), &Mat4::identity());
+        assert_matrix_close(&inverse, &transform.inverse);
+        assert_matrix_close(&inverse.inverse().unwrap(), &matrix);
+        assert_eq!(Mat4::identity().inverse(), Some(Mat4::identity()));
+    }
+
+    #[test]
+    fn inverse_needs_pivoting() {
+        // A zero on the diagonal, which elimination without row swaps would divide by.
+        let matrix = Mat4::new([
+            [0.0, 1.0, 0.0, 2.0],
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 4.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        let inverse = matrix.inverse().unwrap();
+        assert_matrix_close(&(matrix * inverse), &Mat4::identity());
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        let flattened = Transform::scale(&Vec3::new(1.0, 0.0, 1.0)).matrix;
+        assert_eq!(flattened.inverse(), None);
+        let repeated_row = Mat4::new([
+            [1.0, 2.0, 3.0, 4.0],
+            [1.0, 2.0, 3.0, 4.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        assert_eq!(repeated_row.inverse(), None);
+    }
+
+    fn assert_vec_close(a: &Vec3, b: &Vec3) {
+        assert!((*a - *b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn transforms_apply_in_order() {
+        let transform = Transform::scale(&Vec3::new(2.0, 2.0, 2.0))
+            .then(&Transform::translate(&Vec3::new(1.0, 0.0, 0.0)));
+        assert_vec_close(
+            &transform.point(&Point::new(1.0, 1.0, 1.0)),
+            &Point::new(3.0, 2.0, 2.0),
+        );
+        // Vectors ignore the translation.
+        assert_vec_close(
+            &transform.vector(&Vec3::new(1.0, 1.0, 1.0)),
+            &Vec3::new(2.0, 2.0, 2.0),
+        );
+        assert_vec_close(
+            &transform.inverse().point(&Point::new(3.0, 2.0, 2.0)),
+            &Point::new(1.0, 1.0, 1.0),
+        );
+        let rotation = Transform::rotate(&Vec3::new(0.0, 0.0, 1.0), 90.0);
+        assert_vec_close(
+            &rotation.vector(&Vec3::new(1.0, 0.0, 0.0)),
+            &Vec3::new(0.0, 1.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scale() {
+        let transform = Transform::scale(&Vec3::new(4.0, 1.0, 0.5))
+            .then(&Transform::rotate(&Vec3::new(1.0, 1.0, 0.0), 30.0));
+        let normal = Vec3::new(1.0, 1.0, 0.0);
+        for tangent in [Vec3::new(1.0, -1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)] {
+            let transformed = dot_product(&transform.vector(&tangent), &transform.normal(&normal));
+            assert!(transformed.abs() < 1e-9, "{}", transformed);
+        }
+        // Transforming the normal like a vector would tilt it off the surface.
+        let wrong = dot_product(
+            &transform.vector(&Vec3::new(1.0, -1.0, 0.0)),
+            &transform.vector(&normal),
+        );
+        assert!(wrong.abs() > 1.0);
+    }
+
+    #[test]
+    fn transformed_boxes_enclose_the_corners() {
+        let bbox = Aabb::new(Point::new(-1.0, -1.0, 0.0), Point::new(1.0, 1.0, 2.0));
+        let transform = Transform::rotate(&Vec3::new(0.0, 0.0, 1.0), 45.0)
+            .then(&Transform::translate(&Vec3::new(0.0, 0.0, 1.0)));
+        let transformed = transform.aabb(&bbox);
+        let half_diagonal = 2f64.sqrt();
+        assert_vec_close(
+            &transformed.min,
+            &Point::new(-half_diagonal, -half_diagonal, 1.0),
+        );
+        assert_vec_close(
+            &transformed.max,
+            &Point::new(half_diagonal, half_diagonal, 3.0),
+        );
+    }
+}
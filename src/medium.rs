@@ -0,0 +1,263 @@
+use crate::math::{dot_product, random_float, to_unit_vector, Aabb, Color, Ray, Vec3};
+use crate::pdf::{henyey_greenstein, HenyeyGreensteinPdf, SpherePdf};
+use crate::texture::Texture;
+use crate::trace::{HitRecord, Hittable, Material, ScatterRecord};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Fills a closed `boundary` with a medium of uniform `density`, the chance per unit of distance
+// that a ray scatters. Rays that make it through see nothing of the boundary itself.
+pub struct ConstantMedium {
+    pub boundary: Arc<dyn Hittable + Send + Sync>,
+    pub density: f64,
+    pub phase_function: Arc<dyn Material + Send + Sync>,
+}
+
+// Phase function materials are for media; they scatter the same way whatever the hit's normal.
+pub struct IsotropicMaterial {
+    pub albedo: Arc<dyn Texture + Send + Sync>,
+}
+
+pub struct HenyeyGreensteinMaterial {
+    pub albedo: Arc<dyn Texture + Send + Sync>,
+    // Mean cosine of the scattering angle, in (-1, 1).
+    pub g: f64,
+}
+
+impl ConstantMedium {
+    // Walks the spans of the ray inside the boundary between `t_min` and `t_max`, in order,
+    // until `visit` returns a value. Spans start behind the ray so a ray that starts inside is
+    // caught too.
+    fn spans<T>(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        mut visit: impl FnMut(f64, f64) -> Option<T>,
+    ) -> Option<T> {
+        const SEPARATION: f64 = 1e-4;
+        let mut t = f64::NEG_INFINITY;
+        loop {
+            let entry = self.boundary.hit(ray, t, f64::INFINITY)?;
+            let exit = self
+                .boundary
+                .hit(ray, entry.t + SEPARATION, f64::INFINITY)?;
+            let start = entry.t.max(t_min);
+            let end = exit.t.min(t_max);
+            if start < end {
+                if let Some(result) = visit(start, end) {
+                    return Some(result);
+                }
+            }
+            if exit.t >= t_max {
+                return None;
+            }
+            t = exit.t + SEPARATION;
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    // Free flight is memoryless, so each span is sampled afresh.
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let speed = ray.direction.length();
+        self.spans(ray, t_min, t_max, |start, end| {
+            let distance = -random_float().ln() / self.density;
+            let hit_t = start + distance / speed;
+            if hit_t < end {
+                Some(HitRecord::in_medium(
+                    ray,
+                    hit_t,
+                    self.phase_function.clone(),
+                ))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+
+    fn is_medium(&self) -> bool {
+        true
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let mut inside = 0.0;
+        self.spans(ray, t_min, t_max, |start, end| {
+            inside += end - start;
+            None::<()>
+        });
+        (-self.density * inside * ray.direction.length()).exp()
+    }
+}
+
+impl Material for IsotropicMaterial {
+    fn scatter(&self, _ray: &Ray, _hit: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Sampled(Box::new(SpherePdf)))
+    }
+
+    fn eval(&self, _ray: &Ray, hit: &HitRecord, _direction: &Vec3) -> Color {
+        self.albedo.value(hit.u, hit.v, &hit.point) / (4.0 * PI)
+    }
+}
+
+impl Material for HenyeyGreensteinMaterial {
+    fn scatter(&self, ray: &Ray, _hit: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Sampled(Box::new(HenyeyGreensteinPdf::new(
+            &ray.direction,
+            self.g,
+        ))))
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color {
+        let cos_theta = dot_product(&to_unit_vector(&ray.direction), &to_unit_vector(direction));
+        self.albedo.value(hit.u, hit.v, &hit.point) * henyey_greenstein(cos_theta, self.g)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Point;
+    use crate::texture::SolidColor;
+    use crate::trace::{HittableCollection, Sphere};
+
+    fn albedo() -> Arc<dyn Texture + Send + Sync> {
+        Arc::new(SolidColor::new(Color::new(0.25, 0.5, 1.0)))
+    }
+
+    fn isotropic() -> Arc<dyn Material + Send + Sync> {
+        Arc::new(IsotropicMaterial { albedo: albedo() })
+    }
+
+    fn sphere(center: Point, radius: f64) -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(Sphere::new(&center, radius, isotropic()))
+    }
+
+    fn medium(boundary: Arc<dyn Hittable + Send + Sync>, density: f64) -> ConstantMedium {
+        ConstantMedium {
+            boundary,
+            density,
+            phase_function: isotropic(),
+        }
+    }
+
+    fn ray(origin: Point, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time: 0.0,
+        }
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn mean_free_flight_is_one_over_density() {
+        // The boundary is far enough away that no ray gets through.
+        let medium = medium(sphere(Point::new(0.0, 0.0, 0.0), 1000.0), 0.5);
+        // Distances are measured along the ray, so a faster ray gets there in less t.
+        for speed in [1.0, 4.0] {
+            const NUM_SAMPLES: usize = 100_000;
+            let r = ray(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, speed, 0.0));
+            let total: f64 = (0..NUM_SAMPLES)
+                .map(|_| {
+                    let hit = medium.hit(&r, 0.001, f64::INFINITY).unwrap();
+                    assert!(hit.is_medium_event());
+                    hit.t * speed
+                })
+                .sum();
+            let mean = total / NUM_SAMPLES as f64;
+            assert!((mean - 2.0).abs() < 0.03, "{}", mean);
+        }
+    }
+
+    #[test]
+    fn transmittance_is_exponential_in_the_distance_inside() {
+        let medium = medium(sphere(Point::new(0.0, 0.0, 0.0), 1.0), 0.7);
+        let through = ray(Point::new(-5.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0));
+        assert_close(
+            medium.transmittance(&through, 0.001, f64::INFINITY),
+            (-0.7 * 2.0f64).exp(),
+        );
+        // Stopping at the center, or starting there.
+        assert_close(medium.transmittance(&through, 0.001, 2.5), (-0.7f64).exp());
+        let from_center = ray(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_close(
+            medium.transmittance(&from_center, 0.0, f64::INFINITY),
+            (-0.7f64).exp(),
+        );
+        let past = ray(Point::new(-5.0, 2.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(medium.transmittance(&past, 0.001, f64::INFINITY), 1.0);
+
+        // The chance of a ray getting through without scattering is the transmittance.
+        const NUM_SAMPLES: usize = 100_000;
+        let num_through = (0..NUM_SAMPLES)
+            .filter(|_| medium.hit(&through, 0.001, f64::INFINITY).is_none())
+            .count();
+        let fraction = num_through as f64 / NUM_SAMPLES as f64;
+        assert!((fraction - (-1.4f64).exp()).abs() < 0.01, "{}", fraction);
+    }
+
+    #[test]
+    fn transmittance_adds_up_every_span() {
+        let mut spheres = HittableCollection::new();
+        spheres.add(sphere(Point::new(0.0, 0.0, 0.0), 1.0));
+        spheres.add(sphere(Point::new(4.0, 0.0, 0.0), 0.5));
+        let medium = medium(Arc::new(spheres), 0.25);
+        let through = ray(Point::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_close(
+            medium.transmittance(&through, 0.001, f64::INFINITY),
+            (-0.25 * 3.0f64).exp(),
+        );
+    }
+
+    #[test]
+    fn phase_functions_match_their_pdfs() {
+        let r = ray(Point::new(0.0, 0.0, 0.0), Vec3::new(1.0, 2.0, -1.0));
+        let hit = HitRecord::in_medium(&r, 1.0, isotropic());
+        let materials: Vec<Arc<dyn Material + Send + Sync>> = vec![
+            isotropic(),
+            Arc::new(HenyeyGreensteinMaterial {
+                albedo: albedo(),
+                g: 0.7,
+            }),
+            Arc::new(HenyeyGreensteinMaterial {
+                albedo: albedo(),
+                g: -0.3,
+            }),
+        ];
+        // Sampling by the pdf leaves the albedo as the weight of every direction.
+        for material in materials {
+            let pdf = match material.scatter(&r, &hit) {
+                Some(ScatterRecord::Sampled(pdf)) => pdf,
+                _ => panic!("phase functions scatter by a pdf"),
+            };
+            for _ in 0..100 {
+                let direction = pdf.generate();
+                let weight = material.eval(&r, &hit, &direction) / pdf.value(&direction);
+                assert!((weight - Color::new(0.25, 0.5, 1.0)).length() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn henyey_greenstein_favors_forward_scattering_for_positive_g() {
+        let r = ray(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = HitRecord::in_medium(&r, 1.0, isotropic());
+        let forward = HenyeyGreensteinMaterial {
+            albedo: albedo(),
+            g: 0.5,
+        };
+        let ahead = forward.eval(&r, &hit, &Vec3::new(0.0, 0.0, 1.0)).z();
+        let behind = forward.eval(&r, &hit, &Vec3::new(0.0, 0.0, -1.0)).z();
+        // (1 - g^2) / (4 pi (1 -+ g)^3)
+        assert_close(ahead, 0.75 / (4.0 * PI * 0.125));
+        assert_close(behind, 0.75 / (4.0 * PI * 3.375));
+    }
+}
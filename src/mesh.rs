@@ -0,0 +1,625 @@
+use crate::bvh::Bvh;
+use crate::math::{
+    cross_product, dot_product, is_in_range, random_float, to_unit_vector, Aabb, Color, Point, Ray,
+    Vec3,
+};
+use crate::trace::{HitRecord, Hittable, HittableCollection, Material};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+// Vertex attributes are shared by every triangle of the mesh. `normals`, `uvs` and `colors`
+// are either empty or have one entry per position.
+pub struct TriangleMesh {
+    pub positions: Vec<Point>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub colors: Vec<Color>,
+    pub indices: Vec<[usize; 3]>,
+    pub material: Arc<dyn Material + Send + Sync>,
+}
+
+pub struct Triangle {
+    mesh: Arc<TriangleMesh>,
+    index: usize,
+}
+
+pub struct Mesh {
+    pub data: Arc<TriangleMesh>,
+    bvh: Bvh,
+    area_cdf: Vec<f64>,
+}
+
+impl TriangleMesh {
+    pub fn num_triangles(&self) -> usize {
+        self.indices.len()
+    }
+}
+
+impl Triangle {
+    pub fn new(mesh: Arc<TriangleMesh>, index: usize) -> Self {
+        Triangle { mesh, index }
+    }
+
+    pub fn from_vertices(
+        p0: &Point,
+        p1: &Point,
+        p2: &Point,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        let mesh = TriangleMesh {
+            positions: vec![*p0, *p1, *p2],
+            normals: vec![],
+            uvs: vec![],
+            colors: vec![],
+            indices: vec![[0, 1, 2]],
+            material,
+        };
+        Triangle::new(Arc::new(mesh), 0)
+    }
+
+    fn vertices(&self) -> (Point, Point, Point) {
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        (
+            self.mesh.positions[i0],
+            self.mesh.positions[i1],
+            self.mesh.positions[i2],
+        )
+    }
+
+    fn area(&self) -> f64 {
+        let (p0, p1, p2) = self.vertices();
+        0.5 * cross_product(&(p1 - p0), &(p2 - p0)).length()
+    }
+
+    // Uniformly distributed over the triangle's area.
+    fn random_point(&self) -> Point {
+        let (p0, p1, p2) = self.vertices();
+        let su0 = random_float().sqrt();
+        let b0 = 1.0 - su0;
+        let b1 = random_float() * su0;
+        p0 * b0 + p1 * b1 + p2 * (1.0 - b0 - b1)
+    }
+}
+
+// Converts an area density at the point `ray` hits into a solid angle density at its origin.
+pub fn area_to_solid_angle_pdf(ray: &Ray, hit: &HitRecord, area: f64) -> f64 {
+    let distance_squared = hit.t * hit.t * ray.direction.length_squared();
+    let cosine = dot_product(&ray.direction, &hit.geometric_normal).abs() / ray.direction.length();
+    if cosine <= 0.0 {
+        return 0.0;
+    }
+    distance_squared / (cosine * area)
+}
+
+impl Hittable for Triangle {
+    // Watertight ray/triangle intersection (Woop, Benthin and Wald 2013): the vertices are moved
+    // into a space where the ray starts at the origin and points down +z, so the edge tests
+    // reduce to 2D and neighbouring triangles agree exactly on their shared edges.
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (p0, p1, p2) = self.vertices();
+
+        let d = ray.direction;
+        let kz = if d.x().abs() > d.y().abs() {
+            if d.x().abs() > d.z().abs() {
+                0
+            } else {
+                2
+            }
+        } else if d.y().abs() > d.z().abs() {
+            1
+        } else {
+            2
+        };
+        let mut kx = (kz + 1) % 3;
+        let mut ky = (kx + 1) % 3;
+        if d.e[kz] < 0.0 {
+            std::mem::swap(&mut kx, &mut ky);
+        }
+        let permute = |v: &Vec3| Vec3::new(v.e[kx], v.e[ky], v.e[kz]);
+
+        let d = permute(&d);
+        let shear_x = -d.x() / d.z();
+        let shear_y = -d.y() / d.z();
+        let shear_z = 1.0 / d.z();
+        let transform = |p: &Point| {
+            let p = permute(&(*p - ray.origin));
+            Vec3::new(p.x() + shear_x * p.z(), p.y() + shear_y * p.z(), p.z())
+        };
+        let p0t = transform(&p0);
+        let p1t = transform(&p1);
+        let p2t = transform(&p2);
+
+        let e0 = p1t.x() * p2t.y() - p1t.y() * p2t.x();
+        let e1 = p2t.x() * p0t.y() - p2t.y() * p0t.x();
+        let e2 = p0t.x() * p1t.y() - p0t.y() * p1t.x();
+        if (e0 < 0.0 || e1 < 0.0 || e2 < 0.0) && (e0 > 0.0 || e1 > 0.0 || e2 > 0.0) {
+            return None;
+        }
+        let det = e0 + e1 + e2;
+        if det == 0.0 {
+            return None;
+        }
+
+        let t_scaled = (e0 * p0t.z() + e1 * p1t.z() + e2 * p2t.z()) * shear_z;
+        let t = t_scaled / det;
+        if !is_in_range(t, t_min, t_max) {
+            return None;
+        }
+
+        let b0 = e0 / det;
+        let b1 = e1 / det;
+        let b2 = e2 / det;
+        let point = p0 * b0 + p1 * b1 + p2 * b2;
+
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let (uv0, uv1, uv2) = if self.mesh.uvs.is_empty() {
+            ((0.0, 0.0), (1.0, 0.0), (1.0, 1.0))
+        } else {
+            (self.mesh.uvs[i0], self.mesh.uvs[i1], self.mesh.uvs[i2])
+        };
+        let u = b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0;
+        let v = b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1;
+
+        let geometric_normal = to_unit_vector(&cross_product(&(p1 - p0), &(p2 - p0)));
+        let mut hit = HitRecord::from_hit(
+            &point,
+            ray,
+            t,
+            &geometric_normal,
+            u,
+            v,
+            self.mesh.material.clone(),
+        );
+
+        // Solve for the derivatives from the edges and their texture coordinate differences;
+        // degenerate texture coordinates keep the default frame.
+        let duv02 = (uv0.0 - uv2.0, uv0.1 - uv2.1);
+        let duv12 = (uv1.0 - uv2.0, uv1.1 - uv2.1);
+        let (dp02, dp12) = (p0 - p2, p1 - p2);
+        let uv_det = duv02.0 * duv12.1 - duv02.1 * duv12.0;
+        if uv_det.abs() > 1e-12 {
+            let dpdu = (dp02 * duv12.1 - dp12 * duv02.1) / uv_det;
+            let dpdv = (dp12 * duv02.0 - dp02 * duv12.0) / uv_det;
+            hit.set_tangents(&dpdu, &dpdv);
+        }
+
+        // Interpolated normals only shade; which side was hit is still decided by the geometry.
+        if !self.mesh.normals.is_empty() {
+            hit.set_shading_normal(
+                &(self.mesh.normals[i0] * b0
+                    + self.mesh.normals[i1] * b1
+                    + self.mesh.normals[i2] * b2),
+            );
+        }
+
+        if !self.mesh.colors.is_empty() {
+            hit.color =
+                self.mesh.colors[i0] * b0 + self.mesh.colors[i1] * b1 + self.mesh.colors[i2] * b2;
+        }
+
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (p0, p1, p2) = self.vertices();
+        Some(Aabb::new(p0, p0).grow(&p1).grow(&p2))
+    }
+
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f64 {
+        let ray = Ray {
+            origin: *origin,
+            direction: *direction,
+            time: 0.0,
+        };
+        match self.hit(&ray, 0.001, f64::INFINITY) {
+            Some(hit) => area_to_solid_angle_pdf(&ray, &hit, self.area()),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point) -> Vec3 {
+        self.random_point() - *origin
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.mesh.material.is_emissive()
+    }
+}
+
+impl Mesh {
+    pub fn new(data: TriangleMesh) -> Self {
+        let data = Arc::new(data);
+        let mut triangles = HittableCollection::new();
+        let mut area_cdf = Vec::with_capacity(data.num_triangles());
+        let mut total_area = 0.0;
+        for index in 0..data.num_triangles() {
+            let triangle = Triangle::new(data.clone(), index);
+            total_area += triangle.area();
+            area_cdf.push(total_area);
+            triangles.add(Arc::new(triangle));
+        }
+        Mesh {
+            data,
+            bvh: Bvh::new(triangles),
+            area_cdf,
+        }
+    }
+
+    fn total_area(&self) -> f64 {
+        self.area_cdf.last().cloned().unwrap_or(0.0)
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.bvh.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounding_box()
+    }
+
+    // Light samples are spread over the whole surface, picking triangles by area.
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f64 {
+        let ray = Ray {
+            origin: *origin,
+            direction: *direction,
+            time: 0.0,
+        };
+        match self.hit(&ray, 0.001, f64::INFINITY) {
+            Some(hit) => area_to_solid_angle_pdf(&ray, &hit, self.total_area()),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point) -> Vec3 {
+        let target = random_float() * self.total_area();
+        let index = self
+            .area_cdf
+            .partition_point(|&area| area < target)
+            .min(self.area_cdf.len() - 1);
+        Triangle::new(self.data.clone(), index).random_point() - *origin
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.data.material.is_emissive()
+    }
+}
+
+// Indices of one face corner into the file-wide position, texcoord and normal lists.
+type ObjVertex = (usize, Option<usize>, Option<usize>);
+
+struct ObjGroupBuilder {
+    faces: Vec<[ObjVertex; 3]>,
+}
+
+// Reads positions, texture coordinates, normals and faces from a Wavefront OBJ file. Each
+// group (`g` or `o`) becomes its own mesh; polygons are triangulated as fans. Materials in
+// the file are ignored and every mesh uses `material`.
+pub fn load_obj(path: &Path, material: Arc<dyn Material + Send + Sync>) -> io::Result<Vec<Mesh>> {
+    read_obj(path, BufReader::new(File::open(path)?), material)
+}
+
+// Parses OBJ text from `reader`; `path` only names the file in errors.
+fn read_obj(
+    path: &Path,
+    reader: impl BufRead,
+    material: Arc<dyn Material + Send + Sync>,
+) -> io::Result<Vec<Mesh>> {
+    let mut positions = vec![];
+    let mut uvs = vec![];
+    let mut normals = vec![];
+    let mut groups = vec![ObjGroupBuilder { faces: vec![] }];
+
+    for (line_idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let parse_error = |msg: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path.display(), line_idx + 1, msg),
+            )
+        };
+
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+        let parse_floats = |count: usize| -> io::Result<Vec<f64>> {
+            if args.len() < count {
+                return Err(parse_error(format!(
+                    "'{}' needs {} values, found {}",
+                    keyword,
+                    count,
+                    args.len()
+                )));
+            }
+            args[..count]
+                .iter()
+                .map(|arg| {
+                    arg.parse::<f64>()
+                        .map_err(|_| parse_error(format!("invalid number '{}'", arg)))
+                })
+                .collect()
+        };
+
+        match keyword {
+            "v" => {
+                let p = parse_floats(3)?;
+                positions.push(Point::new(p[0], p[1], p[2]));
+            }
+            "vt" => {
+                // v may be left out and defaults to 0; a third coordinate is ignored.
+                let t = parse_floats(args.len().clamp(1, 2))?;
+                uvs.push((t[0], t.get(1).copied().unwrap_or(0.0)));
+            }
+            "vn" => {
+                let n = parse_floats(3)?;
+                normals.push(Vec3::new(n[0], n[1], n[2]));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(parse_error(String::from(
+                        "a face needs at least 3 vertices",
+                    )));
+                }
+                let corners = args
+                    .iter()
+                    .map(|arg| {
+                        parse_obj_vertex(arg, positions.len(), uvs.len(), normals.len())
+                            .map_err(parse_error)
+                    })
+                    .collect::<io::Result<Vec<_>>>()?;
+                let group = groups.last_mut().expect("no current group");
+                for i in 1..(corners.len() - 1) {
+                    group.faces.push([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            "g" | "o" => {
+                let group = groups.last().expect("no current group");
+                if !group.faces.is_empty() {
+                    groups.push(ObjGroupBuilder { faces: vec![] });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let meshes = groups
+        .into_iter()
+        .filter(|group| !group.faces.is_empty())
+        .map(|group| {
+            Mesh::new(build_obj_mesh(
+                group,
+                &positions,
+                &uvs,
+                &normals,
+                material.clone(),
+            ))
+        })
+        .collect();
+    Ok(meshes)
+}
+
+// Parses a face corner of the form `p`, `p/t`, `p//n` or `p/t/n`. Indices are 1-based, and
+// negative indices count back from the most recently declared element.
+fn parse_obj_vertex(
+    token: &str,
+    num_positions: usize,
+    num_uvs: usize,
+    num_normals: usize,
+) -> Result<ObjVertex, String> {
+    let resolve = |field: &str, count: usize| -> Result<usize, String> {
+        let index: i64 = field
+            .parse()
+            .map_err(|_| format!("invalid index '{}' in face '{}'", field, token))?;
+        let resolved = if index < 0 {
+            count as i64 + index
+        } else {
+            index - 1
+        };
+        if resolved < 0 || resolved >= count as i64 {
+            return Err(format!("index {} out of range in face '{}'", index, token));
+        }
+        Ok(resolved as usize)
+    };
+
+    let mut fields = token.split('/');
+    let position = resolve(fields.next().unwrap_or(""), num_positions)?;
+    let uv = match fields.next() {
+        Some(field) if !field.is_empty() => Some(resolve(field, num_uvs)?),
+        _ => None,
+    };
+    let normal = match fields.next() {
+        Some(field) if !field.is_empty() => Some(resolve(field, num_normals)?),
+        _ => None,
+    };
+    Ok((position, uv, normal))
+}
+
+// OBJ indexes each attribute separately, so every distinct combination becomes one mesh
+// vertex. Normals and UVs are kept only if every corner in the group has them.
+fn build_obj_mesh(
+    group: ObjGroupBuilder,
+    positions: &[Point],
+    uvs: &[(f64, f64)],
+    normals: &[Vec3],
+    material: Arc<dyn Material + Send + Sync>,
+) -> TriangleMesh {
+    let corners = || group.faces.iter().flat_map(|face| face.iter());
+    let has_uvs = corners().all(|corner| corner.1.is_some());
+    let has_normals = corners().all(|corner| corner.2.is_some());
+
+    let mut mesh = TriangleMesh {
+        positions: vec![],
+        normals: vec![],
+        uvs: vec![],
+        colors: vec![],
+        indices: vec![],
+        material,
+    };
+    let mut vertex_indices: HashMap<ObjVertex, usize> = HashMap::new();
+    for face in group.faces.iter() {
+        let mut triangle = [0; 3];
+        for (corner, index) in face.iter().zip(triangle.iter_mut()) {
+            let key = (
+                corner.0,
+                corner.1.filter(|_| has_uvs),
+                corner.2.filter(|_| has_normals),
+            );
+            *index = *vertex_indices.entry(key).or_insert_with(|| {
+                mesh.positions.push(positions[key.0]);
+                if let Some(uv) = key.1 {
+                    mesh.uvs.push(uvs[uv]);
+                }
+                if let Some(normal) = key.2 {
+                    mesh.normals.push(normals[normal]);
+                }
+                mesh.positions.len() - 1
+            });
+        }
+        mesh.indices.push(triangle);
+    }
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::error_message;
+    use crate::trace::LambertianMaterial;
+
+    fn parse(text: &str) -> io::Result<Vec<Mesh>> {
+        let material = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+        read_obj(Path::new("test.obj"), text.as_bytes(), material)
+    }
+
+    #[test]
+    fn positions_only_triangle() {
+        let meshes = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        assert_eq!(meshes.len(), 1);
+        let mesh = &meshes[0].data;
+        assert_eq!(mesh.indices, vec![[0, 1, 2]]);
+        assert_eq!(mesh.positions[1], Point::new(1.0, 0.0, 0.0));
+        assert!(mesh.normals.is_empty());
+        assert!(mesh.uvs.is_empty());
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_latest_vertex() {
+        let text = "v 9 9 9\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\n";
+        let mesh = &parse(text).unwrap()[0].data;
+        assert_eq!(
+            mesh.positions,
+            vec![
+                Point::new(0.0, 0.0, 0.0),
+                Point::new(1.0, 0.0, 0.0),
+                Point::new(0.0, 1.0, 0.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn corners_with_uvs_and_normals() {
+        let text = "v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                    vt 0 0\nvt 1 0\nvt 0 1\n\
+                    vn 0 0 1\n\
+                    f 1/1/1 2/2/1 3/3/1\n";
+        let mesh = &parse(text).unwrap()[0].data;
+        assert_eq!(mesh.uvs, vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+        assert_eq!(mesh.normals, vec![Vec3::new(0.0, 0.0, 1.0); 3]);
+    }
+
+    #[test]
+    fn normals_without_uvs() {
+        let text = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1\n";
+        let mesh = &parse(text).unwrap()[0].data;
+        assert!(mesh.uvs.is_empty());
+        assert_eq!(mesh.normals.len(), 3);
+    }
+
+    #[test]
+    fn attributes_missing_on_some_corners_are_dropped() {
+        let text = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvn 0 0 1\nf 1/1/1 2 3//1\n";
+        let mesh = &parse(text).unwrap()[0].data;
+        assert!(mesh.uvs.is_empty());
+        assert!(mesh.normals.is_empty());
+        assert_eq!(mesh.positions.len(), 3);
+    }
+
+    #[test]
+    fn polygons_are_triangulated_as_fans() {
+        let text = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv -1 1 0\nf 1 2 3 4 5\n";
+        let mesh = &parse(text).unwrap()[0].data;
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+    }
+
+    #[test]
+    fn shared_corners_share_vertices() {
+        let text = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3\nf 1 3 4\n";
+        let mesh = &parse(text).unwrap()[0].data;
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn groups_become_separate_meshes() {
+        let text = "v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                    g first\nf 1 2 3\n\
+                    g empty\n\
+                    o second\nusemtl red\nf 3 2 1\nf 1 2 3\n";
+        let meshes = parse(text).unwrap();
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0].data.num_triangles(), 1);
+        assert_eq!(meshes[1].data.num_triangles(), 2);
+    }
+
+    #[test]
+    fn errors_report_the_line() {
+        let message = error_message(parse("v 0 0 0\n\n# comment\nv 1 x 0\n"));
+        assert!(message.starts_with("test.obj:4: "), "{}", message);
+        assert!(message.contains("invalid number 'x'"), "{}", message);
+    }
+
+    #[test]
+    fn out_of_range_indices_are_errors() {
+        let message = error_message(parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n"));
+        assert!(message.starts_with("test.obj:4: "), "{}", message);
+        assert!(message.contains("index 4 out of range"), "{}", message);
+
+        let message = error_message(parse("v 0 0 0\nv 1 0 0\nf -3 1 2\n"));
+        assert!(message.contains("index -3 out of range"), "{}", message);
+
+        let message = error_message(parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1/1 2/1 3/1\n"));
+        assert!(
+            message.contains("index 1 out of range in face '1/1'"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn short_faces_and_vertices_are_errors() {
+        let message = error_message(parse("v 0 0 0\nv 1 0 0\nf 1 2\n"));
+        assert!(message.starts_with("test.obj:3: "), "{}", message);
+        assert!(message.contains("at least 3 vertices"), "{}", message);
+
+        let message = error_message(parse("vn 0 0\n"));
+        assert!(
+            message.contains("'vn' needs 3 values, found 2"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn texture_coordinates_default_v_to_zero() {
+        let text = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.5\nvt 1 0.25\nvt 0 1 0\nf 1/1 2/2 3/3\n";
+        let mesh = &parse(text).unwrap()[0].data;
+        assert_eq!(mesh.uvs, vec![(0.5, 0.0), (1.0, 0.25), (0.0, 1.0)]);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::math::{dot_product, to_unit_vector, Color, Ray, Vec3};
+use crate::pdf::{ggx_distribution, ggx_smith_g1, CosinePdf, GgxPdf, MixturePdf};
+use crate::texture::Texture;
+use crate::trace::{HitRecord, Material, ScatterRecord, BLACK, WHITE};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// glTF's metallic-roughness material: a Lambertian base under a GGX specular layer that
+// reflects 4% at normal incidence on dielectrics and the base color on metals, with `metallic`
+// blending between the two. Each texture multiplies the factor beside it, and the
+// metallic-roughness texture holds roughness in its green channel and metalness in its blue.
+pub struct PbrMaterial {
+    pub base_color: Color,
+    pub base_color_texture: Arc<dyn Texture + Send + Sync>,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub metallic_roughness_texture: Arc<dyn Texture + Send + Sync>,
+    pub emissive: Color,
+    pub emissive_texture: Arc<dyn Texture + Send + Sync>,
+}
+
+// The material's parameters at one hit.
+struct Surface {
+    base_color: Color,
+    metallic: f64,
+    alpha: f64,
+}
+
+impl PbrMaterial {
+    const MIN_ALPHA: f64 = 1e-3;
+
+    fn surface(&self, hit: &HitRecord) -> Surface {
+        let metallic_roughness = self
+            .metallic_roughness_texture
+            .value(hit.u, hit.v, &hit.point);
+        let roughness = (self.roughness * metallic_roughness.y()).clamp(0.0, 1.0);
+        Surface {
+            base_color: hit.albedo(self.base_color_texture.as_ref()) * self.base_color,
+            metallic: (self.metallic * metallic_roughness.z()).clamp(0.0, 1.0),
+            alpha: (roughness * roughness).max(PbrMaterial::MIN_ALPHA),
+        }
+    }
+}
+
+impl Surface {
+    fn specular_color(&self) -> Color {
+        Color::new(0.04, 0.04, 0.04) * (1.0 - self.metallic) + self.base_color * self.metallic
+    }
+}
+
+fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+impl Material for PbrMaterial {
+    // Picks between the two lobes by how much each reflects head on, keeping some specular
+    // samples for the highlights Fresnel brings out at grazing angles.
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
+        let surface = self.surface(hit);
+        let specular = luminance(&surface.specular_color());
+        let diffuse = luminance(&surface.base_color) * (1.0 - surface.metallic) * (1.0 - specular);
+        let weight = if specular + diffuse > 0.0 {
+            (specular / (specular + diffuse)).clamp(0.25, 1.0)
+        } else {
+            1.0
+        };
+        Some(ScatterRecord::Sampled(Box::new(MixturePdf::new(
+            Box::new(GgxPdf::new(&hit.normal, &(-ray.direction), surface.alpha)),
+            Box::new(CosinePdf::new(&hit.normal)),
+            weight,
+        ))))
+    }
+
+    // The diffuse base only receives the light the specular layer lets through, taken as what
+    // a smooth layer would transmit towards the viewer so the two never sum to more than one.
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color {
+        let outgoing = -to_unit_vector(&ray.direction);
+        let incoming = to_unit_vector(direction);
+        let n_dot_o = dot_product(&hit.normal, &outgoing);
+        let n_dot_i = dot_product(&hit.normal, &incoming);
+        if n_dot_o <= 0.0 || n_dot_i <= 0.0 {
+            return BLACK;
+        }
+
+        let surface = self.surface(hit);
+        let half = to_unit_vector(&(incoming + outgoing));
+        let n_dot_h = dot_product(&hit.normal, &half);
+        let o_dot_h = dot_product(&outgoing, &half).max(0.0);
+        let f0 = surface.specular_color();
+        let schlick = |cosine: f64| f0 + (WHITE - f0) * (1.0 - cosine).powi(5);
+        let d = ggx_distribution(n_dot_h, surface.alpha);
+        let g = ggx_smith_g1(n_dot_o, surface.alpha) * ggx_smith_g1(n_dot_i, surface.alpha);
+        let specular = schlick(o_dot_h) * (d * g / (4.0 * n_dot_o));
+        let diffuse = (WHITE - schlick(n_dot_o))
+            * surface.base_color
+            * ((1.0 - surface.metallic) * n_dot_i / PI);
+        specular + diffuse
+    }
+
+    fn emitted(&self, _ray: &Ray, hit: &HitRecord) -> Color {
+        if hit.front_face {
+            self.emissive_texture.value(hit.u, hit.v, &hit.point) * self.emissive
+        } else {
+            BLACK
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.emissive.x() > 0.0 || self.emissive.y() > 0.0 || self.emissive.z() > 0.0
+    }
+}
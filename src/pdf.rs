@@ -0,0 +1,328 @@
+use crate::math::{dot_product, random_float, to_unit_vector, Onb, Point, Vec3};
+use crate::trace::Hittable;
+use std::f64::consts::PI;
+
+// A distribution over directions. `value` is the solid angle density of `generate` producing
+// `direction`; directions need not be normalized.
+pub trait Pdf {
+    fn value(&self, direction: &Vec3) -> f64;
+
+    fn generate(&self) -> Vec3;
+}
+
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+// Samples the GGX distribution of microfacet normals around `uvw.w` and reflects `outgoing`
+// about them.
+pub struct GgxPdf {
+    uvw: Onb,
+    outgoing: Vec3,
+    alpha: f64,
+}
+
+// Uniform over all directions.
+pub struct SpherePdf;
+
+// Samples the Henyey-Greenstein phase function around the direction of travel `uvw.w`.
+pub struct HenyeyGreensteinPdf {
+    uvw: Onb,
+    g: f64,
+}
+
+pub struct HittablePdf<'a> {
+    origin: Point,
+    hittable: &'a dyn Hittable,
+}
+
+// Picks `first` with probability `weight` and `second` otherwise.
+pub struct MixturePdf {
+    first: Box<dyn Pdf>,
+    second: Box<dyn Pdf>,
+    weight: f64,
+}
+
+impl CosinePdf {
+    pub fn new(normal: &Vec3) -> Self {
+        CosinePdf {
+            uvw: Onb::from_w(normal),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cosine = dot_product(&to_unit_vector(direction), &self.uvw.w);
+        if cosine <= 0.0 {
+            0.0
+        } else {
+            cosine / PI
+        }
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.uvw.local(&random_cosine_direction())
+    }
+}
+
+impl GgxPdf {
+    // `outgoing` points away from the surface, back along the incoming ray.
+    pub fn new(normal: &Vec3, outgoing: &Vec3, alpha: f64) -> Self {
+        GgxPdf {
+            uvw: Onb::from_w(normal),
+            outgoing: to_unit_vector(outgoing),
+            alpha,
+        }
+    }
+}
+
+impl Pdf for GgxPdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let incoming = to_unit_vector(direction);
+        if dot_product(&incoming, &self.uvw.w) <= 0.0 {
+            return 0.0;
+        }
+        let half = to_unit_vector(&(incoming + self.outgoing));
+        let n_dot_h = dot_product(&half, &self.uvw.w);
+        let o_dot_h = dot_product(&self.outgoing, &half).abs();
+        if n_dot_h <= 0.0 || o_dot_h <= 0.0 {
+            return 0.0;
+        }
+        ggx_distribution(n_dot_h, self.alpha) * n_dot_h / (4.0 * o_dot_h)
+    }
+
+    fn generate(&self) -> Vec3 {
+        let r1 = random_float();
+        let r2 = random_float();
+        let phi = 2.0 * PI * r1;
+        let tan_theta_squared = self.alpha * self.alpha * r2 / (1.0 - r2);
+        let cos_theta = 1.0 / (1.0 + tan_theta_squared).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let half = self.uvw.local(&Vec3::new(
+            phi.cos() * sin_theta,
+            phi.sin() * sin_theta,
+            cos_theta,
+        ));
+        half * 2.0 * dot_product(&self.outgoing, &half) - self.outgoing
+    }
+}
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self) -> Vec3 {
+        let z = 1.0 - 2.0 * random_float();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * random_float();
+        Vec3::new(phi.cos() * r, phi.sin() * r, z)
+    }
+}
+
+impl HenyeyGreensteinPdf {
+    pub fn new(forward: &Vec3, g: f64) -> Self {
+        HenyeyGreensteinPdf {
+            uvw: Onb::from_w(forward),
+            g,
+        }
+    }
+}
+
+impl Pdf for HenyeyGreensteinPdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        henyey_greenstein(dot_product(&to_unit_vector(direction), &self.uvw.w), self.g)
+    }
+
+    fn generate(&self) -> Vec3 {
+        let g = self.g;
+        let r = random_float();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * r
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * r);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random_float();
+        self.uvw.local(&Vec3::new(
+            phi.cos() * sin_theta,
+            phi.sin() * sin_theta,
+            cos_theta,
+        ))
+    }
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(origin: &Point, hittable: &'a dyn Hittable) -> Self {
+        HittablePdf {
+            origin: *origin,
+            hittable,
+        }
+    }
+}
+
+impl<'a> Pdf for HittablePdf<'a> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.hittable.pdf_value(&self.origin, direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.hittable.random(&self.origin)
+    }
+}
+
+impl MixturePdf {
+    pub fn new(first: Box<dyn Pdf>, second: Box<dyn Pdf>, weight: f64) -> Self {
+        MixturePdf {
+            first,
+            second,
+            weight,
+        }
+    }
+}
+
+impl Pdf for MixturePdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.weight * self.first.value(direction)
+            + (1.0 - self.weight) * self.second.value(direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        if random_float() < self.weight {
+            self.first.generate()
+        } else {
+            self.second.generate()
+        }
+    }
+}
+
+// Cosine-weighted direction on the hemisphere around +z.
+pub fn random_cosine_direction() -> Vec3 {
+    let r1 = random_float();
+    let r2 = random_float();
+    let phi = 2.0 * PI * r1;
+    let z = (1.0 - r2).sqrt();
+    let r = r2.sqrt();
+    Vec3::new(phi.cos() * r, phi.sin() * r, z)
+}
+
+// Density of scattering by an angle with cosine `cos_theta` from the direction of travel.
+// Positive `g` favors forward scattering, negative `g` back scattering.
+pub fn henyey_greenstein(cos_theta: f64, g: f64) -> f64 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
+
+pub fn ggx_distribution(n_dot_h: f64, alpha: f64) -> f64 {
+    let alpha_squared = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha_squared - 1.0) + 1.0;
+    alpha_squared / (PI * d * d)
+}
+
+// Smith masking for one direction; the full term is the product for incoming and outgoing.
+pub fn ggx_smith_g1(n_dot_v: f64, alpha: f64) -> f64 {
+    let alpha_squared = alpha * alpha;
+    2.0 * n_dot_v / (n_dot_v + (alpha_squared + (1.0 - alpha_squared) * n_dot_v * n_dot_v).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Color;
+    use crate::trace::{LambertianMaterial, Sphere};
+    use std::sync::Arc;
+
+    const NUM_SAMPLES: usize = 500_000;
+    const NUM_BANDS: usize = 8;
+
+    fn band(direction: &Vec3) -> usize {
+        let z = to_unit_vector(direction).z();
+        ((0.5 * (z + 1.0) * NUM_BANDS as f64) as usize).min(NUM_BANDS - 1)
+    }
+
+    // Splits the sphere into bands of equal z, which have equal solid angle, and compares the
+    // integral of `value` over each band, estimated from uniform directions, with the share of
+    // generated directions landing there. Directions `value` gives no density, like a
+    // reflection under the surface, are not counted. Returns the total integral.
+    fn check_bands(pdf: &dyn Pdf) -> f64 {
+        let mut integrals = [0.0; NUM_BANDS];
+        let mut shares = [0.0; NUM_BANDS];
+        for _ in 0..NUM_SAMPLES {
+            let direction = SpherePdf.generate();
+            integrals[band(&direction)] += 4.0 * PI * pdf.value(&direction) / NUM_SAMPLES as f64;
+            let generated = pdf.generate();
+            if pdf.value(&generated) > 0.0 {
+                shares[band(&generated)] += 1.0 / NUM_SAMPLES as f64;
+            }
+        }
+        for (integral, share) in integrals.iter().zip(shares.iter()) {
+            assert!(
+                (integral - share).abs() < 0.02,
+                "integrals {:?}, shares {:?}",
+                integrals,
+                shares
+            );
+        }
+        integrals.iter().sum()
+    }
+
+    fn assert_normalized(pdf: &dyn Pdf) {
+        let integral = check_bands(pdf);
+        assert!((integral - 1.0).abs() < 0.02, "{}", integral);
+    }
+
+    #[test]
+    fn cosine_pdf() {
+        let pdf = CosinePdf::new(&Vec3::new(0.0, 0.0, 2.0));
+        assert_normalized(&pdf);
+        assert_eq!(pdf.value(&Vec3::new(1.0, 0.0, -0.1)), 0.0);
+        assert!((pdf.value(&Vec3::new(0.0, 0.0, 3.0)) - 1.0 / PI).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sphere_pdf() {
+        assert_normalized(&SpherePdf);
+    }
+
+    #[test]
+    fn ggx_pdf() {
+        // Reflections under the surface are lost, so less than all of the density is left.
+        for (outgoing, alpha) in [
+            (Vec3::new(0.0, 0.0, 1.0), 0.3),
+            (Vec3::new(1.0, 0.0, 1.0), 0.5),
+            (Vec3::new(1.0, 0.0, 0.2), 0.8),
+        ] {
+            let integral = check_bands(&GgxPdf::new(&Vec3::new(0.0, 0.0, 1.0), &outgoing, alpha));
+            assert!(integral > 0.5 && integral < 1.02, "{}", integral);
+        }
+    }
+
+    #[test]
+    fn henyey_greenstein_pdf() {
+        for g in [-0.4, 0.0, 0.6] {
+            assert_normalized(&HenyeyGreensteinPdf::new(&Vec3::new(0.0, 0.0, 1.0), g));
+        }
+    }
+
+    #[test]
+    fn mixture_pdf() {
+        let pdf = MixturePdf::new(
+            Box::new(CosinePdf::new(&Vec3::new(0.0, 0.0, -1.0))),
+            Box::new(SpherePdf),
+            0.3,
+        );
+        assert_normalized(&pdf);
+        let below = Vec3::new(0.0, 0.0, -1.0);
+        assert!((pdf.value(&below) - (0.3 / PI + 0.7 / (4.0 * PI))).abs() < 1e-12);
+    }
+
+    #[test]
+    fn hittable_pdf() {
+        let material = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Sphere::new(&Point::new(0.3, 0.0, 1.2), 1.0, material);
+        let pdf = HittablePdf::new(&Point::new(0.0, 0.0, 0.0), &sphere);
+        assert_normalized(&pdf);
+    }
+}
@@ -0,0 +1,601 @@
+use crate::math::{
+    cross_product, dot_product, is_in_range, random_float, to_unit_vector, Aabb, Onb, Point, Ray,
+    Vec3,
+};
+use crate::mesh::area_to_solid_angle_pdf;
+use crate::trace::{HitRecord, Hittable, Material};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Unbounded plane through `point`. Texture coordinates are distances along two axes in the
+// plane, so textures repeat once per unit.
+pub struct Plane {
+    pub point: Point,
+    frame: Onb,
+    pub material: Arc<dyn Material + Send + Sync>,
+}
+
+// Parallelogram with one corner at `corner` and edges `u` and `v`, facing along u x v. Texture
+// coordinates run from 0 to 1 along each edge.
+pub struct Quad {
+    pub corner: Point,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Arc<dyn Material + Send + Sync>,
+    normal: Vec3,
+    // Maps a point in the plane to its coordinates along `u` and `v`.
+    w: Vec3,
+    area: f64,
+}
+
+// Texture coordinates are the angle around the center in turns and the distance from it in
+// radii.
+pub struct Disk {
+    pub center: Point,
+    pub radius: f64,
+    frame: Onb,
+    pub material: Arc<dyn Material + Send + Sync>,
+}
+
+// Axis-aligned box made of six outward facing quads.
+pub struct Cuboid {
+    faces: Vec<Quad>,
+    bbox: Aabb,
+    area: f64,
+}
+
+// Where `ray` crosses the plane through `point` with unit `normal`.
+fn hit_plane(ray: &Ray, point: &Point, normal: &Vec3, t_min: f64, t_max: f64) -> Option<f64> {
+    let denominator = dot_product(normal, &ray.direction);
+    if denominator.abs() < 1e-12 {
+        return None;
+    }
+    let t = dot_product(normal, &(*point - ray.origin)) / denominator;
+    if is_in_range(t, t_min, t_max) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+impl Plane {
+    pub fn new(point: &Point, normal: &Vec3, material: Arc<dyn Material + Send + Sync>) -> Self {
+        Plane {
+            point: *point,
+            frame: Onb::from_w(normal),
+            material,
+        }
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let t = hit_plane(ray, &self.point, &self.frame.w, t_min, t_max)?;
+        let point = ray.at(t);
+        let offset = point - self.point;
+        let mut hit = HitRecord::from_hit(
+            &point,
+            ray,
+            t,
+            &self.frame.w,
+            dot_product(&offset, &self.frame.u),
+            dot_product(&offset, &self.frame.v),
+            self.material.clone(),
+        );
+        hit.set_tangents(&self.frame.u, &self.frame.v);
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+impl Quad {
+    pub fn new(
+        corner: &Point,
+        u: &Vec3,
+        v: &Vec3,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        let n = cross_product(u, v);
+        Quad {
+            corner: *corner,
+            u: *u,
+            v: *v,
+            material,
+            normal: to_unit_vector(&n),
+            w: n / n.length_squared(),
+            area: n.length(),
+        }
+    }
+
+    fn random_point(&self) -> Point {
+        self.corner + self.u * random_float() + self.v * random_float()
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let t = hit_plane(ray, &self.corner, &self.normal, t_min, t_max)?;
+        let point = ray.at(t);
+        let offset = point - self.corner;
+        let alpha = dot_product(&self.w, &cross_product(&offset, &self.v));
+        let beta = dot_product(&self.w, &cross_product(&self.u, &offset));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        let mut hit = HitRecord::from_hit(
+            &point,
+            ray,
+            t,
+            &self.normal,
+            alpha,
+            beta,
+            self.material.clone(),
+        );
+        hit.set_tangents(&self.u, &self.v);
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let corner = self.corner;
+        Some(
+            Aabb::new(corner, corner)
+                .grow(&(corner + self.u))
+                .grow(&(corner + self.v))
+                .grow(&(corner + self.u + self.v)),
+        )
+    }
+
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f64 {
+        let ray = Ray {
+            origin: *origin,
+            direction: *direction,
+            time: 0.0,
+        };
+        match self.hit(&ray, 0.001, f64::INFINITY) {
+            Some(hit) => area_to_solid_angle_pdf(&ray, &hit, self.area),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point) -> Vec3 {
+        self.random_point() - *origin
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+}
+
+impl Disk {
+    pub fn new(
+        center: &Point,
+        normal: &Vec3,
+        radius: f64,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        Disk {
+            center: *center,
+            radius,
+            frame: Onb::from_w(normal),
+            material,
+        }
+    }
+
+    fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let t = hit_plane(ray, &self.center, &self.frame.w, t_min, t_max)?;
+        let point = ray.at(t);
+        let offset = point - self.center;
+        let distance_squared = offset.length_squared();
+        if distance_squared > self.radius * self.radius {
+            return None;
+        }
+        let (x, y) = (
+            dot_product(&offset, &self.frame.u),
+            dot_product(&offset, &self.frame.v),
+        );
+        let distance = distance_squared.sqrt();
+        let phi = y.atan2(x).rem_euclid(2.0 * PI);
+        let mut hit = HitRecord::from_hit(
+            &point,
+            ray,
+            t,
+            &self.frame.w,
+            phi / (2.0 * PI),
+            distance / self.radius,
+            self.material.clone(),
+        );
+        // The angle is undefined at the center, so it keeps the default frame.
+        if distance > 1e-9 * self.radius {
+            let dpdu = (self.frame.v * x - self.frame.u * y) * (2.0 * PI);
+            let dpdv = (self.frame.u * x + self.frame.v * y) * (self.radius / distance);
+            hit.set_tangents(&dpdu, &dpdv);
+        }
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // The extent along each axis is the radius times the sine of the axis' angle to the
+        // normal.
+        let n = self.frame.w;
+        let extent = Vec3::new(
+            self.radius * (1.0 - n.x() * n.x()).max(0.0).sqrt(),
+            self.radius * (1.0 - n.y() * n.y()).max(0.0).sqrt(),
+            self.radius * (1.0 - n.z() * n.z()).max(0.0).sqrt(),
+        );
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f64 {
+        let ray = Ray {
+            origin: *origin,
+            direction: *direction,
+            time: 0.0,
+        };
+        match self.hit(&ray, 0.001, f64::INFINITY) {
+            Some(hit) => area_to_solid_angle_pdf(&ray, &hit, self.area()),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point) -> Vec3 {
+        let r = self.radius * random_float().sqrt();
+        let phi = 2.0 * PI * random_float();
+        self.center
+            + self
+                .frame
+                .local(&Vec3::new(r * phi.cos(), r * phi.sin(), 0.0))
+            - *origin
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+}
+
+impl Cuboid {
+    pub fn new(a: &Point, b: &Point, material: Arc<dyn Material + Send + Sync>) -> Self {
+        let min = Point::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+        let max = Point::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+        let dx = Vec3::new(max.x() - min.x(), 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y() - min.y(), 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z() - min.z());
+
+        let faces = vec![
+            // +z, +x, -z, -x, +y, -y
+            Quad::new(
+                &Point::new(min.x(), min.y(), max.z()),
+                &dx,
+                &dy,
+                material.clone(),
+            ),
+            Quad::new(
+                &Point::new(max.x(), min.y(), max.z()),
+                &-dz,
+                &dy,
+                material.clone(),
+            ),
+            Quad::new(
+                &Point::new(max.x(), min.y(), min.z()),
+                &-dx,
+                &dy,
+                material.clone(),
+            ),
+            Quad::new(&min, &dz, &dy, material.clone()),
+            Quad::new(
+                &Point::new(min.x(), max.y(), max.z()),
+                &dx,
+                &-dz,
+                material.clone(),
+            ),
+            Quad::new(&min, &dx, &dz, material),
+        ];
+        let area = faces.iter().map(|face| face.area).sum();
+        Cuboid {
+            faces,
+            bbox: Aabb::new(min, max),
+            area,
+        }
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut closest_hit = None;
+        let mut closest_t = t_max;
+        for face in &self.faces {
+            if let Some(hit) = face.hit(ray, t_min, closest_t) {
+                closest_t = hit.t;
+                closest_hit = Some(hit);
+            }
+        }
+        closest_hit
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    // Faces are picked in proportion to their area, so samples are uniform over the surface.
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f64 {
+        self.faces
+            .iter()
+            .map(|face| face.area / self.area * face.pdf_value(origin, direction))
+            .sum()
+    }
+
+    fn random(&self, origin: &Point) -> Vec3 {
+        let mut target = random_float() * self.area;
+        for face in &self.faces {
+            if target < face.area {
+                return face.random(origin);
+            }
+            target -= face.area;
+        }
+        self.faces[self.faces.len() - 1].random(origin)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.faces[0].is_emissive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Color;
+    use crate::pdf::{Pdf, SpherePdf};
+    use crate::trace::LambertianMaterial;
+
+    fn material() -> Arc<dyn Material + Send + Sync> {
+        Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn ray(origin: Point, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time: 0.0,
+        }
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    fn assert_vec_close(a: &Vec3, b: &Vec3) {
+        assert!((*a - *b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    // `pdf_value` must integrate to 1 over the sphere of directions from `origin`. Each
+    // direction `random` picks also stands for 1 / pdf of solid angle, so their average is the
+    // solid angle the shape covers, which uniform directions measure independently.
+    fn check_pdf(hittable: &dyn Hittable, origin: &Point) {
+        const NUM_SAMPLES: usize = 200_000;
+        let mut integral = 0.0;
+        let mut num_uniform_hits = 0;
+        let mut sampled_solid_angle = 0.0;
+        for _ in 0..NUM_SAMPLES {
+            let uniform = SpherePdf.generate();
+            integral += hittable.pdf_value(origin, &uniform);
+            if hittable
+                .hit(&ray(*origin, uniform), 0.001, f64::INFINITY)
+                .is_some()
+            {
+                num_uniform_hits += 1;
+            }
+            sampled_solid_angle += 1.0 / hittable.pdf_value(origin, &hittable.random(origin));
+        }
+        let integral = 4.0 * PI * integral / NUM_SAMPLES as f64;
+        assert!((integral - 1.0).abs() < 0.05, "integral {}", integral);
+        let solid_angle = 4.0 * PI * num_uniform_hits as f64 / NUM_SAMPLES as f64;
+        let sampled_solid_angle = sampled_solid_angle / NUM_SAMPLES as f64;
+        assert!(
+            (sampled_solid_angle - solid_angle).abs() < 0.05 * solid_angle,
+            "{} != {}",
+            sampled_solid_angle,
+            solid_angle
+        );
+    }
+
+    #[test]
+    fn plane_hits() {
+        let plane = Plane::new(
+            &Point::new(0.0, 0.0, 1.0),
+            &Vec3::new(0.0, 0.0, 2.0),
+            material(),
+        );
+        let hit = plane
+            .hit(
+                &ray(Point::new(3.0, 4.0, 5.0), Vec3::new(0.0, 0.0, -2.0)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert_close(hit.t, 2.0);
+        assert_vec_close(&hit.point, &Point::new(3.0, 4.0, 1.0));
+        assert_vec_close(&hit.normal, &Vec3::new(0.0, 0.0, 1.0));
+        assert!(hit.front_face);
+        // Texture coordinates are distances in the plane.
+        assert_close(hit.u * hit.u + hit.v * hit.v, 25.0);
+        // From below, the normal faces the ray.
+        let hit = plane
+            .hit(
+                &ray(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 1.0)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert_vec_close(&hit.normal, &Vec3::new(0.0, 0.0, -1.0));
+        assert!(!hit.front_face);
+        assert!(plane
+            .hit(
+                &ray(Point::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+                0.001,
+                f64::INFINITY
+            )
+            .is_none());
+        assert!(plane.bounding_box().is_none());
+    }
+
+    #[test]
+    fn quad_hits() {
+        let quad = Quad::new(
+            &Point::new(1.0, 1.0, 0.0),
+            &Vec3::new(2.0, 0.0, 0.0),
+            &Vec3::new(1.0, 4.0, 0.0),
+            material(),
+        );
+        let hit = quad
+            .hit(
+                &ray(Point::new(2.5, 3.0, 2.0), Vec3::new(0.0, 0.0, -1.0)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert_close(hit.t, 2.0);
+        assert_vec_close(&hit.normal, &Vec3::new(0.0, 0.0, 1.0));
+        assert!(hit.front_face);
+        // 2.5 = 1 + 2u + v and 3 = 1 + 4v.
+        assert_close(hit.u, 0.5);
+        assert_close(hit.v, 0.5);
+        assert_vec_close(&hit.dpdu, &Vec3::new(2.0, 0.0, 0.0));
+        // Inside the bounding box, outside the parallelogram.
+        assert!(quad
+            .hit(
+                &ray(Point::new(1.2, 4.5, 2.0), Vec3::new(0.0, 0.0, -1.0)),
+                0.001,
+                f64::INFINITY
+            )
+            .is_none());
+        let bbox = quad.bounding_box().unwrap();
+        assert_vec_close(&bbox.min, &Point::new(1.0, 1.0, 0.0));
+        assert_vec_close(&bbox.max, &Point::new(4.0, 5.0, 0.0));
+        assert_close(quad.area, 8.0);
+    }
+
+    #[test]
+    fn disk_hits() {
+        let disk = Disk::new(
+            &Point::new(0.0, 0.0, 0.0),
+            &Vec3::new(0.0, 1.0, 0.0),
+            2.0,
+            material(),
+        );
+        let hit = disk
+            .hit(
+                &ray(Point::new(1.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+                0.001,
+                f64::INFINITY,
+            )
+            .unwrap();
+        assert_close(hit.t, 3.0);
+        assert_vec_close(&hit.normal, &Vec3::new(0.0, 1.0, 0.0));
+        assert_close(hit.v, 0.5);
+        assert!(disk
+            .hit(
+                &ray(Point::new(1.5, 3.0, 1.5), Vec3::new(0.0, -1.0, 0.0)),
+                0.001,
+                f64::INFINITY
+            )
+            .is_none());
+        let bbox = disk.bounding_box().unwrap();
+        assert_vec_close(&bbox.min, &Point::new(-2.0, 0.0, -2.0));
+        assert_vec_close(&bbox.max, &Point::new(2.0, 0.0, 2.0));
+    }
+
+    #[test]
+    fn cuboid_faces_point_outwards() {
+        let cuboid = Cuboid::new(
+            &Point::new(1.0, 2.0, 3.0),
+            &Point::new(-1.0, -2.0, -3.0),
+            material(),
+        );
+        let extent = Vec3::new(1.0, 2.0, 3.0);
+        for axis in 0..3 {
+            for sign in [-1.0, 1.0] {
+                let mut outward = Vec3::new(0.0, 0.0, 0.0);
+                outward.e[axis] = sign;
+                let from_outside = ray(outward * 10.0, -outward);
+                let hit = cuboid.hit(&from_outside, 0.001, f64::INFINITY).unwrap();
+                assert_close(hit.t, 10.0 - extent.e[axis]);
+                assert_vec_close(&hit.normal, &outward);
+                assert!(hit.front_face);
+                // Leaving from the center, the ray meets the back of the same face.
+                let from_inside = ray(Point::new(0.0, 0.0, 0.0), outward);
+                let hit = cuboid.hit(&from_inside, 0.001, f64::INFINITY).unwrap();
+                assert_close(hit.t, extent.e[axis]);
+                assert_vec_close(&hit.normal, &-outward);
+                assert!(!hit.front_face);
+            }
+        }
+        assert_close(cuboid.area, 2.0 * (2.0 * 4.0 + 2.0 * 6.0 + 4.0 * 6.0));
+    }
+
+    #[test]
+    fn cuboid_hits_match_its_box() {
+        let cuboid = Cuboid::new(
+            &Point::new(-1.0, 0.0, 2.0),
+            &Point::new(0.5, 3.0, 2.5),
+            material(),
+        );
+        let bbox = cuboid.bounding_box().unwrap();
+        for _ in 0..1000 {
+            let r = ray(
+                Vec3::random_in_range(-5.0, 5.0),
+                Vec3::random_in_range(-1.0, 1.0),
+            );
+            // Rays starting inside leave through the far side.
+            let expected = bbox
+                .clip(&r, f64::NEG_INFINITY, f64::INFINITY)
+                .and_then(|(t0, t1)| [t0, t1].iter().copied().find(|&t| t > 0.001));
+            let actual = cuboid.hit(&r, 0.001, f64::INFINITY).map(|hit| hit.t);
+            match (actual, expected) {
+                (Some(actual), Some(expected)) => assert!((actual - expected).abs() < 1e-9),
+                (None, None) => {}
+                _ => panic!("cuboid {:?} and box {:?} disagree", actual, expected),
+            }
+        }
+    }
+
+    #[test]
+    fn quad_pdf() {
+        let quad = Quad::new(
+            &Point::new(-1.0, 2.0, -1.0),
+            &Vec3::new(2.0, 0.0, 0.0),
+            &Vec3::new(0.5, 0.5, 1.5),
+            material(),
+        );
+        check_pdf(&quad, &Point::new(0.0, 1.5, 0.0));
+    }
+
+    #[test]
+    fn disk_pdf() {
+        let disk = Disk::new(
+            &Point::new(0.5, 1.5, 0.0),
+            &Vec3::new(1.0, -1.0, 0.5),
+            1.0,
+            material(),
+        );
+        check_pdf(&disk, &Point::new(0.0, 0.8, 0.0));
+    }
+
+    #[test]
+    fn cuboid_pdf() {
+        // Long and thin, so faces differ a lot in area.
+        let cuboid = Cuboid::new(
+            &Point::new(-2.0, 0.5, -0.5),
+            &Point::new(2.0, 1.5, 0.5),
+            material(),
+        );
+        check_pdf(&cuboid, &Point::new(2.4, 0.3, 0.2));
+    }
+}
@@ -0,0 +1,685 @@
+use crate::math::{Color, Point, Vec3};
+use crate::mesh::{Mesh, TriangleMesh};
+use crate::tonemap::srgb_decode;
+use crate::trace::Material;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Copy, Clone)]
+enum ScalarType {
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Float32,
+    Float64,
+}
+
+enum PropertyKind {
+    Scalar(ScalarType),
+    List { count: ScalarType, item: ScalarType },
+}
+
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+}
+
+// Where each vertex attribute is found among the vertex element's properties.
+struct VertexLayout {
+    position: [usize; 3],
+    normal: Option<[usize; 3]>,
+    uv: Option<[usize; 2]>,
+    // The color properties and the value that stands for full intensity.
+    color: Option<([usize; 3], f64)>,
+}
+
+// Walks the data after the header, one value at a time.
+struct BodyReader<'a> {
+    format: Format,
+    data: &'a [u8],
+    position: usize,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "char" | "int8" => Some(ScalarType::Int8),
+            "uchar" | "uint8" => Some(ScalarType::Uint8),
+            "short" | "int16" => Some(ScalarType::Int16),
+            "ushort" | "uint16" => Some(ScalarType::Uint16),
+            "int" | "int32" => Some(ScalarType::Int32),
+            "uint" | "uint32" => Some(ScalarType::Uint32),
+            "float" | "float32" => Some(ScalarType::Float32),
+            "double" | "float64" => Some(ScalarType::Float64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::Uint8 => 1,
+            ScalarType::Int16 | ScalarType::Uint16 => 2,
+            ScalarType::Int32 | ScalarType::Uint32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8,
+        }
+    }
+
+    // Integer colors run up to the type's largest value; float colors up to 1.
+    fn full_intensity(self) -> f64 {
+        match self {
+            ScalarType::Int8 => i8::MAX as f64,
+            ScalarType::Uint8 => u8::MAX as f64,
+            ScalarType::Int16 => i16::MAX as f64,
+            ScalarType::Uint16 => u16::MAX as f64,
+            ScalarType::Int32 => i32::MAX as f64,
+            ScalarType::Uint32 => u32::MAX as f64,
+            ScalarType::Float32 | ScalarType::Float64 => 1.0,
+        }
+    }
+}
+
+impl Element {
+    fn scalar(&self, names: &[&str]) -> Option<(usize, ScalarType)> {
+        self.properties
+            .iter()
+            .enumerate()
+            .find_map(|(index, property)| match property.kind {
+                PropertyKind::Scalar(scalar) if names.contains(&property.name.as_str()) => {
+                    Some((index, scalar))
+                }
+                _ => None,
+            })
+    }
+
+    // Finds all of `names`, one property for each, or none of them.
+    fn scalars<const N: usize>(&self, names: [&[&str]; N]) -> Option<[(usize, ScalarType); N]> {
+        let found = names.map(|names| self.scalar(names));
+        if found.iter().all(Option::is_some) {
+            Some(found.map(Option::unwrap))
+        } else {
+            None
+        }
+    }
+}
+
+impl VertexLayout {
+    fn new(element: &Element) -> Result<Self, String> {
+        let position = element
+            .scalars([&["x"], &["y"], &["z"]])
+            .ok_or("the vertex element has no x, y and z properties")?;
+        let normal = element.scalars([&["nx"], &["ny"], &["nz"]]);
+        let uv = element.scalars([
+            &["u", "s", "texture_u", "texture_s"],
+            &["v", "t", "texture_v", "texture_t"],
+        ]);
+        let color = element.scalars([
+            &["red", "diffuse_red"],
+            &["green", "diffuse_green"],
+            &["blue", "diffuse_blue"],
+        ]);
+        Ok(VertexLayout {
+            position: position.map(|(index, _)| index),
+            normal: normal.map(|normal| normal.map(|(index, _)| index)),
+            uv: uv.map(|uv| uv.map(|(index, _)| index)),
+            color: color.map(|color| (color.map(|(index, _)| index), color[0].1.full_intensity())),
+        })
+    }
+
+    fn push(&self, row: &[f64], mesh: &mut TriangleMesh) {
+        let [x, y, z] = self.position;
+        mesh.positions.push(Point::new(row[x], row[y], row[z]));
+        if let Some([x, y, z]) = self.normal {
+            mesh.normals.push(Vec3::new(row[x], row[y], row[z]));
+        }
+        if let Some([u, v]) = self.uv {
+            mesh.uvs.push((row[u], row[v]));
+        }
+        if let Some(([r, g, b], full)) = self.color {
+            let channel = |value: f64| srgb_decode((value / full).clamp(0.0, 1.0));
+            mesh.colors.push(Color::new(
+                channel(row[r]),
+                channel(row[g]),
+                channel(row[b]),
+            ));
+        }
+    }
+}
+
+impl<'a> BodyReader<'a> {
+    fn read(&mut self, scalar: ScalarType) -> Result<f64, String> {
+        if self.format == Format::Ascii {
+            let rest = &self.data[self.position..];
+            let start = rest
+                .iter()
+                .position(|byte| !byte.is_ascii_whitespace())
+                .ok_or("unexpected end of file")?;
+            let length = rest[start..]
+                .iter()
+                .position(|byte| byte.is_ascii_whitespace())
+                .unwrap_or(rest.len() - start);
+            self.position += start + length;
+            let token = String::from_utf8_lossy(&rest[start..start + length]);
+            return token
+                .parse::<f64>()
+                .map_err(|_| format!("invalid number '{}'", token));
+        }
+
+        let size = scalar.size();
+        let bytes = self
+            .data
+            .get(self.position..self.position + size)
+            .ok_or("unexpected end of file")?;
+        self.position += size;
+        let mut buffer = [0u8; 8];
+        buffer[..size].copy_from_slice(bytes);
+        if self.format == Format::BinaryBigEndian {
+            buffer[..size].reverse();
+        }
+        let [b0, b1, b2, b3, ..] = buffer;
+        Ok(match scalar {
+            ScalarType::Int8 => b0 as i8 as f64,
+            ScalarType::Uint8 => b0 as f64,
+            ScalarType::Int16 => i16::from_le_bytes([b0, b1]) as f64,
+            ScalarType::Uint16 => u16::from_le_bytes([b0, b1]) as f64,
+            ScalarType::Int32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            ScalarType::Uint32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            ScalarType::Float32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            ScalarType::Float64 => f64::from_le_bytes(buffer),
+        })
+    }
+
+    // List lengths and vertex indices must be whole and not negative.
+    fn read_index(&mut self, scalar: ScalarType) -> Result<usize, String> {
+        let value = self.read(scalar)?;
+        if value < 0.0 || value.fract() != 0.0 {
+            return Err(format!("expected an index, found {}", value));
+        }
+        Ok(value as usize)
+    }
+}
+
+// Parses the header, returning it and the offset of the data after it.
+fn read_header(path: &Path, data: &[u8]) -> io::Result<(Header, usize)> {
+    let error = |line: usize, msg: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}:{}: {}", path.display(), line, msg),
+        )
+    };
+
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    let mut offset = 0;
+    let mut line_number = 0;
+    loop {
+        let rest = &data[offset..];
+        let length = rest
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .ok_or_else(|| error(line_number + 1, "the header has no end_header line"))?;
+        offset += length + 1;
+        line_number += 1;
+        let line = String::from_utf8_lossy(&rest[..length]);
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        if line_number == 1 {
+            if tokens != ["ply"] {
+                return Err(error(1, "not a PLY file"));
+            }
+            continue;
+        }
+        match tokens.as_slice() {
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            ["end_header"] => break,
+            ["format", name, version] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(error(line_number, &format!("unknown format '{}'", name))),
+                });
+                if *version != "1.0" {
+                    return Err(error(
+                        line_number,
+                        &format!("unsupported version '{}'", version),
+                    ));
+                }
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| {
+                    error(line_number, &format!("invalid element count '{}'", count))
+                })?,
+                properties: vec![],
+            }),
+            ["property", rest @ ..] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| error(line_number, "property declared before any element"))?;
+                let scalar = |name: &str| {
+                    ScalarType::parse(name).ok_or_else(|| {
+                        error(line_number, &format!("unknown property type '{}'", name))
+                    })
+                };
+                let (kind, name) = match rest {
+                    ["list", count, item, name] => (
+                        PropertyKind::List {
+                            count: scalar(count)?,
+                            item: scalar(item)?,
+                        },
+                        name,
+                    ),
+                    [scalar_type, name] if *scalar_type != "list" => {
+                        (PropertyKind::Scalar(scalar(scalar_type)?), name)
+                    }
+                    _ => {
+                        return Err(error(
+                            line_number,
+                            "expected 'property <type> <name>' or \
+                             'property list <count type> <item type> <name>'",
+                        ))
+                    }
+                };
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind,
+                });
+            }
+            [keyword, ..] => {
+                return Err(error(
+                    line_number,
+                    &format!("unexpected '{}' in header", keyword),
+                ))
+            }
+        }
+    }
+
+    let format = format.ok_or_else(|| error(line_number, "the header has no format line"))?;
+    Ok((Header { format, elements }, offset))
+}
+
+// Reads a PLY file in any of its three encodings. Vertices take their normals (`nx`, `ny`,
+// `nz`), texture coordinates (`u` and `v`, or `s` and `t`) and colors (`red`, `green`, `blue`,
+// sRGB encoded) when the file has them; faces come from the `vertex_indices` lists, with
+// polygons triangulated as fans. Other elements and properties are skipped.
+pub fn load_ply(path: &Path, material: Arc<dyn Material + Send + Sync>) -> io::Result<Mesh> {
+    read_ply(path, &fs::read(path)?, material)
+}
+
+// Parses the contents of a PLY file; `path` only names the file in errors.
+fn read_ply(
+    path: &Path,
+    data: &[u8],
+    material: Arc<dyn Material + Send + Sync>,
+) -> io::Result<Mesh> {
+    let (header, offset) = read_header(path, data)?;
+    let error = |msg: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), msg),
+        )
+    };
+
+    let mut mesh = TriangleMesh {
+        positions: vec![],
+        normals: vec![],
+        uvs: vec![],
+        colors: vec![],
+        indices: vec![],
+        material,
+    };
+    let mut reader = BodyReader {
+        format: header.format,
+        data: &data[offset..],
+        position: 0,
+    };
+    for element in &header.elements {
+        let layout = match element.name.as_str() {
+            "vertex" => Some(VertexLayout::new(element).map_err(|msg| error(msg.to_string()))?),
+            _ => None,
+        };
+        let face_indices = match element.name.as_str() {
+            "face" => Some(
+                element
+                    .properties
+                    .iter()
+                    .position(|property| {
+                        matches!(property.kind, PropertyKind::List { .. })
+                            && (property.name == "vertex_indices"
+                                || property.name == "vertex_index")
+                    })
+                    .ok_or_else(|| {
+                        error("the face element has no vertex_indices list".to_string())
+                    })?,
+            ),
+            _ => None,
+        };
+
+        let mut row = vec![0.0; element.properties.len()];
+        let mut polygon = vec![];
+        for index in 0..element.count {
+            let element_error = |msg: String| error(format!("{} {}: {}", element.name, index, msg));
+            for (property_index, property) in element.properties.iter().enumerate() {
+                match property.kind {
+                    PropertyKind::Scalar(scalar) => {
+                        row[property_index] = reader.read(scalar).map_err(element_error)?;
+                    }
+                    PropertyKind::List { count, item } => {
+                        let is_face = face_indices == Some(property_index);
+                        let count = reader.read_index(count).map_err(element_error)?;
+                        if is_face {
+                            polygon.clear();
+                        }
+                        for _ in 0..count {
+                            if is_face {
+                                polygon.push(reader.read_index(item).map_err(element_error)?);
+                            } else {
+                                reader.read(item).map_err(element_error)?;
+                            }
+                        }
+                    }
+                }
+            }
+
+            if let Some(layout) = &layout {
+                layout.push(&row, &mut mesh);
+            }
+            if face_indices.is_some() {
+                if polygon.len() < 3 {
+                    return Err(element_error(format!(
+                        "a face needs at least 3 vertices, found {}",
+                        polygon.len()
+                    )));
+                }
+                for i in 1..(polygon.len() - 1) {
+                    mesh.indices.push([polygon[0], polygon[i], polygon[i + 1]]);
+                }
+            }
+        }
+    }
+
+    // Vertices may come after the faces, so indices are only checked at the end.
+    if let Some(index) = mesh
+        .indices
+        .iter()
+        .flatten()
+        .find(|&&index| index >= mesh.positions.len())
+    {
+        return Err(error(format!(
+            "vertex index {} out of range, there are {} vertices",
+            index,
+            mesh.positions.len()
+        )));
+    }
+    if mesh.indices.is_empty() {
+        return Err(error("no faces".to_string()));
+    }
+    Ok(Mesh::new(mesh))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::error_message;
+    use crate::trace::LambertianMaterial;
+
+    fn parse(data: &[u8]) -> io::Result<Mesh> {
+        let material = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+        read_ply(Path::new("test.ply"), data, material)
+    }
+
+    fn unit_square() -> Vec<Point> {
+        vec![
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(1.0, 0.0, 0.0),
+            Point::new(1.0, 1.0, 0.0),
+            Point::new(0.0, 1.0, 0.0),
+        ]
+    }
+
+    const QUAD_HEADER: &str = "element vertex 4\n\
+                               property float x\n\
+                               property float y\n\
+                               property float z\n\
+                               element face 1\n\
+                               property list uchar int vertex_indices\n\
+                               end_header\n";
+
+    // The unit square's corners as floats and one quad face, in the given byte order.
+    fn binary_quad(
+        format: &str,
+        to_bytes: fn(f32) -> [u8; 4],
+        index: fn(i32) -> [u8; 4],
+    ) -> Vec<u8> {
+        let mut data = format!("ply\nformat {} 1.0\n{}", format, QUAD_HEADER).into_bytes();
+        for p in unit_square() {
+            for c in p.e {
+                data.extend_from_slice(&to_bytes(c as f32));
+            }
+        }
+        data.push(4);
+        for i in 0..4 {
+            data.extend_from_slice(&index(i));
+        }
+        data
+    }
+
+    #[test]
+    fn ascii_quad() {
+        let data = format!(
+            "ply\nformat ascii 1.0\ncomment made by hand\n{}\
+                            0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n",
+            QUAD_HEADER
+        );
+        let mesh = parse(data.as_bytes()).unwrap();
+        assert_eq!(mesh.data.positions, unit_square());
+        assert_eq!(mesh.data.indices, vec![[0, 1, 2], [0, 2, 3]]);
+        assert!(mesh.data.normals.is_empty());
+        assert!(mesh.data.uvs.is_empty());
+        assert!(mesh.data.colors.is_empty());
+    }
+
+    #[test]
+    fn binary_little_endian_quad() {
+        let mesh = parse(&binary_quad(
+            "binary_little_endian",
+            f32::to_le_bytes,
+            i32::to_le_bytes,
+        ))
+        .unwrap();
+        assert_eq!(mesh.data.positions, unit_square());
+        assert_eq!(mesh.data.indices, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn binary_big_endian_quad() {
+        let mesh = parse(&binary_quad(
+            "binary_big_endian",
+            f32::to_be_bytes,
+            i32::to_be_bytes,
+        ))
+        .unwrap();
+        assert_eq!(mesh.data.positions, unit_square());
+        assert_eq!(mesh.data.indices, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn truncated_binary_body() {
+        let mut data = binary_quad("binary_little_endian", f32::to_le_bytes, i32::to_le_bytes);
+        data.truncate(data.len() - 2);
+        assert_eq!(
+            error_message(parse(&data)),
+            "test.ply: face 0: unexpected end of file"
+        );
+    }
+
+    #[test]
+    fn lists_after_vertex_indices_are_skipped() {
+        let data = "ply\nformat ascii 1.0\n\
+                    element vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+                    element face 1\n\
+                    property list uchar int vertex_indices\n\
+                    property list uchar float texcoord\n\
+                    end_header\n\
+                    0 0 0\n1 0 0\n1 1 0\n0 1 0\n\
+                    4 0 1 2 3 8 0 0 1 0 1 1 0 1\n";
+        let mesh = parse(data.as_bytes()).unwrap();
+        assert_eq!(mesh.data.indices, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn other_properties_and_elements_are_skipped() {
+        let data = "ply\nformat ascii 1.0\n\
+                    element vertex 3\nproperty float x\nproperty float confidence\n\
+                    property float y\nproperty float z\n\
+                    element face 1\n\
+                    property list uchar int flags\n\
+                    property list uchar uint vertex_index\n\
+                    property uchar material\n\
+                    element edge 1\nproperty int vertex1\nproperty int vertex2\n\
+                    end_header\n\
+                    0 9 0 0\n1 9 0 0\n0 9 1 0\n\
+                    2 7 7 3 0 1 2 5\n\
+                    0 1\n";
+        let mesh = parse(data.as_bytes()).unwrap();
+        assert_eq!(mesh.data.positions[2], Point::new(0.0, 1.0, 0.0));
+        assert_eq!(mesh.data.indices, vec![[0, 1, 2]]);
+    }
+
+    #[test]
+    fn vertex_normals_uvs_and_colors() {
+        let data = "ply\nformat ascii 1.0\n\
+                    element vertex 3\n\
+                    property float x\nproperty float y\nproperty float z\n\
+                    property float nx\nproperty float ny\nproperty float nz\n\
+                    property float s\nproperty float t\n\
+                    property uchar red\nproperty uchar green\nproperty uchar blue\n\
+                    element face 1\nproperty list uchar int vertex_indices\n\
+                    end_header\n\
+                    0 0 0 0 0 1 0 0 255 0 0\n\
+                    1 0 0 0 0 1 1 0 0 255 0\n\
+                    0 1 0 0 0 1 0 1 0 0 0\n\
+                    3 0 1 2\n";
+        let mesh = parse(data.as_bytes()).unwrap();
+        assert_eq!(mesh.data.normals, vec![Vec3::new(0.0, 0.0, 1.0); 3]);
+        assert_eq!(mesh.data.uvs, vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+        assert_eq!(
+            mesh.data.colors,
+            vec![
+                Color::new(1.0, 0.0, 0.0),
+                Color::new(0.0, 1.0, 0.0),
+                Color::new(0.0, 0.0, 0.0)
+            ]
+        );
+
+        // Float colors run up to 1 and are sRGB encoded too.
+        let data = "ply\nformat ascii 1.0\n\
+                    element vertex 3\n\
+                    property float x\nproperty float y\nproperty float z\n\
+                    property float red\nproperty float green\nproperty float blue\n\
+                    element face 1\nproperty list uchar int vertex_indices\n\
+                    end_header\n\
+                    0 0 0 0.5 2 -1\n1 0 0 0 0 0\n0 1 0 0 0 0\n\
+                    3 0 1 2\n";
+        let mesh = parse(data.as_bytes()).unwrap();
+        assert_eq!(mesh.data.colors[0], Color::new(srgb_decode(0.5), 1.0, 0.0));
+    }
+
+    #[test]
+    fn polygons_are_triangulated_as_fans() {
+        let data = "ply\nformat ascii 1.0\n\
+                    element vertex 5\nproperty float x\nproperty float y\nproperty float z\n\
+                    element face 2\nproperty list uchar int vertex_indices\n\
+                    end_header\n\
+                    0 0 0\n1 0 0\n2 1 0\n1 2 0\n0 1 0\n\
+                    5 0 1 2 3 4\n3 4 3 1\n";
+        let mesh = parse(data.as_bytes()).unwrap();
+        assert_eq!(
+            mesh.data.indices,
+            vec![[0, 1, 2], [0, 2, 3], [0, 3, 4], [4, 3, 1]]
+        );
+    }
+
+    #[test]
+    fn out_of_range_indices_are_errors() {
+        let data = "ply\nformat ascii 1.0\n\
+                    element vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+                    element face 1\nproperty list uchar int vertex_indices\n\
+                    end_header\n\
+                    0 0 0\n1 0 0\n0 1 0\n3 0 1 3\n";
+        assert_eq!(
+            error_message(parse(data.as_bytes())),
+            "test.ply: vertex index 3 out of range, there are 3 vertices"
+        );
+        let data = data.replace("3 0 1 3", "3 0 1 -1");
+        assert_eq!(
+            error_message(parse(data.as_bytes())),
+            "test.ply: face 0: expected an index, found -1"
+        );
+    }
+
+    #[test]
+    fn faces_need_three_vertices() {
+        let data = "ply\nformat ascii 1.0\n\
+                    element vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+                    element face 2\nproperty list uchar int vertex_indices\n\
+                    end_header\n\
+                    0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n2 0 1\n";
+        assert_eq!(
+            error_message(parse(data.as_bytes())),
+            "test.ply: face 1: a face needs at least 3 vertices, found 2"
+        );
+    }
+
+    #[test]
+    fn header_errors_report_the_line() {
+        assert_eq!(error_message(parse(b"PLY\n")), "test.ply:1: not a PLY file");
+        assert_eq!(
+            error_message(parse(
+                b"ply\nformat ascii 1.0\nproperty float x\nend_header\n"
+            )),
+            "test.ply:3: property declared before any element"
+        );
+        assert_eq!(
+            error_message(parse(
+                b"ply\nformat ascii 1.0\nelement vertex 1\nproperty half x\n"
+            )),
+            "test.ply:4: unknown property type 'half'"
+        );
+        assert_eq!(
+            error_message(parse(b"ply\nformat binary 1.0\n")),
+            "test.ply:2: unknown format 'binary'"
+        );
+        assert_eq!(
+            error_message(parse(b"ply\nformat ascii 1.0\nelement vertex 1\n")),
+            "test.ply:4: the header has no end_header line"
+        );
+        assert_eq!(
+            error_message(parse(b"ply\nelement vertex 0\nend_header\n")),
+            "test.ply:3: the header has no format line"
+        );
+    }
+}
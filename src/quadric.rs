@@ -0,0 +1,605 @@
+use crate::math::{
+    is_in_range, solve_quadratic, solve_quartic, to_unit_vector, Aabb, Point, Ray, Vec3,
+};
+use crate::trace::{HitRecord, Hittable, Material};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Surfaces of revolution around the y axis through `base`, swept from the +x axis towards +z
+// by `phi_max` radians. They are not sampled as lights.
+//
+// On the sides u runs around the sweep and v up the axis; on caps, v runs out from the axis.
+
+// Side of radius `radius` from `base` up to `height`, with optional caps at both ends.
+pub struct Cylinder {
+    pub base: Point,
+    pub radius: f64,
+    pub height: f64,
+    pub phi_max: f64,
+    pub capped: bool,
+    pub material: Arc<dyn Material + Send + Sync>,
+}
+
+// Narrows from `radius` at `base` to a point `height` above it; the cap closes the base.
+pub struct Cone {
+    pub base: Point,
+    pub radius: f64,
+    pub height: f64,
+    pub phi_max: f64,
+    pub capped: bool,
+    pub material: Arc<dyn Material + Send + Sync>,
+}
+
+// Bowl with its vertex at `base`, opening up to `radius` at `height`; the cap closes the top.
+pub struct Paraboloid {
+    pub base: Point,
+    pub radius: f64,
+    pub height: f64,
+    pub phi_max: f64,
+    pub capped: bool,
+    pub material: Arc<dyn Material + Send + Sync>,
+}
+
+// Tube of radius `minor_radius` around a circle of radius `major_radius` in the xz plane
+// through `center`. v runs around the tube, starting on the outside.
+pub struct Torus {
+    pub center: Point,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub phi_max: f64,
+    pub material: Arc<dyn Material + Send + Sync>,
+}
+
+// A hit in the shape's own frame, relative to its base.
+struct LocalHit {
+    t: f64,
+    point: Point,
+    normal: Vec3,
+    u: f64,
+    v: f64,
+    // None where the parameterization degenerates, as on the axis.
+    tangents: Option<(Vec3, Vec3)>,
+}
+
+impl LocalHit {
+    fn into_record(
+        self,
+        ray: &Ray,
+        origin: &Point,
+        material: &Arc<dyn Material + Send + Sync>,
+    ) -> HitRecord {
+        let mut hit = HitRecord::from_hit(
+            &(self.point + *origin),
+            ray,
+            self.t,
+            &to_unit_vector(&self.normal),
+            self.u,
+            self.v,
+            material.clone(),
+        );
+        if let Some((dpdu, dpdv)) = self.tangents {
+            hit.set_tangents(&dpdu, &dpdv);
+        }
+        hit
+    }
+}
+
+fn nearest(a: Option<LocalHit>, b: Option<LocalHit>) -> Option<LocalHit> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if a.t <= b.t { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+// Angle of `point` around the y axis, from +x towards +z, in [0, 2 pi).
+fn azimuth(point: &Point) -> f64 {
+    point.z().atan2(point.x()).rem_euclid(2.0 * PI)
+}
+
+// Derivative of a point on a surface of revolution along its sweep.
+fn sweep_tangent(point: &Point, phi_max: f64) -> Vec3 {
+    Vec3::new(-point.z(), 0.0, point.x()) * phi_max
+}
+
+// Box around a swept surface of revolution reaching out to `radius` up to `height` above its
+// base. Partial sweeps keep the box of the full one.
+fn revolution_bounds(base: &Point, radius: f64, height: f64) -> Aabb {
+    Aabb::new(
+        *base + Vec3::new(-radius, 0.0, -radius),
+        *base + Vec3::new(radius, height, radius),
+    )
+}
+
+// First of the roots `roots` inside the ray's range whose point passes `accept`.
+fn first_root(
+    roots: &[f64],
+    origin: &Point,
+    direction: &Vec3,
+    t_min: f64,
+    t_max: f64,
+    accept: impl Fn(&Point) -> bool,
+) -> Option<(f64, Point)> {
+    roots
+        .iter()
+        .filter(|t| is_in_range(**t, t_min, t_max))
+        .map(|t| (*t, *origin + *direction * *t))
+        .find(|(_, point)| accept(point))
+}
+
+// Disk closing a swept surface at height `y`, with its normal along +y when `up` is set.
+struct Cap {
+    y: f64,
+    radius: f64,
+    phi_max: f64,
+    up: bool,
+}
+
+impl Cap {
+    fn hit(&self, origin: &Point, direction: &Vec3, t_min: f64, t_max: f64) -> Option<LocalHit> {
+        let Cap {
+            y,
+            radius,
+            phi_max,
+            up,
+        } = *self;
+        if direction.y() == 0.0 {
+            return None;
+        }
+        let t = (y - origin.y()) / direction.y();
+        if !is_in_range(t, t_min, t_max) {
+            return None;
+        }
+        let point = *origin + *direction * t;
+        let distance = (point.x() * point.x() + point.z() * point.z()).sqrt();
+        let phi = azimuth(&point);
+        if distance > radius || phi > phi_max {
+            return None;
+        }
+        let tangents = if distance > 1e-9 * radius {
+            let radial = Vec3::new(point.x(), 0.0, point.z()) * (radius / distance);
+            Some((sweep_tangent(&point, phi_max), radial))
+        } else {
+            None
+        };
+        Some(LocalHit {
+            t,
+            point,
+            normal: Vec3::new(0.0, if up { 1.0 } else { -1.0 }, 0.0),
+            u: phi / phi_max,
+            v: distance / radius,
+            tangents,
+        })
+    }
+}
+
+impl Cylinder {
+    fn hit_side(&self, origin: &Point, d: &Vec3, t_min: f64, t_max: f64) -> Option<LocalHit> {
+        let a = d.x() * d.x() + d.z() * d.z();
+        if a == 0.0 {
+            return None;
+        }
+        let b = 2.0 * (origin.x() * d.x() + origin.z() * d.z());
+        let c = origin.x() * origin.x() + origin.z() * origin.z() - self.radius * self.radius;
+        let (r0, r1) = solve_quadratic(a, b, c)?;
+        let (t, point) = first_root(&[r0, r1], origin, d, t_min, t_max, |p| {
+            (0.0..=self.height).contains(&p.y()) && azimuth(p) <= self.phi_max
+        })?;
+        Some(LocalHit {
+            t,
+            point,
+            normal: Vec3::new(point.x(), 0.0, point.z()),
+            u: azimuth(&point) / self.phi_max,
+            v: point.y() / self.height,
+            tangents: Some((
+                sweep_tangent(&point, self.phi_max),
+                Vec3::new(0.0, self.height, 0.0),
+            )),
+        })
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let origin = ray.origin - self.base;
+        let d = ray.direction;
+        let mut hit = self.hit_side(&origin, &d, t_min, t_max);
+        if self.capped {
+            let t_max = hit.as_ref().map_or(t_max, |hit| hit.t);
+            for (y, up) in [(0.0, false), (self.height, true)] {
+                let cap = Cap {
+                    y,
+                    radius: self.radius,
+                    phi_max: self.phi_max,
+                    up,
+                };
+                hit = nearest(hit, cap.hit(&origin, &d, t_min, t_max));
+            }
+        }
+        Some(hit?.into_record(ray, &self.base, &self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(revolution_bounds(&self.base, self.radius, self.height))
+    }
+}
+
+impl Cone {
+    fn hit_side(&self, origin: &Point, d: &Vec3, t_min: f64, t_max: f64) -> Option<LocalHit> {
+        // x^2 + z^2 = k^2 (h - y)^2
+        let (h, k) = (self.height, self.radius / self.height);
+        let k2 = k * k;
+        let a = d.x() * d.x() + d.z() * d.z() - k2 * d.y() * d.y();
+        let b = 2.0 * (origin.x() * d.x() + origin.z() * d.z() + k2 * (h - origin.y()) * d.y());
+        let c = origin.x() * origin.x() + origin.z() * origin.z()
+            - k2 * (h - origin.y()) * (h - origin.y());
+        let (r0, r1) = solve_quadratic(a, b, c)?;
+        let (t, point) = first_root(&[r0, r1], origin, d, t_min, t_max, |p| {
+            (0.0..=h).contains(&p.y()) && azimuth(p) <= self.phi_max
+        })?;
+        let v = point.y() / h;
+        let tangents = if v < 1.0 - 1e-9 {
+            let phi = azimuth(&point);
+            let dpdv = Vec3::new(-self.radius * phi.cos(), h, -self.radius * phi.sin());
+            Some((sweep_tangent(&point, self.phi_max), dpdv))
+        } else {
+            None
+        };
+        Some(LocalHit {
+            t,
+            point,
+            normal: Vec3::new(point.x(), k2 * (h - point.y()), point.z()),
+            u: azimuth(&point) / self.phi_max,
+            v,
+            tangents,
+        })
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let origin = ray.origin - self.base;
+        let d = ray.direction;
+        let mut hit = self.hit_side(&origin, &d, t_min, t_max);
+        if self.capped {
+            let t_max = hit.as_ref().map_or(t_max, |hit| hit.t);
+            let cap = Cap {
+                y: 0.0,
+                radius: self.radius,
+                phi_max: self.phi_max,
+                up: false,
+            };
+            hit = nearest(hit, cap.hit(&origin, &d, t_min, t_max));
+        }
+        Some(hit?.into_record(ray, &self.base, &self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(revolution_bounds(&self.base, self.radius, self.height))
+    }
+}
+
+impl Paraboloid {
+    fn hit_side(&self, origin: &Point, d: &Vec3, t_min: f64, t_max: f64) -> Option<LocalHit> {
+        // x^2 + z^2 = k y
+        let k = self.radius * self.radius / self.height;
+        let a = d.x() * d.x() + d.z() * d.z();
+        let b = 2.0 * (origin.x() * d.x() + origin.z() * d.z()) - k * d.y();
+        let c = origin.x() * origin.x() + origin.z() * origin.z() - k * origin.y();
+        let (r0, r1) = solve_quadratic(a, b, c)?;
+        let (t, point) = first_root(&[r0, r1], origin, d, t_min, t_max, |p| {
+            (0.0..=self.height).contains(&p.y()) && azimuth(p) <= self.phi_max
+        })?;
+        let v = point.y() / self.height;
+        let tangents = if v > 1e-9 {
+            // The distance from the axis grows as radius * sqrt(v).
+            let phi = azimuth(&point);
+            let spread = self.radius / (2.0 * v.sqrt());
+            let dpdv = Vec3::new(spread * phi.cos(), self.height, spread * phi.sin());
+            Some((sweep_tangent(&point, self.phi_max), dpdv))
+        } else {
+            None
+        };
+        Some(LocalHit {
+            t,
+            point,
+            normal: Vec3::new(2.0 * point.x(), -k, 2.0 * point.z()),
+            u: azimuth(&point) / self.phi_max,
+            v,
+            tangents,
+        })
+    }
+}
+
+impl Hittable for Paraboloid {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let origin = ray.origin - self.base;
+        let d = ray.direction;
+        let mut hit = self.hit_side(&origin, &d, t_min, t_max);
+        if self.capped {
+            let t_max = hit.as_ref().map_or(t_max, |hit| hit.t);
+            let cap = Cap {
+                y: self.height,
+                radius: self.radius,
+                phi_max: self.phi_max,
+                up: true,
+            };
+            hit = nearest(hit, cap.hit(&origin, &d, t_min, t_max));
+        }
+        Some(hit?.into_record(ray, &self.base, &self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(revolution_bounds(&self.base, self.radius, self.height))
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // The quartic is solved along a unit direction, which keeps its coefficients in
+        // proportion; distances are scaled back to the ray's own afterwards.
+        let length = ray.direction.length();
+        let d = ray.direction / length;
+        let o = ray.origin - self.center;
+        let (major, minor) = (self.major_radius, self.minor_radius);
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2) with p = o + s d.
+        let b = o.x() * d.x() + o.y() * d.y() + o.z() * d.z();
+        let k = o.length_squared() + major * major - minor * minor;
+        let planar_a = d.x() * d.x() + d.z() * d.z();
+        let planar_b = o.x() * d.x() + o.z() * d.z();
+        let planar_c = o.x() * o.x() + o.z() * o.z();
+        let four_r2 = 4.0 * major * major;
+        let roots = solve_quartic(&[
+            k * k - four_r2 * planar_c,
+            4.0 * b * k - 2.0 * four_r2 * planar_b,
+            4.0 * b * b + 2.0 * k - four_r2 * planar_a,
+            4.0 * b,
+            1.0,
+        ]);
+        let distances: Vec<f64> = roots.iter().map(|s| s / length).collect();
+        let (t, point) = first_root(&distances, &o, &ray.direction, t_min, t_max, |p| {
+            azimuth(p) <= self.phi_max
+        })?;
+
+        let phi = azimuth(&point);
+        let planar_distance = (point.x() * point.x() + point.z() * point.z()).sqrt();
+        let tube = planar_distance - major;
+        let ring = Vec3::new(point.x(), 0.0, point.z()) * (major / planar_distance);
+        let theta = point.y().atan2(tube).rem_euclid(2.0 * PI);
+        let dpdv = Vec3::new(-point.y() * phi.cos(), tube, -point.y() * phi.sin()) * (2.0 * PI);
+        let hit = LocalHit {
+            t,
+            point,
+            normal: point - ring,
+            u: phi / self.phi_max,
+            v: theta / (2.0 * PI),
+            tangents: Some((sweep_tangent(&point, self.phi_max), dpdv)),
+        };
+        Some(hit.into_record(ray, &self.center, &self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let reach = self.major_radius + self.minor_radius;
+        let extent = Vec3::new(reach, self.minor_radius, reach);
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Color;
+    use crate::trace::LambertianMaterial;
+
+    fn material() -> Arc<dyn Material + Send + Sync> {
+        Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn cast(hittable: &dyn Hittable, origin: Point, direction: Vec3) -> Option<HitRecord> {
+        let ray = Ray {
+            origin,
+            direction,
+            time: 0.0,
+        };
+        hittable.hit(&ray, 0.001, f64::INFINITY)
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    fn assert_vec_close(a: &Vec3, b: &Vec3) {
+        assert!((*a - *b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    // t, the normal facing the ray, u and v.
+    fn assert_hit(hit: Option<HitRecord>, t: f64, normal: Vec3, u: f64, v: f64) {
+        let hit = hit.expect("expected a hit");
+        assert_close(hit.t, t);
+        assert_vec_close(&hit.normal, &to_unit_vector(&normal));
+        assert_close(hit.u, u);
+        assert_close(hit.v, v);
+    }
+
+    fn cylinder(phi_max: f64, capped: bool) -> Cylinder {
+        Cylinder {
+            base: Point::new(0.0, -1.0, 0.0),
+            radius: 2.0,
+            height: 3.0,
+            phi_max,
+            capped,
+            material: material(),
+        }
+    }
+
+    #[test]
+    fn cylinder_side_and_caps() {
+        let closed = cylinder(2.0 * PI, true);
+        let side = cast(
+            &closed,
+            Point::new(0.0, 0.5, 5.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        assert_hit(side.clone(), 3.0, Vec3::new(0.0, 0.0, 1.0), 0.25, 0.5);
+        assert_vec_close(&side.unwrap().dpdu, &Vec3::new(-2.0 * 2.0 * PI, 0.0, 0.0));
+        let top = cast(
+            &closed,
+            Point::new(1.0, 5.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+        );
+        assert_hit(top, 3.0, Vec3::new(0.0, 1.0, 0.0), 0.0, 0.5);
+        let bottom = cast(
+            &closed,
+            Point::new(0.0, -5.0, 1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        assert_hit(bottom, 4.0, Vec3::new(0.0, -1.0, 0.0), 0.25, 0.5);
+        assert!(cast(
+            &closed,
+            Point::new(3.0, 5.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0)
+        )
+        .is_none());
+
+        // Without caps the ray drops straight through, and from inside it meets the side's back.
+        let open = cylinder(2.0 * PI, false);
+        assert!(cast(&open, Point::new(1.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).is_none());
+        let inside = cast(&open, Point::new(0.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0)).unwrap();
+        assert_close(inside.t, 2.0);
+        assert!(!inside.front_face);
+        assert_vec_close(&inside.normal, &Vec3::new(-1.0, 0.0, 0.0));
+
+        let bbox = closed.bounding_box().unwrap();
+        assert_vec_close(&bbox.min, &Point::new(-2.0, -1.0, -2.0));
+        assert_vec_close(&bbox.max, &Point::new(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn partial_sweeps_reject_hits_beyond_phi_max() {
+        // Only the half with z >= 0 is left.
+        let half = cylinder(PI, true);
+        let front = cast(&half, Point::new(0.0, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_hit(front, 3.0, Vec3::new(0.0, 0.0, 1.0), 0.5, 0.5);
+        // From -z the near side is missing, so the ray goes on to the inside of the far side.
+        let back = cast(&half, Point::new(0.0, 0.5, -5.0), Vec3::new(0.0, 0.0, 1.0)).unwrap();
+        assert_close(back.t, 7.0);
+        assert!(!back.front_face);
+        // The caps are cut the same way.
+        assert!(cast(&half, Point::new(0.0, 5.0, -1.0), Vec3::new(0.0, -1.0, 0.0)).is_none());
+        let cap = cast(&half, Point::new(0.0, 5.0, 1.0), Vec3::new(0.0, -1.0, 0.0));
+        assert_hit(cap, 3.0, Vec3::new(0.0, 1.0, 0.0), 0.5, 0.5);
+    }
+
+    #[test]
+    fn cone_side_and_cap() {
+        let cone = Cone {
+            base: Point::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+            height: 2.0,
+            phi_max: 2.0 * PI,
+            capped: true,
+            material: material(),
+        };
+        // Halfway up the radius is 0.5, and the side leans in by 1 in 2.
+        let side = cast(&cone, Point::new(5.0, 1.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert_hit(side, 4.5, Vec3::new(2.0, 1.0, 0.0), 0.0, 0.5);
+        let cap = cast(&cone, Point::new(0.0, -3.0, 0.5), Vec3::new(0.0, 1.0, 0.0));
+        assert_hit(cap, 3.0, Vec3::new(0.0, -1.0, 0.0), 0.25, 0.5);
+        // From above, the side slopes the same way.
+        let above = cast(&cone, Point::new(0.9, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert_hit(above, 4.8, Vec3::new(2.0, 1.0, 0.0), 0.0, 0.1);
+
+        let bbox = cone.bounding_box().unwrap();
+        assert_vec_close(&bbox.min, &Point::new(-1.0, 0.0, -1.0));
+        assert_vec_close(&bbox.max, &Point::new(1.0, 2.0, 1.0));
+    }
+
+    #[test]
+    fn paraboloid_side_and_cap() {
+        // x^2 + z^2 = y
+        let bowl = |capped| Paraboloid {
+            base: Point::new(0.0, 0.0, 0.0),
+            radius: 2.0,
+            height: 4.0,
+            phi_max: 2.0 * PI,
+            capped,
+            material: material(),
+        };
+        let outside = cast(
+            &bowl(true),
+            Point::new(5.0, 1.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+        );
+        assert_hit(outside, 4.0, Vec3::new(2.0, -1.0, 0.0), 0.0, 0.25);
+        // Looking down into the open bowl, the ray meets its inside.
+        let inside = cast(
+            &bowl(false),
+            Point::new(0.0, 10.0, 1.0),
+            Vec3::new(0.0, -1.0, 0.0),
+        );
+        assert_hit(inside, 9.0, Vec3::new(0.0, 1.0, -2.0), 0.25, 0.25);
+        let cap = cast(
+            &bowl(true),
+            Point::new(0.0, 10.0, 1.0),
+            Vec3::new(0.0, -1.0, 0.0),
+        );
+        assert_hit(cap, 6.0, Vec3::new(0.0, 1.0, 0.0), 0.25, 0.5);
+
+        let bbox = bowl(true).bounding_box().unwrap();
+        assert_vec_close(&bbox.min, &Point::new(-2.0, 0.0, -2.0));
+        assert_vec_close(&bbox.max, &Point::new(2.0, 4.0, 2.0));
+    }
+
+    fn torus(phi_max: f64) -> Torus {
+        Torus {
+            center: Point::new(0.0, 0.0, 0.0),
+            major_radius: 3.0,
+            minor_radius: 1.0,
+            phi_max,
+            material: material(),
+        }
+    }
+
+    #[test]
+    fn torus_through_the_hole_and_the_tube() {
+        let torus = torus(2.0 * PI);
+        // Straight down the middle misses.
+        assert!(cast(
+            &torus,
+            Point::new(0.0, 10.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0)
+        )
+        .is_none());
+        // Down onto the top of the tube.
+        let top = cast(
+            &torus,
+            Point::new(3.0, 10.0, 0.0),
+            Vec3::new(0.0, -2.0, 0.0),
+        );
+        assert_hit(top, 4.5, Vec3::new(0.0, 1.0, 0.0), 0.0, 0.25);
+        // Along the x axis, onto the outside of the tube.
+        let outer = cast(
+            &torus,
+            Point::new(-10.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        );
+        assert_hit(outer, 6.0, Vec3::new(-1.0, 0.0, 0.0), 0.5, 0.0);
+        // From inside the tube.
+        let inside = cast(&torus, Point::new(3.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)).unwrap();
+        assert_close(inside.t, 1.0);
+        assert!(!inside.front_face);
+
+        let bbox = torus.bounding_box().unwrap();
+        assert_vec_close(&bbox.min, &Point::new(-4.0, -1.0, -4.0));
+        assert_vec_close(&bbox.max, &Point::new(4.0, 1.0, 4.0));
+    }
+
+    #[test]
+    fn partial_torus() {
+        // Only the half with z >= 0 is left, so along +z the ray crosses the hole and meets the
+        // inner side of the far half of the tube.
+        let half = torus(PI);
+        let hit = cast(&half, Point::new(0.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_hit(hit, 12.0, Vec3::new(0.0, 0.0, -1.0), 0.5, 0.5);
+    }
+}
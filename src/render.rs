@@ -0,0 +1,132 @@
+use crate::math::{random_float, Color};
+use crate::tonemap::ToneMapping;
+use crate::trace::{get_ray_color, Camera, Scene, BLACK};
+use rayon::prelude::*;
+use std::sync::Arc;
+
+#[derive(Debug, Copy, Clone)]
+pub struct RenderSettings {
+    pub image_width: u32,
+    pub image_height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub tone_mapping: ToneMapping,
+}
+
+// Rows are stored bottom to top, matching the camera's v axis.
+pub struct FrameBuffer {
+    pub color: Vec<Vec<Color>>,
+    // Fraction of each pixel's camera rays that hit the world rather than the background.
+    pub alpha: Vec<Vec<f64>>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            image_width: 1280,
+            image_height: 720,
+            samples_per_pixel: 500,
+            max_depth: 20,
+            tile_width: 16,
+            tile_height: 9,
+            tone_mapping: ToneMapping::default(),
+        }
+    }
+}
+
+impl FrameBuffer {
+    pub fn width(&self) -> usize {
+        self.color.first().map_or(0, |row| row.len())
+    }
+
+    pub fn height(&self) -> usize {
+        self.color.len()
+    }
+}
+
+impl RenderSettings {
+    pub fn aspect_ratio(&self) -> f64 {
+        self.image_width as f64 / self.image_height as f64
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.image_width < 2 || self.image_height < 2 {
+            return Err(String::from("width and height must be at least 2"));
+        }
+        if self.samples_per_pixel == 0 {
+            return Err(String::from("samples_per_pixel must be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+pub fn render(scene: &Arc<Scene>, camera: &Arc<Camera>, settings: &RenderSettings) -> FrameBuffer {
+    let settings = *settings;
+    let scene = scene.clone();
+    let camera = camera.clone();
+
+    // Tiles along the right and top edges are clipped when the image size is not a multiple of
+    // the tile size.
+    let tiles_per_row = settings.image_width.div_ceil(settings.tile_width);
+    let tiles_per_col = settings.image_height.div_ceil(settings.tile_height);
+    let num_tiles = tiles_per_row * tiles_per_col;
+
+    let tile_results = (0..num_tiles)
+        .into_par_iter()
+        .map(move |tile_idx| {
+            let col_start = (tile_idx % tiles_per_row) * settings.tile_width;
+            let col_end = (col_start + settings.tile_width).min(settings.image_width);
+            let row_start = (tile_idx / tiles_per_row) * settings.tile_height;
+            let row_end = (row_start + settings.tile_height).min(settings.image_height);
+
+            let mut tile_buffer = vec![
+                vec![(BLACK, 0.0); (col_end - col_start) as usize];
+                (row_end - row_start) as usize
+            ];
+
+            for j in row_start..row_end {
+                for i in col_start..col_end {
+                    let mut pixel_color = BLACK;
+                    let mut hits = 0;
+
+                    for _s in 0..settings.samples_per_pixel {
+                        let u = (i as f64 + random_float()) / (settings.image_width - 1) as f64;
+                        let v = (j as f64 + random_float()) / (settings.image_height - 1) as f64;
+                        let ray = camera.get_ray(u, v);
+                        let (color, hit) = get_ray_color(&ray, &scene, settings.max_depth);
+                        pixel_color += color;
+                        if hit {
+                            hits += 1;
+                        }
+                    }
+                    pixel_color /= settings.samples_per_pixel as f64;
+                    let alpha = hits as f64 / settings.samples_per_pixel as f64;
+                    let tile_j = j - row_start;
+                    let tile_i = i - col_start;
+                    tile_buffer[tile_j as usize][tile_i as usize] = (pixel_color, alpha);
+                }
+            }
+
+            (tile_idx, tile_buffer)
+        })
+        .collect::<Vec<_>>();
+
+    let mut frame_buffer = FrameBuffer {
+        color: vec![vec![BLACK; settings.image_width as usize]; settings.image_height as usize],
+        alpha: vec![vec![0.0; settings.image_width as usize]; settings.image_height as usize],
+    };
+    for (tile_idx, tile_buffer) in tile_results {
+        for (j, tile_row) in tile_buffer.iter().enumerate() {
+            for (i, (pixel_color, alpha)) in tile_row.iter().enumerate() {
+                let frame_buffer_i = (tile_idx % tiles_per_row) * settings.tile_width + i as u32;
+                let frame_buffer_j = (tile_idx / tiles_per_row) * settings.tile_height + j as u32;
+                frame_buffer.color[frame_buffer_j as usize][frame_buffer_i as usize] = *pixel_color;
+                frame_buffer.alpha[frame_buffer_j as usize][frame_buffer_i as usize] = *alpha;
+            }
+        }
+    }
+
+    frame_buffer
+}
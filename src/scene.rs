@@ -0,0 +1,1756 @@
+use crate::bump::{BumpMap, NormalMap, PerturbedMaterial};
+use crate::bvh::Bvh;
+use crate::csg::{Csg, CsgOperation};
+use crate::curve::{load_strands, Curve, CurveKind};
+use crate::gltf_import::load_gltf;
+use crate::hair::HairMaterial;
+use crate::heightfield::{HeightGrid, Heightfield};
+use crate::instance::Instance;
+use crate::math::{cross_product, degrees_to_radians, Aabb, Color, Point, Transform, Vec3};
+use crate::medium::{ConstantMedium, HenyeyGreensteinMaterial, IsotropicMaterial};
+use crate::mesh::{load_obj, Triangle};
+use crate::planar::{Cuboid, Disk, Plane, Quad};
+use crate::ply::load_ply;
+use crate::quadric::{Cone, Cylinder, Paraboloid, Torus};
+use crate::render::RenderSettings;
+use crate::sdf::{
+    DistanceField, Mandelbulb, Sdf, SdfBox, SdfCombination, SdfCylinder, SdfRepetition, SdfSphere,
+    SdfTorus, SdfTwist,
+};
+use crate::stl::load_stl;
+use crate::texture::{
+    CheckerTexture, ImageTexture, MarbleTexture, NoiseTexture, SolidColor, Texture,
+};
+use crate::tonemap::{ToneMapOperator, ToneMapping};
+use crate::trace::{
+    Background, Camera, DiaelectriMaterial, DiffuseLightMaterial, Hittable, HittableCollection,
+    LambertianMaterial, Material, MetalMaterial, Moving, MovingSphere, Scene, Sphere, BLACK, WHITE,
+};
+use crate::volume::{DensityField, DensityGrid, HeterogeneousMedium, NoiseDensity};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use toml::Spanned;
+
+pub struct SceneFile {
+    pub scene: Scene,
+    pub camera: CameraSettings,
+    pub settings: RenderSettings,
+}
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraSettings {
+    pub look_from: Point,
+    pub look_at: Point,
+    #[serde(default = "default_vup")]
+    pub vup: Vec3,
+    pub vfov: f64,
+    #[serde(default)]
+    pub aperture: f64,
+    // Defaults to the distance between `look_from` and `look_at`.
+    pub focus_distance: Option<f64>,
+    // Fractions of the frame the shutter is open for; moving objects blur over this interval.
+    #[serde(default)]
+    pub shutter_open: f64,
+    #[serde(default)]
+    pub shutter_close: f64,
+}
+
+// Materials and objects are kept as raw TOML until the whole file has parsed, so errors in
+// them can be reported against the line of the table they came from.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    render: Option<Spanned<RenderDescription>>,
+    camera: CameraSettings,
+    #[serde(default)]
+    background: Option<Spanned<toml::Value>>,
+    #[serde(default)]
+    textures: HashMap<String, Spanned<toml::Value>>,
+    #[serde(default)]
+    materials: HashMap<String, Spanned<toml::Value>>,
+    #[serde(default)]
+    objects: Vec<Spanned<toml::Value>>,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RenderDescription {
+    width: u32,
+    height: u32,
+    samples_per_pixel: u32,
+    max_depth: u32,
+    tone_map: ToneMapOperator,
+    exposure: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundDescription {
+    Sky,
+    Solid { color: Color },
+}
+
+// Anywhere a color is taken, the name of a texture can be given instead.
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "expected a color or the name of a texture")]
+enum TextureReference {
+    Color(Color),
+    Name(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDescription {
+    Checker {
+        even: TextureReference,
+        odd: TextureReference,
+        #[serde(default = "default_texture_scale")]
+        scale: f64,
+    },
+    // PNG or PPM file, relative to the scene file. Images are sRGB encoded unless `linear` is
+    // set, as it should be for normal maps.
+    Image {
+        path: String,
+        #[serde(default)]
+        linear: bool,
+    },
+    Noise {
+        #[serde(default = "default_texture_color")]
+        color: Color,
+        #[serde(default = "default_texture_scale")]
+        scale: f64,
+    },
+    Marble {
+        #[serde(default = "default_texture_color")]
+        color: Color,
+        #[serde(default = "default_texture_scale")]
+        scale: f64,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDescription {
+    Lambertian {
+        albedo: TextureReference,
+    },
+    Metal {
+        albedo: TextureReference,
+        #[serde(default)]
+        fuzziness: f64,
+    },
+    Dielectric {
+        ref_idx: f64,
+        albedo: Option<TextureReference>,
+    },
+    DiffuseLight {
+        emit: TextureReference,
+        #[serde(default)]
+        two_sided: bool,
+    },
+    // Wraps another material, perturbing its shading normal.
+    Bump {
+        material: String,
+        height: TextureReference,
+        #[serde(default = "default_bump_scale")]
+        scale: f64,
+    },
+    NormalMap {
+        material: String,
+        normals: TextureReference,
+    },
+    // Phase functions, for objects filled with a medium.
+    Isotropic {
+        albedo: TextureReference,
+    },
+    HenyeyGreenstein {
+        albedo: TextureReference,
+        g: f64,
+    },
+    // For curves. The absorption inside the fiber comes from one of `color`, the melanin
+    // concentrations, or `sigma_a` directly; `alpha` is in degrees.
+    Hair {
+        color: Option<Color>,
+        eumelanin: Option<f64>,
+        pheomelanin: Option<f64>,
+        sigma_a: Option<Color>,
+        #[serde(default = "default_hair_eta")]
+        eta: f64,
+        #[serde(default = "default_hair_roughness")]
+        beta_m: f64,
+        #[serde(default = "default_hair_roughness")]
+        beta_n: f64,
+        #[serde(default = "default_hair_alpha")]
+        alpha: f64,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDescription {
+    Sphere {
+        center: Point,
+        radius: f64,
+        material: String,
+    },
+    Triangle {
+        vertices: [Point; 3],
+        material: String,
+    },
+    // PLY or STL file by extension, or otherwise Wavefront OBJ, relative to the scene file.
+    Mesh {
+        path: String,
+        material: String,
+    },
+    // Unbounded; faces along `normal`.
+    Plane {
+        point: Point,
+        normal: Vec3,
+        material: String,
+    },
+    // Parallelogram with edges `u` and `v` from `corner`, facing along u x v.
+    Quad {
+        corner: Point,
+        u: Vec3,
+        v: Vec3,
+        material: String,
+    },
+    Disk {
+        center: Point,
+        normal: Vec3,
+        radius: f64,
+        material: String,
+    },
+    // Axis-aligned, between opposite corners `min` and `max`.
+    Box {
+        min: Point,
+        max: Point,
+        material: String,
+    },
+    // Surfaces of revolution stand on `base` and rise along +y; `phi_max` is in degrees.
+    Cylinder {
+        base: Point,
+        radius: f64,
+        height: f64,
+        #[serde(default = "default_phi_max")]
+        phi_max: f64,
+        #[serde(default = "default_capped")]
+        capped: bool,
+        material: String,
+    },
+    Cone {
+        base: Point,
+        radius: f64,
+        height: f64,
+        #[serde(default = "default_phi_max")]
+        phi_max: f64,
+        #[serde(default = "default_capped")]
+        capped: bool,
+        material: String,
+    },
+    Paraboloid {
+        base: Point,
+        radius: f64,
+        height: f64,
+        #[serde(default = "default_phi_max")]
+        phi_max: f64,
+        #[serde(default = "default_capped")]
+        capped: bool,
+        material: String,
+    },
+    // Lies in the xz plane.
+    Torus {
+        center: Point,
+        major_radius: f64,
+        minor_radius: f64,
+        #[serde(default = "default_phi_max")]
+        phi_max: f64,
+        material: String,
+    },
+    // Heterogeneous medium filling the box from `min` to `max`, with coefficients scaled by
+    // `absorption_field` and `scattering_field`, or `field` for either left out, stretched over
+    // the box. Absorbing volumes glow with `emission`, scaled by `emission_field` if there is
+    // one. The material is the phase function.
+    Volume {
+        min: Point,
+        max: Point,
+        field: Option<FieldDescription>,
+        #[serde(default)]
+        absorption: f64,
+        absorption_field: Option<FieldDescription>,
+        #[serde(default)]
+        scattering: f64,
+        scattering_field: Option<FieldDescription>,
+        emission: Option<Color>,
+        emission_field: Option<FieldDescription>,
+        material: String,
+    },
+    // Image or raw float heights, relative to the scene file, spread over `size` along x and z
+    // from `corner` and scaled by `size.y`.
+    Heightfield {
+        path: String,
+        resolution: Option<[usize; 2]>,
+        corner: Point,
+        size: Vec3,
+        material: String,
+    },
+    // Distance field sphere traced within the box from `min` to `max`. `lipschitz` must bound
+    // how fast the field changes, e.g. above 1 for twisted shapes or the Mandelbulb.
+    Sdf {
+        min: Point,
+        max: Point,
+        shape: ShapeDescription,
+        #[serde(default = "default_lipschitz")]
+        lipschitz: f64,
+        material: String,
+    },
+    // Cubic Bezier curve whose width goes from `widths[0]` to `widths[1]` along it.
+    Curve {
+        control_points: [Point; 4],
+        widths: [f64; 2],
+        #[serde(default = "default_curve_kind")]
+        kind: CurveKind,
+        material: String,
+    },
+    // Strand file, relative to the scene file, with one strand of `x y z width` vertices per
+    // line.
+    Strands {
+        path: String,
+        #[serde(default = "default_curve_kind")]
+        kind: CurveKind,
+        material: String,
+    },
+    // Every mesh in a glTF or GLB file, relative to the scene file, with the file's own
+    // materials. Its cameras are ignored.
+    Gltf {
+        path: String,
+    },
+    // Solid combination of two closed objects, each written like any other object.
+    Csg {
+        operation: CsgOperation,
+        left: toml::Value,
+        right: toml::Value,
+    },
+}
+
+// Distance field shapes. Combinations fold over `shapes` in order and blend over `smoothness`;
+// angles are in degrees.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ShapeDescription {
+    Sphere {
+        center: Point,
+        radius: f64,
+    },
+    Box {
+        center: Point,
+        size: Vec3,
+        #[serde(default)]
+        rounding: f64,
+    },
+    Torus {
+        center: Point,
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    Cylinder {
+        center: Point,
+        radius: f64,
+        height: f64,
+    },
+    Union {
+        shapes: Vec<ShapeDescription>,
+        #[serde(default)]
+        smoothness: f64,
+    },
+    Intersection {
+        shapes: Vec<ShapeDescription>,
+        #[serde(default)]
+        smoothness: f64,
+    },
+    Difference {
+        shapes: Vec<ShapeDescription>,
+        #[serde(default)]
+        smoothness: f64,
+    },
+    Repeat {
+        period: Vec3,
+        shape: Box<ShapeDescription>,
+    },
+    // Twists `shape` around the y axis by `angle` per unit of height.
+    Twist {
+        angle: f64,
+        shape: Box<ShapeDescription>,
+    },
+    Mandelbulb {
+        #[serde(default = "default_mandelbulb_power")]
+        power: f64,
+        #[serde(default = "default_mandelbulb_iterations")]
+        iterations: u32,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum FieldDescription {
+    // NRRD file, or raw little-endian floats with the given resolution, relative to the scene
+    // file.
+    Grid {
+        path: String,
+        resolution: Option<[usize; 3]>,
+    },
+    Noise {
+        #[serde(default = "default_texture_scale")]
+        frequency: f64,
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+    },
+}
+
+// Keys any object accepts on top of its own.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectPlacement {
+    // Steps applied in order to the object as described, before any motion.
+    #[serde(default)]
+    transform: Vec<TransformStep>,
+    // Distance the object travels over the frame.
+    motion: Option<Vec3>,
+    // Turns the object into the boundary of a medium with this density, which scatters with
+    // the object's material. The object must be closed.
+    density: Option<f64>,
+}
+
+// One step of an object's transform, written as a single-key table such as
+// `{ rotate_y = 30 }`. Angles are in degrees.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TransformStep {
+    Translate(Vec3),
+    Scale(ScaleFactors),
+    Rotate { axis: Vec3, angle: f64 },
+    RotateX(f64),
+    RotateY(f64),
+    RotateZ(f64),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "expected a number or a list of three numbers")]
+enum ScaleFactors {
+    Uniform(f64),
+    PerAxis(Vec3),
+}
+
+struct SceneLoader<'a> {
+    path: &'a Path,
+    source: &'a str,
+    texture_descriptions: &'a HashMap<String, Spanned<toml::Value>>,
+    material_descriptions: &'a HashMap<String, Spanned<toml::Value>>,
+    textures: HashMap<String, Arc<dyn Texture + Send + Sync>>,
+    materials: HashMap<String, Arc<dyn Material + Send + Sync>>,
+    // Loaded meshes by path and material name, so objects repeating a mesh share it.
+    meshes: HashMap<(PathBuf, String), Vec<Arc<dyn Hittable + Send + Sync>>>,
+    gltf_scenes: HashMap<PathBuf, Vec<Arc<dyn Hittable + Send + Sync>>>,
+}
+
+impl ObjectPlacement {
+    const KEYS: [&'static str; 3] = ["transform", "motion", "density"];
+
+    fn transform(&self) -> Result<Transform, String> {
+        let mut transform = Transform::identity();
+        for step in &self.transform {
+            let step = match step {
+                TransformStep::Translate(offset) => Transform::translate(offset),
+                TransformStep::Scale(factors) => {
+                    let factors = match factors {
+                        ScaleFactors::Uniform(factor) => Vec3::new(*factor, *factor, *factor),
+                        ScaleFactors::PerAxis(factors) => *factors,
+                    };
+                    if factors.e.contains(&0.0) {
+                        return Err("scale factors must be non-zero".to_string());
+                    }
+                    Transform::scale(&factors)
+                }
+                TransformStep::Rotate { axis, angle } => {
+                    if axis.length_squared() == 0.0 {
+                        return Err("rotation axis must be non-zero".to_string());
+                    }
+                    Transform::rotate(axis, *angle)
+                }
+                TransformStep::RotateX(angle) => {
+                    Transform::rotate(&Vec3::new(1.0, 0.0, 0.0), *angle)
+                }
+                TransformStep::RotateY(angle) => {
+                    Transform::rotate(&Vec3::new(0.0, 1.0, 0.0), *angle)
+                }
+                TransformStep::RotateZ(angle) => {
+                    Transform::rotate(&Vec3::new(0.0, 0.0, 1.0), *angle)
+                }
+            };
+            transform = transform.then(&step);
+        }
+        Ok(transform)
+    }
+}
+
+impl ObjectDescription {
+    fn material(&self) -> Option<&str> {
+        match self {
+            ObjectDescription::Sphere { material, .. }
+            | ObjectDescription::Triangle { material, .. }
+            | ObjectDescription::Mesh { material, .. }
+            | ObjectDescription::Plane { material, .. }
+            | ObjectDescription::Quad { material, .. }
+            | ObjectDescription::Disk { material, .. }
+            | ObjectDescription::Box { material, .. }
+            | ObjectDescription::Cylinder { material, .. }
+            | ObjectDescription::Cone { material, .. }
+            | ObjectDescription::Paraboloid { material, .. }
+            | ObjectDescription::Torus { material, .. }
+            | ObjectDescription::Volume { material, .. }
+            | ObjectDescription::Heightfield { material, .. }
+            | ObjectDescription::Sdf { material, .. }
+            | ObjectDescription::Curve { material, .. }
+            | ObjectDescription::Strands { material, .. } => Some(material),
+            ObjectDescription::Csg { .. } | ObjectDescription::Gltf { .. } => None,
+        }
+    }
+}
+
+impl CameraSettings {
+    // Swings `look_from` by `angle` radians around the vertical axis through `look_at`, keeping
+    // its height and distance.
+    pub fn orbit(&self, angle: f64) -> CameraSettings {
+        let offset = self.look_from - self.look_at;
+        let radius = (offset.x() * offset.x() + offset.z() * offset.z()).sqrt();
+        let start_angle = offset.z().atan2(offset.x());
+        let look_from = self.look_at
+            + Vec3::new(
+                radius * (start_angle + angle).cos(),
+                offset.y(),
+                radius * (start_angle + angle).sin(),
+            );
+        CameraSettings { look_from, ..*self }
+    }
+
+    pub fn build(&self, aspect_ratio: f64) -> Camera {
+        let focus_distance = self
+            .focus_distance
+            .unwrap_or_else(|| (self.look_from - self.look_at).length());
+        Camera::new(
+            &self.look_from,
+            &self.look_at,
+            &self.vup,
+            self.vfov,
+            aspect_ratio,
+            self.aperture,
+            focus_distance,
+        )
+        .with_shutter(self.shutter_open, self.shutter_close)
+    }
+}
+
+impl Default for RenderDescription {
+    fn default() -> Self {
+        let settings = RenderSettings::default();
+        RenderDescription {
+            width: settings.image_width,
+            height: settings.image_height,
+            samples_per_pixel: settings.samples_per_pixel,
+            max_depth: settings.max_depth,
+            tone_map: settings.tone_mapping.operator,
+            exposure: settings.tone_mapping.exposure,
+        }
+    }
+}
+
+fn default_vup() -> Vec3 {
+    Vec3::new(0.0, 1.0, 0.0)
+}
+
+fn default_texture_color() -> Color {
+    WHITE
+}
+
+fn default_texture_scale() -> f64 {
+    1.0
+}
+
+fn default_bump_scale() -> f64 {
+    0.01
+}
+
+fn default_phi_max() -> f64 {
+    360.0
+}
+
+fn default_capped() -> bool {
+    true
+}
+
+fn default_octaves() -> u32 {
+    7
+}
+
+fn default_hair_eta() -> f64 {
+    1.55
+}
+
+fn default_hair_roughness() -> f64 {
+    0.3
+}
+
+fn default_hair_alpha() -> f64 {
+    2.0
+}
+
+fn default_curve_kind() -> CurveKind {
+    CurveKind::Ribbon
+}
+
+fn default_lipschitz() -> f64 {
+    1.0
+}
+
+fn default_mandelbulb_power() -> f64 {
+    8.0
+}
+
+fn default_mandelbulb_iterations() -> u32 {
+    10
+}
+
+// Checks the dimensions shared by the surfaces of revolution.
+fn validate_revolution(radius: f64, height: f64, phi_max: f64) -> Result<(), String> {
+    if radius <= 0.0 || height <= 0.0 {
+        return Err("radius and height must be positive".to_string());
+    }
+    if phi_max <= 0.0 || phi_max > 360.0 {
+        return Err("phi_max must be in (0, 360]".to_string());
+    }
+    Ok(())
+}
+
+pub fn load_scene(path: &Path) -> io::Result<SceneFile> {
+    let is_gltf = path.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("gltf") || extension.eq_ignore_ascii_case("glb")
+    });
+    if is_gltf {
+        return load_gltf_scene(path);
+    }
+
+    let source = fs::read_to_string(path)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+    parse_scene(path, &source)
+}
+
+// Builds a scene from the TOML text of `path`, which relative mesh and image paths are resolved
+// against and errors are reported in.
+fn parse_scene(path: &Path, source: &str) -> io::Result<SceneFile> {
+    let description: SceneDescription = toml::from_str(source).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), err),
+        )
+    })?;
+
+    let mut loader = SceneLoader {
+        path,
+        source,
+        texture_descriptions: &description.textures,
+        material_descriptions: &description.materials,
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        meshes: HashMap::new(),
+        gltf_scenes: HashMap::new(),
+    };
+
+    let render_offset = description
+        .render
+        .as_ref()
+        .map_or(0, |render| render.span().start);
+    let render = description
+        .render
+        .map_or_else(RenderDescription::default, Spanned::into_inner);
+    let settings = RenderSettings {
+        image_width: render.width,
+        image_height: render.height,
+        samples_per_pixel: render.samples_per_pixel,
+        max_depth: render.max_depth,
+        tone_mapping: ToneMapping {
+            operator: render.tone_map,
+            exposure: render.exposure,
+        },
+        ..RenderSettings::default()
+    };
+    settings
+        .validate()
+        .map_err(|msg| loader.error(render_offset, &format!("render: {}", msg)))?;
+
+    let background = match &description.background {
+        None => Background::Sky,
+        Some(value) => match loader.parse::<BackgroundDescription>(value, "background")? {
+            BackgroundDescription::Sky => Background::Sky,
+            BackgroundDescription::Solid { color } => Background::Solid(color),
+        },
+    };
+
+    let mut texture_names: Vec<&String> = description.textures.keys().collect();
+    texture_names.sort();
+    for name in texture_names {
+        loader.build_texture(name, &mut Vec::new())?;
+    }
+
+    let mut material_names: Vec<&String> = description.materials.keys().collect();
+    material_names.sort();
+    for name in material_names {
+        loader.build_material(name, &mut Vec::new())?;
+    }
+
+    let mut world = HittableCollection::new();
+    for (idx, value) in description.objects.iter().enumerate() {
+        let context = format!("objects[{}]", idx);
+        let (object, placement) = loader.parse_object(value, &context)?;
+        loader.add_object(&mut world, object, &placement, value.span().start, &context)?;
+    }
+
+    Ok(SceneFile {
+        scene: Scene::new(world, background),
+        camera: description.camera,
+        settings,
+    })
+}
+
+// A glTF file rendered on its own, under the sky: through its first camera, or otherwise from
+// in front of everything in it.
+fn load_gltf_scene(path: &Path) -> io::Result<SceneFile> {
+    let gltf = load_gltf(path)?;
+    let mut world = HittableCollection::new();
+    for hittable in gltf.hittables {
+        world.add(hittable);
+    }
+    let bounds = world.bounding_box().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: no meshes to render", path.display()),
+        )
+    })?;
+
+    let mut settings = RenderSettings::default();
+    let camera = match gltf.cameras.first() {
+        Some((camera, aspect_ratio)) => {
+            if let Some(aspect_ratio) = aspect_ratio {
+                settings.image_height =
+                    ((settings.image_width as f64 / aspect_ratio).round() as u32).max(1);
+            }
+            *camera
+        }
+        None => {
+            let vfov: f64 = 40.0;
+            let look_at = bounds.centroid();
+            let radius = 0.5 * bounds.diagonal().length();
+            let distance = radius / degrees_to_radians(0.5 * vfov).sin();
+            CameraSettings {
+                look_from: look_at + Vec3::new(0.0, 0.3, 1.0) * (distance / 1.09f64.sqrt()),
+                look_at,
+                vup: default_vup(),
+                vfov,
+                aperture: 0.0,
+                focus_distance: None,
+                shutter_open: 0.0,
+                shutter_close: 0.0,
+            }
+        }
+    };
+
+    Ok(SceneFile {
+        scene: Scene::new(world, Background::Sky),
+        camera,
+        settings,
+    })
+}
+
+impl<'a> SceneLoader<'a> {
+    fn error(&self, offset: usize, msg: &str) -> io::Error {
+        let (line, column) = line_and_column(self.source, offset);
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}:{}:{}: {}", self.path.display(), line, column, msg),
+        )
+    }
+
+    fn parse<T: DeserializeOwned>(
+        &self,
+        value: &Spanned<toml::Value>,
+        context: &str,
+    ) -> io::Result<T> {
+        value.get_ref().clone().try_into().map_err(|err| {
+            self.error(
+                value.span().start,
+                &format!("{}: {}", context, err.message()),
+            )
+        })
+    }
+
+    fn material(
+        &self,
+        name: &str,
+        offset: usize,
+        context: &str,
+    ) -> io::Result<Arc<dyn Material + Send + Sync>> {
+        self.materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| self.error(offset, &format!("{}: unknown material '{}'", context, name)))
+    }
+
+    // Builds the named texture after any textures it refers to. `pending` holds the textures
+    // currently being built, to catch cycles.
+    fn build_texture(&mut self, name: &str, pending: &mut Vec<String>) -> io::Result<()> {
+        if self.textures.contains_key(name) {
+            return Ok(());
+        }
+        let value = &self.texture_descriptions[name];
+        let offset = value.span().start;
+        let context = format!("textures.{}", name);
+        if pending.iter().any(|pending_name| pending_name == name) {
+            return Err(self.error(
+                offset,
+                &format!("{}: texture refers back to itself", context),
+            ));
+        }
+        pending.push(name.to_string());
+
+        let texture: Arc<dyn Texture + Send + Sync> = match self.parse(value, &context)? {
+            TextureDescription::Checker { even, odd, scale } => {
+                for reference in [&even, &odd] {
+                    if let TextureReference::Name(dependency) = reference {
+                        if self.texture_descriptions.contains_key(dependency) {
+                            self.build_texture(dependency, pending)?;
+                        }
+                    }
+                }
+                Arc::new(CheckerTexture {
+                    even: self.texture(&even, offset, &context)?,
+                    odd: self.texture(&odd, offset, &context)?,
+                    scale,
+                })
+            }
+            TextureDescription::Image { path, linear } => {
+                let image_path = self.resolve_path(&path);
+                Arc::new(ImageTexture::load(&image_path, !linear).map_err(|err| {
+                    self.error(
+                        offset,
+                        &format!("{}: could not load image: {}", context, err),
+                    )
+                })?)
+            }
+            TextureDescription::Noise { color, scale } => Arc::new(NoiseTexture::new(color, scale)),
+            TextureDescription::Marble { color, scale } => {
+                Arc::new(MarbleTexture::new(color, scale))
+            }
+        };
+
+        pending.pop();
+        self.textures.insert(name.to_string(), texture);
+        Ok(())
+    }
+
+    fn texture(
+        &self,
+        reference: &TextureReference,
+        offset: usize,
+        context: &str,
+    ) -> io::Result<Arc<dyn Texture + Send + Sync>> {
+        match reference {
+            TextureReference::Color(color) => Ok(Arc::new(SolidColor::new(*color))),
+            TextureReference::Name(name) => self.textures.get(name).cloned().ok_or_else(|| {
+                self.error(offset, &format!("{}: unknown texture '{}'", context, name))
+            }),
+        }
+    }
+
+    // Builds the named material after any materials it wraps, the same way as textures.
+    fn build_material(&mut self, name: &str, pending: &mut Vec<String>) -> io::Result<()> {
+        if self.materials.contains_key(name) {
+            return Ok(());
+        }
+        let value = &self.material_descriptions[name];
+        let offset = value.span().start;
+        let context = format!("materials.{}", name);
+        if pending.iter().any(|pending_name| pending_name == name) {
+            return Err(self.error(
+                offset,
+                &format!("{}: material refers back to itself", context),
+            ));
+        }
+        pending.push(name.to_string());
+
+        let description: MaterialDescription = self.parse(value, &context)?;
+        if let MaterialDescription::Bump { material, .. }
+        | MaterialDescription::NormalMap { material, .. } = &description
+        {
+            if self.material_descriptions.contains_key(material) {
+                self.build_material(material, pending)?;
+            }
+        }
+
+        let material: Arc<dyn Material + Send + Sync> = match description {
+            MaterialDescription::Lambertian { albedo } => Arc::new(LambertianMaterial {
+                albedo: self.texture(&albedo, offset, &context)?,
+            }),
+            MaterialDescription::Metal { albedo, fuzziness } => Arc::new(MetalMaterial {
+                albedo: self.texture(&albedo, offset, &context)?,
+                fuzziness,
+            }),
+            MaterialDescription::Dielectric { ref_idx, albedo } => Arc::new(DiaelectriMaterial {
+                ref_idx,
+                albedo: self.texture(
+                    &albedo.unwrap_or(TextureReference::Color(WHITE)),
+                    offset,
+                    &context,
+                )?,
+            }),
+            MaterialDescription::DiffuseLight { emit, two_sided } => {
+                Arc::new(DiffuseLightMaterial {
+                    emit: self.texture(&emit, offset, &context)?,
+                    two_sided,
+                })
+            }
+            MaterialDescription::Bump {
+                material,
+                height,
+                scale,
+            } => Arc::new(PerturbedMaterial {
+                material: self.material(&material, offset, &context)?,
+                perturbation: BumpMap {
+                    height: self.texture(&height, offset, &context)?,
+                    scale,
+                },
+            }),
+            MaterialDescription::NormalMap { material, normals } => Arc::new(PerturbedMaterial {
+                material: self.material(&material, offset, &context)?,
+                perturbation: NormalMap {
+                    normals: self.texture(&normals, offset, &context)?,
+                },
+            }),
+            MaterialDescription::Isotropic { albedo } => Arc::new(IsotropicMaterial {
+                albedo: self.texture(&albedo, offset, &context)?,
+            }),
+            MaterialDescription::HenyeyGreenstein { albedo, g } => {
+                if g <= -1.0 || g >= 1.0 {
+                    return Err(self.error(offset, &format!("{}: g must be in (-1, 1)", context)));
+                }
+                Arc::new(HenyeyGreensteinMaterial {
+                    albedo: self.texture(&albedo, offset, &context)?,
+                    g,
+                })
+            }
+            MaterialDescription::Hair {
+                color,
+                eumelanin,
+                pheomelanin,
+                sigma_a,
+                eta,
+                beta_m,
+                beta_n,
+                alpha,
+            } => {
+                if beta_m <= 0.0 || beta_n <= 0.0 || beta_m > 1.0 || beta_n > 1.0 || eta <= 0.0 {
+                    return Err(self.error(
+                        offset,
+                        &format!(
+                            "{}: beta_m and beta_n must be in (0, 1] and eta positive",
+                            context
+                        ),
+                    ));
+                }
+                let melanin = eumelanin.is_some() || pheomelanin.is_some();
+                let sigma_a = match (color, melanin, sigma_a) {
+                    (Some(color), false, None) => HairMaterial::sigma_a_from_color(&color, beta_n),
+                    (None, true, None) => HairMaterial::sigma_a_from_melanin(
+                        eumelanin.unwrap_or(0.0),
+                        pheomelanin.unwrap_or(0.0),
+                    ),
+                    (None, false, Some(sigma_a)) => sigma_a,
+                    _ => {
+                        return Err(self.error(
+                            offset,
+                            &format!(
+                                "{}: expected exactly one of color, eumelanin and pheomelanin, \
+                                 or sigma_a",
+                                context
+                            ),
+                        ))
+                    }
+                };
+                Arc::new(HairMaterial {
+                    sigma_a,
+                    eta,
+                    beta_m,
+                    beta_n,
+                    alpha,
+                })
+            }
+        };
+
+        pending.pop();
+        self.materials.insert(name.to_string(), material);
+        Ok(())
+    }
+
+    fn is_phase_function(&self, name: &str) -> bool {
+        self.material_descriptions.get(name).is_some_and(|value| {
+            matches!(
+                value.get_ref().get("type").and_then(toml::Value::as_str),
+                Some("isotropic" | "henyey_greenstein")
+            )
+        })
+    }
+
+    fn density_field(
+        &self,
+        field: FieldDescription,
+        offset: usize,
+        context: &str,
+    ) -> io::Result<Arc<dyn DensityField + Send + Sync>> {
+        match field {
+            FieldDescription::Grid { path, resolution } => {
+                let grid =
+                    DensityGrid::load(&self.resolve_path(&path), resolution).map_err(|err| {
+                        self.error(
+                            offset,
+                            &format!("{}: could not load density grid: {}", context, err),
+                        )
+                    })?;
+                Ok(Arc::new(grid))
+            }
+            FieldDescription::Noise { frequency, octaves } => {
+                if frequency <= 0.0 || octaves == 0 {
+                    return Err(self.error(
+                        offset,
+                        &format!("{}: noise frequency and octaves must be positive", context),
+                    ));
+                }
+                Ok(Arc::new(NoiseDensity::new(frequency, octaves)))
+            }
+        }
+    }
+
+    // Paths in the scene file are relative to the scene file.
+    fn resolve_path(&self, path: &str) -> PathBuf {
+        self.path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(path)
+    }
+
+    // Splits the placement keys off an object table, so each half can be parsed strictly.
+    fn parse_object(
+        &self,
+        value: &Spanned<toml::Value>,
+        context: &str,
+    ) -> io::Result<(ObjectDescription, ObjectPlacement)> {
+        let mut object = value.get_ref().clone();
+        let mut placement = toml::value::Table::new();
+        if let Some(table) = object.as_table_mut() {
+            for key in ObjectPlacement::KEYS {
+                if let Some(field) = table.remove(key) {
+                    placement.insert(key.to_string(), field);
+                }
+            }
+        }
+        let span = value.span();
+        Ok((
+            self.parse(&Spanned::new(span.clone(), object), context)?,
+            self.parse(&Spanned::new(span, toml::Value::Table(placement)), context)?,
+        ))
+    }
+
+    // Builds an operand of a CSG object from its table, which has no span of its own, so errors
+    // point at the CSG object.
+    fn csg_operand(
+        &mut self,
+        value: toml::Value,
+        offset: usize,
+        context: &str,
+    ) -> io::Result<Arc<dyn Hittable + Send + Sync>> {
+        let (object, placement) =
+            self.parse_object(&Spanned::new(offset..offset, value), context)?;
+        let mut operand = HittableCollection::new();
+        self.add_object(&mut operand, object, &placement, offset, context)?;
+        if !operand.media().hittables.is_empty() {
+            return Err(self.error(
+                offset,
+                &format!("{}: csg operands cannot be media", context),
+            ));
+        }
+        Ok(match operand.hittables.len() {
+            1 => operand.hittables.pop().unwrap(),
+            _ => Arc::new(operand),
+        })
+    }
+
+    fn add_object(
+        &mut self,
+        world: &mut HittableCollection,
+        object: ObjectDescription,
+        placement: &ObjectPlacement,
+        offset: usize,
+        context: &str,
+    ) -> io::Result<()> {
+        let transform = placement
+            .transform()
+            .map_err(|msg| self.error(offset, &format!("{}: transform: {}", context, msg)))?;
+        let phase_function = match placement.density {
+            Some(density) => {
+                if density <= 0.0 {
+                    return Err(
+                        self.error(offset, &format!("{}: density must be positive", context))
+                    );
+                }
+                let name = object.material().ok_or_else(|| {
+                    self.error(
+                        offset,
+                        &format!("{}: csg and gltf objects cannot fill a medium", context),
+                    )
+                })?;
+                if !self.is_phase_function(name) {
+                    return Err(self.error(
+                        offset,
+                        &format!(
+                            "{}: material '{}' must be isotropic or henyey_greenstein to fill \
+                             a medium",
+                            context, name
+                        ),
+                    ));
+                }
+                if matches!(object, ObjectDescription::Volume { .. }) {
+                    return Err(
+                        self.error(offset, &format!("{}: volumes take no density", context))
+                    );
+                }
+                Some(self.material(name, offset, context)?)
+            }
+            None => None,
+        };
+        // Spheres move on their own; everything else is wrapped.
+        let moves_itself =
+            matches!(object, ObjectDescription::Sphere { .. }) && placement.transform.is_empty();
+
+        let mut hittables: Vec<Arc<dyn Hittable + Send + Sync>> = vec![];
+        match object {
+            ObjectDescription::Sphere {
+                center,
+                radius,
+                material,
+            } => {
+                let material = self.material(&material, offset, context)?;
+                match placement.motion {
+                    Some(motion) if moves_itself => hittables.push(Arc::new(MovingSphere::new(
+                        &center,
+                        &(center + motion),
+                        radius,
+                        material,
+                    ))),
+                    _ => hittables.push(Arc::new(Sphere::new(&center, radius, material))),
+                }
+            }
+            ObjectDescription::Triangle { vertices, material } => {
+                let material = self.material(&material, offset, context)?;
+                hittables.push(Arc::new(Triangle::from_vertices(
+                    &vertices[0],
+                    &vertices[1],
+                    &vertices[2],
+                    material,
+                )));
+            }
+            ObjectDescription::Mesh { path, material } => {
+                let mesh_path = self.resolve_path(&path);
+                let key = (mesh_path, material);
+                if !self.meshes.contains_key(&key) {
+                    let material = self.material(&key.1, offset, context)?;
+                    let extension = key
+                        .0
+                        .extension()
+                        .and_then(|extension| extension.to_str())
+                        .unwrap_or("")
+                        .to_ascii_lowercase();
+                    let meshes = match extension.as_str() {
+                        "ply" => load_ply(&key.0, material).map(|mesh| vec![mesh]),
+                        "stl" => load_stl(&key.0, material).map(|mesh| vec![mesh]),
+                        _ => load_obj(&key.0, material),
+                    }
+                    .map_err(|err| {
+                        self.error(
+                            offset,
+                            &format!("{}: could not load mesh: {}", context, err),
+                        )
+                    })?;
+                    let meshes = meshes
+                        .into_iter()
+                        .map(|mesh| Arc::new(mesh) as Arc<dyn Hittable + Send + Sync>)
+                        .collect();
+                    self.meshes.insert(key.clone(), meshes);
+                }
+                hittables.extend(self.meshes[&key].iter().cloned());
+            }
+            ObjectDescription::Plane {
+                point,
+                normal,
+                material,
+            } => {
+                if normal.length_squared() == 0.0 {
+                    return Err(
+                        self.error(offset, &format!("{}: normal must be non-zero", context))
+                    );
+                }
+                let material = self.material(&material, offset, context)?;
+                hittables.push(Arc::new(Plane::new(&point, &normal, material)));
+            }
+            ObjectDescription::Quad {
+                corner,
+                u,
+                v,
+                material,
+            } => {
+                if cross_product(&u, &v).length_squared() == 0.0 {
+                    return Err(self.error(
+                        offset,
+                        &format!("{}: edges must be non-zero and not parallel", context),
+                    ));
+                }
+                let material = self.material(&material, offset, context)?;
+                hittables.push(Arc::new(Quad::new(&corner, &u, &v, material)));
+            }
+            ObjectDescription::Disk {
+                center,
+                normal,
+                radius,
+                material,
+            } => {
+                if normal.length_squared() == 0.0 {
+                    return Err(
+                        self.error(offset, &format!("{}: normal must be non-zero", context))
+                    );
+                }
+                if radius <= 0.0 {
+                    return Err(
+                        self.error(offset, &format!("{}: radius must be positive", context))
+                    );
+                }
+                let material = self.material(&material, offset, context)?;
+                hittables.push(Arc::new(Disk::new(&center, &normal, radius, material)));
+            }
+            ObjectDescription::Box { min, max, material } => {
+                let material = self.material(&material, offset, context)?;
+                hittables.push(Arc::new(Cuboid::new(&min, &max, material)));
+            }
+            ObjectDescription::Cylinder {
+                base,
+                radius,
+                height,
+                phi_max,
+                capped,
+                material,
+            } => {
+                validate_revolution(radius, height, phi_max)
+                    .map_err(|msg| self.error(offset, &format!("{}: {}", context, msg)))?;
+                hittables.push(Arc::new(Cylinder {
+                    base,
+                    radius,
+                    height,
+                    phi_max: degrees_to_radians(phi_max),
+                    capped,
+                    material: self.material(&material, offset, context)?,
+                }));
+            }
+            ObjectDescription::Cone {
+                base,
+                radius,
+                height,
+                phi_max,
+                capped,
+                material,
+            } => {
+                validate_revolution(radius, height, phi_max)
+                    .map_err(|msg| self.error(offset, &format!("{}: {}", context, msg)))?;
+                hittables.push(Arc::new(Cone {
+                    base,
+                    radius,
+                    height,
+                    phi_max: degrees_to_radians(phi_max),
+                    capped,
+                    material: self.material(&material, offset, context)?,
+                }));
+            }
+            ObjectDescription::Paraboloid {
+                base,
+                radius,
+                height,
+                phi_max,
+                capped,
+                material,
+            } => {
+                validate_revolution(radius, height, phi_max)
+                    .map_err(|msg| self.error(offset, &format!("{}: {}", context, msg)))?;
+                hittables.push(Arc::new(Paraboloid {
+                    base,
+                    radius,
+                    height,
+                    phi_max: degrees_to_radians(phi_max),
+                    capped,
+                    material: self.material(&material, offset, context)?,
+                }));
+            }
+            ObjectDescription::Torus {
+                center,
+                major_radius,
+                minor_radius,
+                phi_max,
+                material,
+            } => {
+                if minor_radius <= 0.0 || minor_radius >= major_radius {
+                    return Err(self.error(
+                        offset,
+                        &format!(
+                            "{}: minor_radius must be positive and less than major_radius",
+                            context
+                        ),
+                    ));
+                }
+                validate_revolution(major_radius, minor_radius, phi_max)
+                    .map_err(|msg| self.error(offset, &format!("{}: {}", context, msg)))?;
+                hittables.push(Arc::new(Torus {
+                    center,
+                    major_radius,
+                    minor_radius,
+                    phi_max: degrees_to_radians(phi_max),
+                    material: self.material(&material, offset, context)?,
+                }));
+            }
+            ObjectDescription::Volume {
+                min,
+                max,
+                field,
+                absorption,
+                absorption_field,
+                scattering,
+                scattering_field,
+                emission,
+                emission_field,
+                material,
+            } => {
+                if (0..3).any(|axis| min.e[axis] >= max.e[axis]) {
+                    return Err(self.error(
+                        offset,
+                        &format!("{}: min must be below max on every axis", context),
+                    ));
+                }
+                if absorption < 0.0 || scattering < 0.0 || absorption + scattering <= 0.0 {
+                    return Err(self.error(
+                        offset,
+                        &format!(
+                            "{}: absorption and scattering must not be negative, and one must \
+                             be positive",
+                            context
+                        ),
+                    ));
+                }
+                if !self.is_phase_function(&material) {
+                    return Err(self.error(
+                        offset,
+                        &format!(
+                            "{}: material '{}' must be isotropic or henyey_greenstein",
+                            context, material
+                        ),
+                    ));
+                }
+                let density_field = |field: Option<FieldDescription>| match field {
+                    Some(field) => self.density_field(field, offset, context).map(Some),
+                    None => Ok(None),
+                };
+                let field = density_field(field)?;
+                let absorption_field = density_field(absorption_field)?.or_else(|| field.clone());
+                let scattering_field = density_field(scattering_field)?.or_else(|| field.clone());
+                let emission_field = density_field(emission_field)?;
+                // A coefficient of zero needs no field of its own.
+                let (absorption_field, scattering_field) =
+                    match (absorption_field, scattering_field) {
+                        (Some(absorption_field), Some(scattering_field)) => {
+                            (absorption_field, scattering_field)
+                        }
+                        (Some(field), None) if scattering == 0.0 => (field.clone(), field),
+                        (None, Some(field)) if absorption == 0.0 => (field.clone(), field),
+                        _ => {
+                            return Err(self.error(
+                                offset,
+                                &format!(
+                                    "{}: absorption and scattering each need a field, or a shared \
+                                 'field'",
+                                    context
+                                ),
+                            ))
+                        }
+                    };
+                hittables.push(Arc::new(HeterogeneousMedium::new(
+                    Aabb::new(min, max),
+                    absorption_field,
+                    absorption,
+                    scattering_field,
+                    scattering,
+                    emission.unwrap_or(BLACK),
+                    emission_field,
+                    self.material(&material, offset, context)?,
+                )));
+            }
+            ObjectDescription::Heightfield {
+                path,
+                resolution,
+                corner,
+                size,
+                material,
+            } => {
+                if size.x() <= 0.0 || size.z() <= 0.0 {
+                    return Err(self.error(
+                        offset,
+                        &format!("{}: size must be positive along x and z", context),
+                    ));
+                }
+                let grid =
+                    HeightGrid::load(&self.resolve_path(&path), resolution).map_err(|err| {
+                        self.error(
+                            offset,
+                            &format!("{}: could not load heights: {}", context, err),
+                        )
+                    })?;
+                let material = self.material(&material, offset, context)?;
+                hittables.push(Arc::new(Heightfield::new(&grid, &corner, &size, material)));
+            }
+            ObjectDescription::Sdf {
+                min,
+                max,
+                shape,
+                lipschitz,
+                material,
+            } => {
+                if (0..3).any(|axis| min.e[axis] >= max.e[axis]) {
+                    return Err(self.error(
+                        offset,
+                        &format!("{}: min must be below max on every axis", context),
+                    ));
+                }
+                if lipschitz <= 0.0 {
+                    return Err(
+                        self.error(offset, &format!("{}: lipschitz must be positive", context))
+                    );
+                }
+                let field = build_shape(shape)
+                    .map_err(|msg| self.error(offset, &format!("{}: shape: {}", context, msg)))?;
+                hittables.push(Arc::new(Sdf {
+                    field,
+                    bounds: Aabb::new(min, max),
+                    lipschitz,
+                    material: self.material(&material, offset, context)?,
+                }));
+            }
+            ObjectDescription::Curve {
+                control_points,
+                widths,
+                kind,
+                material,
+            } => {
+                if widths[0] <= 0.0 || widths[1] <= 0.0 {
+                    return Err(
+                        self.error(offset, &format!("{}: widths must be positive", context))
+                    );
+                }
+                hittables.push(Arc::new(Curve {
+                    control_points,
+                    widths,
+                    kind,
+                    material: self.material(&material, offset, context)?,
+                }));
+            }
+            ObjectDescription::Strands {
+                path,
+                kind,
+                material,
+            } => {
+                let material = self.material(&material, offset, context)?;
+                let curves =
+                    load_strands(&self.resolve_path(&path), kind, material).map_err(|err| {
+                        self.error(
+                            offset,
+                            &format!("{}: could not load strands: {}", context, err),
+                        )
+                    })?;
+                // Strand files hold many curves, so they get a hierarchy of their own that
+                // instances can share, like meshes.
+                let mut collection = HittableCollection::new();
+                for curve in curves {
+                    collection.add(Arc::new(curve));
+                }
+                hittables.push(Arc::new(Bvh::new(collection)));
+            }
+            ObjectDescription::Gltf { path } => {
+                let gltf_path = self.resolve_path(&path);
+                if !self.gltf_scenes.contains_key(&gltf_path) {
+                    let gltf = load_gltf(&gltf_path).map_err(|err| {
+                        self.error(
+                            offset,
+                            &format!("{}: could not load gltf: {}", context, err),
+                        )
+                    })?;
+                    self.gltf_scenes.insert(gltf_path.clone(), gltf.hittables);
+                }
+                hittables.extend(self.gltf_scenes[&gltf_path].iter().cloned());
+            }
+            ObjectDescription::Csg {
+                operation,
+                left,
+                right,
+            } => {
+                let left = self.csg_operand(left, offset, &format!("{}.left", context))?;
+                let right = self.csg_operand(right, offset, &format!("{}.right", context))?;
+                hittables.push(Arc::new(Csg {
+                    operation,
+                    left,
+                    right,
+                }));
+            }
+        }
+
+        for mut hittable in hittables {
+            if !placement.transform.is_empty() {
+                hittable = Arc::new(Instance::new(hittable, transform));
+            }
+            // Outside any transform, so the density is per unit of world space.
+            if let (Some(density), Some(phase_function)) = (placement.density, &phase_function) {
+                hittable = Arc::new(ConstantMedium {
+                    boundary: hittable,
+                    density,
+                    phase_function: phase_function.clone(),
+                });
+            }
+            match placement.motion {
+                Some(offset) if !moves_itself => world.add(Arc::new(Moving { hittable, offset })),
+                _ => world.add(hittable),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn build_shape(shape: ShapeDescription) -> Result<Arc<dyn DistanceField + Send + Sync>, String> {
+    let combine = |operation, shapes: Vec<ShapeDescription>, smoothness: f64| {
+        if smoothness < 0.0 {
+            return Err("smoothness must not be negative".to_string());
+        }
+        let mut shapes = shapes.into_iter();
+        let first = shapes
+            .next()
+            .ok_or_else(|| "combinations need at least one shape".to_string())?;
+        shapes.try_fold(build_shape(first)?, |left, right| {
+            Ok(Arc::new(SdfCombination {
+                operation,
+                left,
+                right: build_shape(right)?,
+                smoothness,
+            }) as Arc<dyn DistanceField + Send + Sync>)
+        })
+    };
+    Ok(match shape {
+        ShapeDescription::Sphere { center, radius } => {
+            if radius <= 0.0 {
+                return Err("radius must be positive".to_string());
+            }
+            Arc::new(SdfSphere { center, radius })
+        }
+        ShapeDescription::Box {
+            center,
+            size,
+            rounding,
+        } => {
+            let half_size = size * 0.5;
+            if half_size.e.iter().any(|&half| half <= 0.0) {
+                return Err("size must be positive".to_string());
+            }
+            if rounding < 0.0 || half_size.e.iter().any(|&half| rounding > half) {
+                return Err("rounding must be between zero and half the size".to_string());
+            }
+            Arc::new(SdfBox {
+                center,
+                half_size,
+                rounding,
+            })
+        }
+        ShapeDescription::Torus {
+            center,
+            major_radius,
+            minor_radius,
+        } => {
+            if minor_radius <= 0.0 || major_radius <= 0.0 {
+                return Err("radii must be positive".to_string());
+            }
+            Arc::new(SdfTorus {
+                center,
+                major_radius,
+                minor_radius,
+            })
+        }
+        ShapeDescription::Cylinder {
+            center,
+            radius,
+            height,
+        } => {
+            if radius <= 0.0 || height <= 0.0 {
+                return Err("radius and height must be positive".to_string());
+            }
+            Arc::new(SdfCylinder {
+                center,
+                radius,
+                half_height: height * 0.5,
+            })
+        }
+        ShapeDescription::Union { shapes, smoothness } => {
+            combine(CsgOperation::Union, shapes, smoothness)?
+        }
+        ShapeDescription::Intersection { shapes, smoothness } => {
+            combine(CsgOperation::Intersection, shapes, smoothness)?
+        }
+        ShapeDescription::Difference { shapes, smoothness } => {
+            combine(CsgOperation::Difference, shapes, smoothness)?
+        }
+        ShapeDescription::Repeat { period, shape } => {
+            if period.e.iter().any(|&period| period < 0.0) {
+                return Err("period must not be negative".to_string());
+            }
+            Arc::new(SdfRepetition {
+                field: build_shape(*shape)?,
+                period,
+            })
+        }
+        ShapeDescription::Twist { angle, shape } => Arc::new(SdfTwist {
+            field: build_shape(*shape)?,
+            rate: degrees_to_radians(angle),
+        }),
+        ShapeDescription::Mandelbulb { power, iterations } => {
+            if power < 2.0 || iterations == 0 {
+                return Err("power must be at least 2 and iterations positive".to_string());
+            }
+            Arc::new(Mandelbulb { power, iterations })
+        }
+    })
+}
+
+// 1-based line and column of a byte offset into `source`.
+fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |idx| idx + 1) + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::error_message;
+
+    const CAMERA: &str = "[camera]\nlook_from = [0, 0, 5]\nlook_at = [0, 0, 0]\nvfov = 40\n";
+
+    fn scene_error(body: &str) -> String {
+        let source = format!("{}{}", CAMERA, body);
+        error_message(parse_scene(Path::new("test.toml"), &source))
+    }
+
+    #[test]
+    fn line_and_column_count_from_one() {
+        let source = "ab\ncd\n\nef";
+        assert_eq!(line_and_column(source, 0), (1, 1));
+        assert_eq!(line_and_column(source, 1), (1, 2));
+        assert_eq!(line_and_column(source, 3), (2, 1));
+        assert_eq!(line_and_column(source, 7), (4, 1));
+        assert_eq!(line_and_column(source, 100), (4, 3));
+    }
+
+    #[test]
+    fn minimal_scene() {
+        let source = format!(
+            "{}[materials.red]\ntype = \"lambertian\"\nalbedo = [1, 0, 0]\n\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"red\"\n",
+            CAMERA
+        );
+        let scene = parse_scene(Path::new("test.toml"), &source).unwrap();
+        assert_eq!(scene.scene.world.num_hittables(), 1);
+        assert_eq!(scene.camera.vfov, 40.0);
+    }
+
+    #[test]
+    fn unknown_material_reports_file_line_and_column() {
+        // Errors in an object point at the start of its table.
+        let message = scene_error(
+            "\n[materials.red]\ntype = \"lambertian\"\nalbedo = [1, 0, 0]\n\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"blue\"\n",
+        );
+        assert_eq!(
+            message,
+            "test.toml:10:1: objects[0]: unknown material 'blue'"
+        );
+    }
+
+    #[test]
+    fn invalid_material_reports_its_table() {
+        let message = scene_error("[materials.shiny]\ntype = \"chrome\"\n");
+        assert!(message.starts_with("test.toml:5:1: "), "{}", message);
+        assert!(message.contains("materials.shiny"), "{}", message);
+    }
+
+    #[test]
+    fn volumes_take_a_field_for_each_coefficient() {
+        let volume = |fields: &str| {
+            format!(
+                "[materials.fog]\ntype = \"isotropic\"\nalbedo = [1, 1, 1]\n\n\
+                 [[objects]]\ntype = \"volume\"\nmin = [0, 0, 0]\nmax = [1, 1, 1]\n\
+                 material = \"fog\"\n{}",
+                fields
+            )
+        };
+        let noise = "{ type = \"noise\", frequency = 2, octaves = 1 }";
+        for fields in [
+            format!("field = {}\nabsorption = 1\nscattering = 1\n", noise),
+            format!("absorption_field = {}\nabsorption = 1\n", noise),
+            format!(
+                "field = {0}\nscattering_field = {0}\nabsorption = 1\nscattering = 1\n",
+                noise
+            ),
+        ] {
+            let source = format!("{}{}", CAMERA, volume(&fields));
+            let scene = parse_scene(Path::new("test.toml"), &source).unwrap();
+            assert_eq!(scene.scene.world.num_hittables(), 1);
+        }
+
+        let message = scene_error(&volume(&format!(
+            "absorption_field = {}\nabsorption = 1\nscattering = 1\n",
+            noise
+        )));
+        assert_eq!(
+            message,
+            "test.toml:9:1: objects[0]: absorption and scattering each need a field, or a shared \
+             'field'"
+        );
+    }
+
+    #[test]
+    fn invalid_render_settings_report_the_render_table() {
+        let message = scene_error("\n[render]\nwidth = 0\n");
+        assert!(message.starts_with("test.toml:6:1: "), "{}", message);
+        assert!(message.contains("render: "), "{}", message);
+    }
+}
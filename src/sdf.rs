@@ -0,0 +1,450 @@
+use crate::csg::CsgOperation;
+use crate::math::{to_unit_vector, Aabb, Point, Ray, Vec3};
+use crate::trace::{HitRecord, Hittable, Material};
+use std::sync::Arc;
+
+// Signed distance to a surface, negative inside. Fields built from others may only bound the
+// distance from below after scaling by a Lipschitz bound, which `Sdf` takes from the user.
+pub trait DistanceField {
+    fn distance(&self, point: &Point) -> f64;
+}
+
+pub struct SdfSphere {
+    pub center: Point,
+    pub radius: f64,
+}
+
+// Box with half its size along each axis in `half_size`, its edges rounded by `rounding`.
+pub struct SdfBox {
+    pub center: Point,
+    pub half_size: Vec3,
+    pub rounding: f64,
+}
+
+// Lies in the xz plane.
+pub struct SdfTorus {
+    pub center: Point,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+}
+
+// Capped, along the y axis.
+pub struct SdfCylinder {
+    pub center: Point,
+    pub radius: f64,
+    pub half_height: f64,
+}
+
+// Blends the two fields over a distance of `smoothness`, or combines them sharply at zero.
+pub struct SdfCombination {
+    pub operation: CsgOperation,
+    pub left: Arc<dyn DistanceField + Send + Sync>,
+    pub right: Arc<dyn DistanceField + Send + Sync>,
+    pub smoothness: f64,
+}
+
+// Copies of a field centered on the origin every `period` along each axis, or once along axes
+// with a zero period. Copies should not reach outside their cell.
+pub struct SdfRepetition {
+    pub field: Arc<dyn DistanceField + Send + Sync>,
+    pub period: Vec3,
+}
+
+// Turns a field around the y axis by `rate` radians per unit of height.
+pub struct SdfTwist {
+    pub field: Arc<dyn DistanceField + Send + Sync>,
+    pub rate: f64,
+}
+
+// The power `power` Mandelbulb at the origin, reaching out to about 1.2.
+pub struct Mandelbulb {
+    pub power: f64,
+    pub iterations: u32,
+}
+
+// Sphere traces a distance field within `bounds`. Steps are the distance divided by
+// `lipschitz`, which must be at least the field's Lipschitz constant for them not to overshoot.
+pub struct Sdf {
+    pub field: Arc<dyn DistanceField + Send + Sync>,
+    pub bounds: Aabb,
+    pub lipschitz: f64,
+    pub material: Arc<dyn Material + Send + Sync>,
+}
+
+// Polynomial smooth minimum; `k` is the width of the blend.
+fn smooth_min(a: f64, b: f64, k: f64) -> f64 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h * h * k * 0.25
+}
+
+impl DistanceField for SdfSphere {
+    fn distance(&self, point: &Point) -> f64 {
+        (*point - self.center).length() - self.radius
+    }
+}
+
+impl DistanceField for SdfBox {
+    fn distance(&self, point: &Point) -> f64 {
+        let p = *point - self.center;
+        let inner = self.half_size - Vec3::new(self.rounding, self.rounding, self.rounding);
+        let q = Vec3::new(
+            p.x().abs() - inner.x(),
+            p.y().abs() - inner.y(),
+            p.z().abs() - inner.z(),
+        );
+        let outside = Vec3::new(q.x().max(0.0), q.y().max(0.0), q.z().max(0.0));
+        outside.length() + q.x().max(q.y()).max(q.z()).min(0.0) - self.rounding
+    }
+}
+
+impl DistanceField for SdfTorus {
+    fn distance(&self, point: &Point) -> f64 {
+        let p = *point - self.center;
+        let ring = (p.x() * p.x() + p.z() * p.z()).sqrt() - self.major_radius;
+        (ring * ring + p.y() * p.y()).sqrt() - self.minor_radius
+    }
+}
+
+impl DistanceField for SdfCylinder {
+    fn distance(&self, point: &Point) -> f64 {
+        let p = *point - self.center;
+        let radial = (p.x() * p.x() + p.z() * p.z()).sqrt() - self.radius;
+        let axial = p.y().abs() - self.half_height;
+        let outside = (radial.max(0.0).powi(2) + axial.max(0.0).powi(2)).sqrt();
+        outside + radial.max(axial).min(0.0)
+    }
+}
+
+impl DistanceField for SdfCombination {
+    fn distance(&self, point: &Point) -> f64 {
+        let left = self.left.distance(point);
+        let right = self.right.distance(point);
+        let k = self.smoothness;
+        match self.operation {
+            CsgOperation::Union => smooth_min(left, right, k),
+            CsgOperation::Intersection => -smooth_min(-left, -right, k),
+            CsgOperation::Difference => -smooth_min(-left, right, k),
+        }
+    }
+}
+
+impl DistanceField for SdfRepetition {
+    fn distance(&self, point: &Point) -> f64 {
+        let mut p = *point;
+        for axis in 0..3 {
+            let period = self.period.e[axis];
+            if period > 0.0 {
+                p.e[axis] -= period * (p.e[axis] / period).round();
+            }
+        }
+        self.field.distance(&p)
+    }
+}
+
+impl DistanceField for SdfTwist {
+    fn distance(&self, point: &Point) -> f64 {
+        let (sin, cos) = (-self.rate * point.y()).sin_cos();
+        let p = Point::new(
+            cos * point.x() - sin * point.z(),
+            point.y(),
+            sin * point.x() + cos * point.z(),
+        );
+        self.field.distance(&p)
+    }
+}
+
+impl DistanceField for Mandelbulb {
+    // Hubbard-Douady distance estimate from the escape time iteration.
+    fn distance(&self, point: &Point) -> f64 {
+        let mut z = *point;
+        let mut derivative = 1.0;
+        let mut r = z.length();
+        for _ in 0..self.iterations {
+            if !(1e-12..=2.0).contains(&r) {
+                break;
+            }
+            let theta = (z.z() / r).acos() * self.power;
+            let phi = z.y().atan2(z.x()) * self.power;
+            derivative = r.powf(self.power - 1.0) * self.power * derivative + 1.0;
+            let scaled = r.powf(self.power);
+            z = Vec3::new(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            ) * scaled
+                + *point;
+            r = z.length();
+        }
+        if r < 1e-12 {
+            return 0.0;
+        }
+        0.5 * r.ln() * r / derivative
+    }
+}
+
+impl Sdf {
+    const MAX_STEPS: usize = 1000;
+    // Surfaces are found to within this fraction of the distance along the ray, so distant
+    // detail, which would be finer than a pixel, takes fewer steps.
+    const PRECISION: f64 = 1e-4;
+
+    // Tetrahedral differences over `h`, four samples instead of six.
+    fn gradient(&self, point: &Point, h: f64) -> Vec3 {
+        [
+            Vec3::new(1.0, -1.0, -1.0),
+            Vec3::new(-1.0, -1.0, 1.0),
+            Vec3::new(-1.0, 1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+        ]
+        .iter()
+        .fold(Vec3::new(0.0, 0.0, 0.0), |gradient, k| {
+            gradient + *k * self.field.distance(&(*point + *k * h))
+        })
+    }
+}
+
+impl Hittable for Sdf {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (t0, t1) = self.bounds.clip(ray, t_min, t_max)?;
+        let speed = ray.direction.length();
+        // Rays that start inside march out through the surface the same way.
+        let side = self.field.distance(&ray.at(t0)).signum();
+        let mut t = t0;
+        for _ in 0..Sdf::MAX_STEPS {
+            let point = ray.at(t);
+            let distance = side * self.field.distance(&point) / self.lipschitz;
+            let tolerance = Sdf::PRECISION * (1e-2 + t * speed);
+            if distance < tolerance {
+                let outward_normal = to_unit_vector(&self.gradient(&point, tolerance));
+                return Some(HitRecord::from_hit(
+                    &point,
+                    ray,
+                    t,
+                    &outward_normal,
+                    0.0,
+                    0.0,
+                    self.material.clone(),
+                ));
+            }
+            t += distance / speed;
+            if t >= t1 {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{cross_product, dot_product, random_float, random_in_range, Color};
+    use crate::trace::{LambertianMaterial, Sphere};
+    use std::f64::consts::PI;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    fn material() -> Arc<dyn Material + Send + Sync> {
+        Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn sphere(center: Point, radius: f64) -> Arc<dyn DistanceField + Send + Sync> {
+        Arc::new(SdfSphere { center, radius })
+    }
+
+    #[test]
+    fn traced_spheres_match_analytic_spheres() {
+        let center = Point::new(0.2, -0.1, 0.3);
+        let radius = 1.5;
+        let analytic = Sphere::new(&center, radius, material());
+        let traced = Sdf {
+            field: sphere(center, radius),
+            bounds: analytic.bounding_box().unwrap(),
+            lipschitz: 1.0,
+            material: material(),
+        };
+        let mut num_hits = 0;
+        for _ in 0..1000 {
+            // Inside and outside, and along rays that miss, leaving out grazing ones where the
+            // march may stop just short of the surface.
+            let origin = center + Vec3::random_in_range(-4.0, 4.0);
+            let target = center + Vec3::random_in_range(-2.0, 2.0);
+            let direction = (target - origin) * random_in_range(0.5, 2.0);
+            let offset = cross_product(&(center - origin), &to_unit_vector(&direction)).length();
+            if (offset - radius).abs() < 0.05 * radius {
+                continue;
+            }
+            let ray = Ray {
+                origin,
+                direction,
+                time: 0.0,
+            };
+            let expected = analytic.hit(&ray, 0.001, f64::INFINITY);
+            let hit = traced.hit(&ray, 0.001, f64::INFINITY);
+            assert_eq!(hit.is_some(), expected.is_some(), "{:?}", ray.origin);
+            if let (Some(hit), Some(expected)) = (hit, expected) {
+                num_hits += 1;
+                // Marching stops short of the surface, within a distance set by the precision.
+                assert!(
+                    hit.t <= expected.t && expected.t - hit.t < 0.01,
+                    "{}",
+                    hit.t
+                );
+                assert!(((hit.point - center).length() - radius).abs() < 1e-3);
+                assert_eq!(hit.front_face, expected.front_face);
+                let outward = to_unit_vector(&(hit.point - center));
+                let normal = if hit.front_face { outward } else { -outward };
+                assert!((hit.normal - normal).length() < 1e-3);
+            }
+        }
+        assert!(num_hits > 200, "{}", num_hits);
+    }
+
+    #[test]
+    fn rays_from_inside_exit_through_the_surface() {
+        let traced = Sdf {
+            field: sphere(Point::new(0.0, 0.0, 0.0), 1.0),
+            bounds: Aabb::new(Point::new(-2.0, -2.0, -2.0), Point::new(2.0, 2.0, 2.0)),
+            lipschitz: 1.0,
+            material: material(),
+        };
+        let ray = Ray {
+            origin: Point::new(0.0, 0.5, 0.0),
+            direction: Vec3::new(0.0, 2.0, 0.0),
+            time: 0.0,
+        };
+        let hit = traced.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 0.25).abs() < 1e-4, "{}", hit.t);
+        assert!(!hit.front_face);
+        // Facing back in, against the ray.
+        assert!(dot_product(&hit.normal, &Vec3::new(0.0, -1.0, 0.0)) > 0.999);
+    }
+
+    #[test]
+    fn primitive_distances() {
+        let origin = Point::new(0.0, 0.0, 0.0);
+        let sharp = SdfBox {
+            center: origin,
+            half_size: Vec3::new(1.0, 2.0, 3.0),
+            rounding: 0.0,
+        };
+        assert_close(sharp.distance(&origin), -1.0);
+        assert_close(sharp.distance(&Point::new(3.0, 0.0, 0.0)), 2.0);
+        assert_close(sharp.distance(&Point::new(2.0, 3.0, 0.0)), 2.0f64.sqrt());
+        let rounded = SdfBox {
+            rounding: 0.5,
+            ..sharp
+        };
+        assert_close(rounded.distance(&Point::new(2.0, 0.0, 0.0)), 1.0);
+        assert_close(
+            rounded.distance(&Point::new(2.0, 3.0, 4.0)),
+            1.5 * 3.0f64.sqrt() - 0.5,
+        );
+
+        let torus = SdfTorus {
+            center: origin,
+            major_radius: 2.0,
+            minor_radius: 0.5,
+        };
+        assert_close(torus.distance(&Point::new(2.0, 0.0, 0.0)), -0.5);
+        assert_close(torus.distance(&origin), 1.5);
+        assert_close(torus.distance(&Point::new(0.0, 1.0, -2.0)), 0.5);
+
+        let cylinder = SdfCylinder {
+            center: origin,
+            radius: 1.0,
+            half_height: 2.0,
+        };
+        assert_close(cylinder.distance(&origin), -1.0);
+        assert_close(cylinder.distance(&Point::new(0.0, 1.5, 0.0)), -0.5);
+        assert_close(cylinder.distance(&Point::new(0.0, 3.0, 0.0)), 1.0);
+        assert_close(cylinder.distance(&Point::new(0.0, 0.0, -2.0)), 1.0);
+        assert_close(cylinder.distance(&Point::new(2.0, 3.0, 0.0)), 2.0f64.sqrt());
+    }
+
+    #[test]
+    fn smooth_min_blends_only_within_its_width() {
+        assert_close(smooth_min(1.0, 3.0, 0.0), 1.0);
+        assert_close(smooth_min(1.0, 3.0, 2.0), 1.0);
+        assert_close(smooth_min(3.0, 1.0, 1.0), 1.0);
+        assert_close(smooth_min(1.0, 1.0, 2.0), 0.5);
+        for _ in 0..100 {
+            let (a, b) = (random_in_range(-2.0, 2.0), random_in_range(-2.0, 2.0));
+            let k = random_float();
+            let blended = smooth_min(a, b, k);
+            assert!(blended <= a.min(b));
+            assert!(blended >= a.min(b) - k * 0.25);
+        }
+    }
+
+    #[test]
+    fn combinations() {
+        let combination = |operation, smoothness| SdfCombination {
+            operation,
+            left: sphere(Point::new(-1.0, 0.0, 0.0), 1.5),
+            right: sphere(Point::new(1.0, 0.0, 0.0), 1.5),
+            smoothness,
+        };
+        let origin = Point::new(0.0, 0.0, 0.0);
+        let far_left = Point::new(-3.0, 0.0, 0.0);
+        assert_close(
+            combination(CsgOperation::Union, 0.0).distance(&far_left),
+            0.5,
+        );
+        assert_close(
+            combination(CsgOperation::Intersection, 0.0).distance(&far_left),
+            2.5,
+        );
+        assert_close(
+            combination(CsgOperation::Difference, 0.0).distance(&origin),
+            0.5,
+        );
+        assert_close(
+            combination(CsgOperation::Difference, 0.0).distance(&far_left),
+            0.5,
+        );
+        // Blending fills in where both are near, and leaves the rest alone.
+        let smooth = combination(CsgOperation::Union, 1.0);
+        assert!(smooth.distance(&Point::new(0.0, 1.2, 0.0)) < -0.1);
+        assert_close(smooth.distance(&far_left), 0.5);
+        let smooth = combination(CsgOperation::Intersection, 1.0);
+        assert_close(smooth.distance(&origin), -0.5 + 0.25);
+    }
+
+    #[test]
+    fn repetition_copies_along_axes_with_a_period() {
+        let repeated = SdfRepetition {
+            field: sphere(Point::new(0.0, 0.0, 0.0), 1.0),
+            period: Vec3::new(4.0, 0.0, 4.0),
+        };
+        assert_close(repeated.distance(&Point::new(8.5, 0.0, -4.0)), -0.5);
+        assert_close(repeated.distance(&Point::new(-2.0, 0.0, 0.0)), 1.0);
+        assert_close(repeated.distance(&Point::new(4.0, 5.0, 4.0)), 4.0);
+    }
+
+    #[test]
+    fn twists_turn_with_height() {
+        // A thin rod along y through (1, 0, 0), turned a quarter for every unit up.
+        let twisted = SdfTwist {
+            field: Arc::new(SdfCylinder {
+                center: Point::new(1.0, 0.0, 0.0),
+                radius: 0.1,
+                half_height: 10.0,
+            }),
+            rate: PI / 2.0,
+        };
+        assert_close(twisted.distance(&Point::new(1.0, 0.0, 0.0)), -0.1);
+        assert_close(twisted.distance(&Point::new(0.0, 1.0, 1.0)), -0.1);
+        assert_close(twisted.distance(&Point::new(-1.0, 2.0, 0.0)), -0.1);
+        assert!(twisted.distance(&Point::new(1.0, 1.0, 0.0)) > 1.0);
+    }
+}
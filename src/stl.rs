@@ -0,0 +1,288 @@
+use crate::math::Point;
+use crate::mesh::{Mesh, TriangleMesh};
+use crate::trace::Material;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// Reads an STL file, binary or ascii. Facet normals are ignored: STL triangles wind counter
+// clockwise seen from outside, which is all the orientation meshes need. Vertices are not
+// shared, so triangles shade flat.
+pub fn load_stl(path: &Path, material: Arc<dyn Material + Send + Sync>) -> io::Result<Mesh> {
+    read_stl(path, &fs::read(path)?, material)
+}
+
+// Parses the contents of an STL file; `path` only names the file in errors.
+fn read_stl(
+    path: &Path,
+    data: &[u8],
+    material: Arc<dyn Material + Send + Sync>,
+) -> io::Result<Mesh> {
+    let error = |msg: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), msg),
+        )
+    };
+
+    // Binary files may start with "solid" too, so they are told apart by their size, which
+    // the triangle count after the 80 byte header fixes.
+    let binary_count = data
+        .get(80..84)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize);
+    let positions = match binary_count {
+        Some(count) if data.len() == 84 + 50 * count => read_binary(&data[84..]),
+        _ if data.starts_with(b"solid") => read_ascii(path, data)?,
+        _ => vec![],
+    };
+    if positions.is_empty() {
+        // Most likely a binary file cut short, whose header may well start with "solid".
+        return Err(error(match binary_count {
+            Some(count) if data.len() != 84 + 50 * count => format!(
+                "no ascii triangles, and a binary header giving {} triangles needs {} bytes, \
+                 found {}",
+                count,
+                84 + 50 * count,
+                data.len()
+            ),
+            _ => "no triangles".to_string(),
+        }));
+    }
+
+    let indices = (0..positions.len() / 3)
+        .map(|triangle| [3 * triangle, 3 * triangle + 1, 3 * triangle + 2])
+        .collect();
+    Ok(Mesh::new(TriangleMesh {
+        positions,
+        normals: vec![],
+        uvs: vec![],
+        colors: vec![],
+        indices,
+        material,
+    }))
+}
+
+// Each triangle is a normal and three vertices of little-endian 32-bit floats, then two bytes
+// of attributes.
+fn read_binary(data: &[u8]) -> Vec<Point> {
+    let float = |bytes: &[u8]| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64;
+    data.chunks_exact(50)
+        .flat_map(|triangle| {
+            (0..3).map(move |vertex| {
+                let start = 12 + 12 * vertex;
+                Point::new(
+                    float(&triangle[start..]),
+                    float(&triangle[start + 4..]),
+                    float(&triangle[start + 8..]),
+                )
+            })
+        })
+        .collect()
+}
+
+// `solid <name>`, then facets of the form `facet normal ...`, `outer loop`, three `vertex x y z`
+// lines, `endloop` and `endfacet`, and finally `endsolid`. Loops with more vertices are
+// triangulated as fans.
+fn read_ascii(path: &Path, data: &[u8]) -> io::Result<Vec<Point>> {
+    let text = String::from_utf8_lossy(data);
+    let mut positions = vec![];
+    let mut corners: Option<Vec<Point>> = None;
+    for (line_index, line) in text.lines().enumerate() {
+        let error = |msg: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path.display(), line_index + 1, msg),
+            )
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            [] | ["solid", ..] | ["endfacet"] | ["endsolid", ..] | ["facet", "normal", ..] => {}
+            ["outer", "loop"] => {
+                if corners.is_some() {
+                    return Err(error("'outer loop' inside another loop".to_string()));
+                }
+                corners = Some(vec![]);
+            }
+            ["vertex", coordinates @ ..] => {
+                let loop_corners = corners
+                    .as_mut()
+                    .ok_or_else(|| error("'vertex' outside a loop".to_string()))?;
+                let values = coordinates
+                    .iter()
+                    .map(|token| {
+                        token
+                            .parse::<f64>()
+                            .map_err(|_| error(format!("invalid number '{}'", token)))
+                    })
+                    .collect::<io::Result<Vec<_>>>()?;
+                if values.len() != 3 {
+                    return Err(error(format!(
+                        "'vertex' needs 3 values, found {}",
+                        values.len()
+                    )));
+                }
+                loop_corners.push(Point::new(values[0], values[1], values[2]));
+            }
+            ["endloop"] => {
+                let loop_corners = corners
+                    .take()
+                    .ok_or_else(|| error("'endloop' outside a loop".to_string()))?;
+                if loop_corners.len() < 3 {
+                    return Err(error(format!(
+                        "a facet needs at least 3 vertices, found {}",
+                        loop_corners.len()
+                    )));
+                }
+                for i in 1..(loop_corners.len() - 1) {
+                    positions.extend([loop_corners[0], loop_corners[i], loop_corners[i + 1]]);
+                }
+            }
+            [keyword, ..] => return Err(error(format!("unexpected '{}'", keyword))),
+        }
+    }
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Color;
+    use crate::test_util::error_message;
+    use crate::trace::LambertianMaterial;
+
+    fn parse(data: &[u8]) -> io::Result<Mesh> {
+        let material = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+        read_stl(Path::new("test.stl"), data, material)
+    }
+
+    // A binary file of `triangles`, whose 80 byte header starts with `header`.
+    fn binary(header: &[u8], triangles: &[[Point; 3]]) -> Vec<u8> {
+        let mut data = header.to_vec();
+        data.resize(80, b' ');
+        data.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+        for triangle in triangles {
+            data.extend_from_slice(&[0; 12]);
+            for vertex in triangle {
+                for c in vertex.e {
+                    data.extend_from_slice(&(c as f32).to_le_bytes());
+                }
+            }
+            data.extend_from_slice(&[0; 2]);
+        }
+        data
+    }
+
+    fn triangles() -> Vec<[Point; 3]> {
+        vec![
+            [
+                Point::new(0.0, 0.0, 0.0),
+                Point::new(1.0, 0.0, 0.0),
+                Point::new(0.0, 1.0, 0.0),
+            ],
+            [
+                Point::new(0.0, 0.0, 1.0),
+                Point::new(1.0, 0.0, 1.0),
+                Point::new(0.0, 1.0, 1.0),
+            ],
+        ]
+    }
+
+    #[test]
+    fn binary_triangles() {
+        let mesh = parse(&binary(b"binary", &triangles())).unwrap();
+        assert_eq!(mesh.data.positions, triangles().concat());
+        assert_eq!(mesh.data.indices, vec![[0, 1, 2], [3, 4, 5]]);
+    }
+
+    #[test]
+    fn binary_header_starting_with_solid() {
+        let mesh = parse(&binary(b"solid exported by a CAD tool", &triangles())).unwrap();
+        assert_eq!(mesh.data.positions, triangles().concat());
+    }
+
+    #[test]
+    fn truncated_binary_file() {
+        let mut data = binary(b"solid part", &triangles());
+        data.truncate(data.len() - 10);
+        assert_eq!(
+            error_message(parse(&data)),
+            "test.stl: no ascii triangles, and a binary header giving 2 triangles needs 184 \
+             bytes, found 174"
+        );
+        let mut data = binary(b"part", &triangles());
+        data.truncate(60);
+        assert_eq!(error_message(parse(&data)), "test.stl: no triangles");
+    }
+
+    #[test]
+    fn ascii_facets() {
+        let data = b"solid cube\n\
+                     facet normal 0 0 1\n\
+                     outer loop\n\
+                     vertex 0 0 0\n\
+                     vertex 1 0 0\n\
+                     vertex 0 1 0\n\
+                     endloop\n\
+                     endfacet\n\
+                     endsolid cube\n";
+        let mesh = parse(data).unwrap();
+        assert_eq!(mesh.data.positions, triangles()[0].to_vec());
+        assert_eq!(mesh.data.indices, vec![[0, 1, 2]]);
+    }
+
+    #[test]
+    fn ascii_loops_are_triangulated_as_fans() {
+        let data = b"solid\n\
+                     facet normal 0 0 1\n\
+                     outer loop\n\
+                     vertex 0 0 0\n\
+                     vertex 1 0 0\n\
+                     vertex 1 1 0\n\
+                     vertex 0 1 0\n\
+                     vertex -1 1 0\n\
+                     endloop\n\
+                     endfacet\n\
+                     endsolid\n";
+        let mesh = parse(data).unwrap();
+        assert_eq!(mesh.data.num_triangles(), 3);
+        let corners: Vec<Point> = mesh.data.positions.iter().step_by(3).cloned().collect();
+        assert_eq!(corners, vec![Point::new(0.0, 0.0, 0.0); 3]);
+        assert_eq!(mesh.data.positions[7], Point::new(0.0, 1.0, 0.0));
+        assert_eq!(mesh.data.positions[8], Point::new(-1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn ascii_errors_report_the_line() {
+        assert_eq!(
+            error_message(parse(b"solid\nfacet normal 0 0 1\nvertex 0 0 0\n")),
+            "test.stl:3: 'vertex' outside a loop"
+        );
+        assert_eq!(
+            error_message(parse(b"solid\nouter loop\nvertex 0 0\n")),
+            "test.stl:3: 'vertex' needs 3 values, found 2"
+        );
+        assert_eq!(
+            error_message(parse(
+                b"solid\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nendloop\n"
+            )),
+            "test.stl:5: a facet needs at least 3 vertices, found 2"
+        );
+        assert_eq!(
+            error_message(parse(b"solid\nouter loop\nouter loop\n")),
+            "test.stl:3: 'outer loop' inside another loop"
+        );
+        assert_eq!(
+            error_message(parse(b"solid\nendloop\n")),
+            "test.stl:2: 'endloop' outside a loop"
+        );
+        assert_eq!(
+            error_message(parse(b"solid\nfacet\n")),
+            "test.stl:2: unexpected 'facet'"
+        );
+        assert_eq!(
+            error_message(parse(b"solid empty\nendsolid\n")),
+            "test.stl: no triangles"
+        );
+    }
+}
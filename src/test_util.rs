@@ -0,0 +1,12 @@
+use std::io;
+
+// The message of an `InvalidData` error, failing the test on success or any other kind.
+pub fn error_message<T>(result: io::Result<T>) -> String {
+    match result {
+        Ok(_) => panic!("expected an error"),
+        Err(err) => {
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            err.to_string()
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use crate::image::{read_image, Image};
+use crate::math::{dot_product, random_float, to_unit_vector, Color, Point, Vec3};
+use crate::tonemap::srgb_decode;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// A color that varies over a surface, looked up by the hit's texture coordinates and position.
+pub trait Texture {
+    fn value(&self, u: f64, v: f64, point: &Point) -> Color;
+}
+
+pub struct SolidColor {
+    pub color: Color,
+}
+
+// Alternates between `even` and `odd` in cubes of side `scale` in world space.
+pub struct CheckerTexture {
+    pub even: Arc<dyn Texture + Send + Sync>,
+    pub odd: Arc<dyn Texture + Send + Sync>,
+    pub scale: f64,
+}
+
+// Bilinearly filtered, repeating outside [0, 1]. v runs from the bottom row to the top.
+pub struct ImageTexture {
+    image: Image,
+}
+
+// Perlin noise remapped to [0, 1], with `scale` the frequency of the noise.
+pub struct NoiseTexture {
+    noise: Perlin,
+    pub color: Color,
+    pub scale: f64,
+}
+
+// Bands along z perturbed by turbulence.
+pub struct MarbleTexture {
+    noise: Perlin,
+    pub color: Color,
+    pub scale: f64,
+}
+
+pub struct Perlin {
+    random_vectors: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        SolidColor { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _point: &Point) -> Color {
+        self.color
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, point: &Point) -> Color {
+        let cell = (point.x() / self.scale).floor()
+            + (point.y() / self.scale).floor()
+            + (point.z() / self.scale).floor();
+        if (cell as i64).rem_euclid(2) == 0 {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+}
+
+impl ImageTexture {
+    // `srgb` images are decoded to linear values; others are used as stored.
+    pub fn load(path: &Path, srgb: bool) -> io::Result<Self> {
+        Ok(ImageTexture::new(read_image(path)?, srgb))
+    }
+
+    pub fn new(mut image: Image, srgb: bool) -> Self {
+        if srgb {
+            for pixel in image.pixels.iter_mut() {
+                *pixel = Color::new(
+                    srgb_decode(pixel.x()),
+                    srgb_decode(pixel.y()),
+                    srgb_decode(pixel.z()),
+                );
+            }
+        }
+        ImageTexture { image }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _point: &Point) -> Color {
+        let x = u * self.image.width as f64 - 0.5;
+        let y = (1.0 - v) * self.image.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let texel = |i: f64, j: f64| {
+            let i = (i as i64).rem_euclid(self.image.width as i64) as usize;
+            let j = (j as i64).rem_euclid(self.image.height as i64) as usize;
+            self.image.pixels[j * self.image.width + i]
+        };
+        let top = texel(x0, y0) * (1.0 - tx) + texel(x0 + 1.0, y0) * tx;
+        let bottom = texel(x0, y0 + 1.0) * (1.0 - tx) + texel(x0 + 1.0, y0 + 1.0) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+impl NoiseTexture {
+    pub fn new(color: Color, scale: f64) -> Self {
+        NoiseTexture {
+            noise: Perlin::new(),
+            color,
+            scale,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, point: &Point) -> Color {
+        self.color * 0.5 * (1.0 + self.noise.noise(&(*point * self.scale)))
+    }
+}
+
+impl MarbleTexture {
+    pub fn new(color: Color, scale: f64) -> Self {
+        MarbleTexture {
+            noise: Perlin::new(),
+            color,
+            scale,
+        }
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, _u: f64, _v: f64, point: &Point) -> Color {
+        let phase = self.scale * point.z() + 10.0 * self.noise.turbulence(point, 7);
+        self.color * 0.5 * (1.0 + phase.sin())
+    }
+}
+
+impl Perlin {
+    const POINT_COUNT: usize = 256;
+
+    pub fn new() -> Self {
+        let random_vectors = (0..Perlin::POINT_COUNT)
+            .map(|_| to_unit_vector(&Vec3::random_in_range(-1.0, 1.0)))
+            .collect();
+        Perlin {
+            random_vectors,
+            perm_x: Perlin::generate_permutation(),
+            perm_y: Perlin::generate_permutation(),
+            perm_z: Perlin::generate_permutation(),
+        }
+    }
+
+    // Gradient noise in roughly [-1, 1].
+    pub fn noise(&self, point: &Point) -> f64 {
+        let (u, v, w) = (
+            point.x() - point.x().floor(),
+            point.y() - point.y().floor(),
+            point.z() - point.z().floor(),
+        );
+        let (i, j, k) = (
+            point.x().floor() as i64,
+            point.y().floor() as i64,
+            point.z().floor() as i64,
+        );
+
+        let mask = Perlin::POINT_COUNT as i64 - 1;
+        let mut corners = [[[Vec3::new(0.0, 0.0, 0.0); 2]; 2]; 2];
+        for (di, plane) in corners.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let idx = self.perm_x[((i + di as i64) & mask) as usize]
+                        ^ self.perm_y[((j + dj as i64) & mask) as usize]
+                        ^ self.perm_z[((k + dk as i64) & mask) as usize];
+                    *corner = self.random_vectors[idx];
+                }
+            }
+        }
+
+        // Hermite smoothing hides the grid.
+        let (uu, vv, ww) = (
+            u * u * (3.0 - 2.0 * u),
+            v * v * (3.0 - 2.0 * v),
+            w * w * (3.0 - 2.0 * w),
+        );
+        let mut accum = 0.0;
+        for (di, plane) in corners.iter().enumerate() {
+            for (dj, row) in plane.iter().enumerate() {
+                for (dk, corner) in row.iter().enumerate() {
+                    let (fi, fj, fk) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * dot_product(corner, &weight);
+                }
+            }
+        }
+        accum
+    }
+
+    // Sum of `depth` octaves of noise with halving amplitude.
+    pub fn turbulence(&self, point: &Point, depth: u32) -> f64 {
+        let mut accum = 0.0;
+        let mut p = *point;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(&p);
+            weight *= 0.5;
+            p *= 2.0;
+        }
+        accum.abs()
+    }
+
+    fn generate_permutation() -> Vec<usize> {
+        let mut perm: Vec<usize> = (0..Perlin::POINT_COUNT).collect();
+        for i in (1..perm.len()).rev() {
+            let target = ((random_float() * (i + 1) as f64) as usize).min(i);
+            perm.swap(i, target);
+        }
+        perm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Color, b: Color) {
+        assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    // Top row red then green, bottom row blue then white.
+    fn two_by_two() -> Image {
+        Image {
+            width: 2,
+            height: 2,
+            pixels: vec![
+                Color::new(1.0, 0.0, 0.0),
+                Color::new(0.0, 1.0, 0.0),
+                Color::new(0.0, 0.0, 1.0),
+                Color::new(1.0, 1.0, 1.0),
+            ],
+        }
+    }
+
+    #[test]
+    fn texel_centers_return_their_texel_with_v_up() {
+        let texture = ImageTexture::new(two_by_two(), false);
+        let origin = Point::new(0.0, 0.0, 0.0);
+        assert_close(
+            texture.value(0.25, 0.75, &origin),
+            Color::new(1.0, 0.0, 0.0),
+        );
+        assert_close(
+            texture.value(0.75, 0.75, &origin),
+            Color::new(0.0, 1.0, 0.0),
+        );
+        assert_close(
+            texture.value(0.25, 0.25, &origin),
+            Color::new(0.0, 0.0, 1.0),
+        );
+        assert_close(
+            texture.value(0.75, 0.25, &origin),
+            Color::new(1.0, 1.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn lookups_are_bilinear_and_repeat() {
+        let texture = ImageTexture::new(two_by_two(), false);
+        let origin = Point::new(0.0, 0.0, 0.0);
+        assert_close(texture.value(0.5, 0.75, &origin), Color::new(0.5, 0.5, 0.0));
+        assert_close(texture.value(0.5, 0.5, &origin), Color::new(0.5, 0.5, 0.5));
+        // The left edge blends the first and last columns.
+        assert_close(texture.value(0.0, 0.75, &origin), Color::new(0.5, 0.5, 0.0));
+        assert_close(
+            texture.value(1.25, -0.25, &origin),
+            texture.value(0.25, 0.75, &origin),
+        );
+    }
+
+    #[test]
+    fn srgb_images_are_decoded() {
+        let image = Image {
+            width: 1,
+            height: 1,
+            pixels: vec![Color::new(0.5, 0.0, 1.0)],
+        };
+        let origin = Point::new(0.0, 0.0, 0.0);
+        let linear = ImageTexture::new(image, true).value(0.5, 0.5, &origin);
+        assert_close(linear, Color::new(srgb_decode(0.5), 0.0, 1.0));
+        assert!((linear.x() - 0.214).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,198 @@
+use crate::math::Color;
+use crate::render::FrameBuffer;
+use serde::Deserialize;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMapOperator {
+    /// Clip radiance above 1
+    #[default]
+    Clamp,
+    /// Reinhard's x / (1 + x)
+    Reinhard,
+    /// Hable's filmic curve from Uncharted 2
+    Hable,
+    /// Narkowicz's fit of the ACES reference rendering transform
+    Aces,
+}
+
+// Turns the frame buffer's linear radiance into display values for 8 and 16-bit formats.
+// `exposure` is in stops. HDR formats are written straight from the frame buffer, so neither the
+// exposure nor the operator applies to them.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    pub exposure: f64,
+}
+
+impl ToneMapping {
+    pub fn expose(&self, color: &Color) -> Color {
+        *color * 2f64.powf(self.exposure)
+    }
+
+    // Tone mapped, sRGB encoded values in [0, 1].
+    pub fn display(&self, color: &Color) -> Color {
+        let exposed = self.expose(color);
+        let mapped = |c: f64| srgb_encode(self.operator.map(c.max(0.0)).min(1.0));
+        Color::new(
+            mapped(exposed.x()),
+            mapped(exposed.y()),
+            mapped(exposed.z()),
+        )
+    }
+
+    pub fn apply(&self, frame_buffer: &FrameBuffer) -> FrameBuffer {
+        let color = frame_buffer
+            .color
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|pixel_color| self.display(pixel_color))
+                    .collect()
+            })
+            .collect();
+        FrameBuffer {
+            color,
+            alpha: frame_buffer.alpha.clone(),
+        }
+    }
+}
+
+impl ToneMapOperator {
+    // Maps a non-negative linear channel value to [0, 1].
+    fn map(&self, c: f64) -> f64 {
+        match self {
+            ToneMapOperator::Clamp => c,
+            ToneMapOperator::Reinhard => c / (1.0 + c),
+            ToneMapOperator::Hable => {
+                const EXPOSURE_BIAS: f64 = 2.0;
+                const WHITE_POINT: f64 = 11.2;
+                hable_curve(c * EXPOSURE_BIAS) / hable_curve(WHITE_POINT)
+            }
+            ToneMapOperator::Aces => (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14),
+        }
+    }
+}
+
+fn hable_curve(x: f64) -> f64 {
+    const A: f64 = 0.15; // shoulder strength
+    const B: f64 = 0.50; // linear strength
+    const C: f64 = 0.10; // linear angle
+    const D: f64 = 0.20; // toe strength
+    const E: f64 = 0.02; // toe numerator
+    const F: f64 = 0.30; // toe denominator
+    (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+}
+
+pub fn srgb_encode(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_decode(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+    }
+
+    fn mapping(operator: ToneMapOperator) -> ToneMapping {
+        ToneMapping {
+            operator,
+            exposure: 0.0,
+        }
+    }
+
+    #[test]
+    fn clamp_clips_to_the_display_range() {
+        let tone_mapping = mapping(ToneMapOperator::Clamp);
+        let display = tone_mapping.display(&Color::new(-1.0, 0.5, 3.0));
+        assert_eq!(display.x(), 0.0);
+        assert_close(display.y(), srgb_encode(0.5));
+        assert_close(display.z(), 1.0);
+    }
+
+    #[test]
+    fn reinhard_curve() {
+        let operator = ToneMapOperator::Reinhard;
+        assert_eq!(operator.map(0.0), 0.0);
+        assert_close(operator.map(1.0), 0.5);
+        assert_close(operator.map(3.0), 0.75);
+        assert!(operator.map(1e6) < 1.0);
+    }
+
+    #[test]
+    fn hable_curve_maps_the_white_point_to_one() {
+        let operator = ToneMapOperator::Hable;
+        assert_close(operator.map(0.0), 0.0);
+        assert_close(operator.map(11.2 / 2.0), 1.0);
+        let mut previous = 0.0;
+        for i in 1..100 {
+            let mapped = operator.map(i as f64 * 0.05);
+            assert!(mapped > previous);
+            previous = mapped;
+        }
+    }
+
+    #[test]
+    fn aces_curve() {
+        let operator = ToneMapOperator::Aces;
+        assert_close(operator.map(0.0), 0.0);
+        assert_close(operator.map(1.0), 2.54 / 3.16);
+        assert!(operator.map(100.0) > 1.0);
+        // Values past the fit's shoulder still display as white.
+        assert_close(
+            mapping(operator).display(&Color::new(100.0, 0.0, 0.0)).x(),
+            1.0,
+        );
+    }
+
+    #[test]
+    fn exposure_is_in_stops() {
+        let tone_mapping = ToneMapping {
+            operator: ToneMapOperator::Clamp,
+            exposure: -2.0,
+        };
+        assert_close(tone_mapping.expose(&Color::new(2.0, 0.0, 0.0)).x(), 0.5);
+        assert_close(
+            tone_mapping.display(&Color::new(2.0, 0.0, 0.0)).x(),
+            srgb_encode(0.5),
+        );
+    }
+
+    #[test]
+    fn srgb_encode_and_decode_round_trip() {
+        for i in 0..=1000 {
+            let c = i as f64 / 1000.0;
+            assert_close(srgb_decode(srgb_encode(c)), c);
+            assert_close(srgb_encode(srgb_decode(c)), c);
+        }
+        assert_close(srgb_encode(0.0031308), 0.0031308 * 12.92);
+        assert_close(srgb_encode(1.0), 1.0);
+        assert_close(srgb_encode(0.5), 0.735357);
+        assert_close(srgb_decode(0.5), 0.214041);
+    }
+
+    #[test]
+    fn apply_keeps_alpha() {
+        let frame_buffer = FrameBuffer {
+            color: vec![vec![Color::new(1.0, 1.0, 1.0)]],
+            alpha: vec![vec![0.5]],
+        };
+        let mapped = mapping(ToneMapOperator::Reinhard).apply(&frame_buffer);
+        assert_close(mapped.color[0][0].x(), srgb_encode(0.5));
+        assert_eq!(mapped.alpha, vec![vec![0.5]]);
+    }
+}
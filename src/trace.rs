@@ -1,27 +1,83 @@
+use crate::bvh::Bvh;
 use crate::math::{
-    clamp, cross_product, degrees_to_radians, dot_product, is_in_range, random_float,
-    random_in_range, random_in_unit_disk, reflect_around_normal, refract_around_normal,
-    to_unit_vector, Color, Point, Ray, Vec3,
+    cross_product, degrees_to_radians, dot_product, is_in_range, random_float, random_in_range,
+    random_in_unit_disk, reflect_around_normal, refract_around_normal, to_unit_vector, Aabb, Color,
+    Onb, Point, Ray, Vec3,
 };
+use crate::pdf::{ggx_distribution, ggx_smith_g1, CosinePdf, GgxPdf, HittablePdf, Pdf};
+use crate::texture::{SolidColor, Texture};
 use std::cmp::Ordering;
 use std::f64::consts::PI;
-use std::io::Write;
 use std::sync::Arc;
 
 pub const BLACK: Color = Color::new(0.0, 0.0, 0.0);
 pub const WHITE: Color = Color::new(1.0, 1.0, 1.0);
 const LIGHT_BLUE: Color = Color::new(0.5, 0.7, 1.0);
 
+// `normal` is the shading normal and `geometric_normal` the true surface normal; both face the
+// side the ray came from. `dpdu` and `dpdv` are the surface derivatives along the texture
+// coordinates, or an arbitrary tangent frame for surfaces without a parameterization.
+// `color` is the interpolated vertex color of meshes that have them, and white elsewhere.
+#[derive(Clone)]
 pub struct HitRecord {
     pub point: Point,
     pub normal: Vec3,
+    pub geometric_normal: Vec3,
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
+    pub time: f64,
     pub front_face: bool,
+    pub color: Color,
     pub material: Arc<dyn Material + Send + Sync>,
 }
 
 pub trait Hittable {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+
+    // Every crossing of the surface between `t_min` and `t_max`, nearest first. Closed
+    // surfaces alternate between crossings that enter them, with `front_face` set, and ones
+    // that leave.
+    fn hit_all(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord> {
+        const SEPARATION: f64 = 1e-4;
+        let mut hits = vec![];
+        let mut t = t_min;
+        while let Some(hit) = self.hit(ray, t, t_max) {
+            t = hit.t + SEPARATION;
+            hits.push(hit);
+        }
+        hits
+    }
+
+    // None for unbounded geometry, which acceleration structures must test separately.
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    // Light sampling: `random` picks a direction from `origin` towards the hittable and
+    // `pdf_value` is the solid angle density of `random` choosing `direction`.
+    fn pdf_value(&self, _origin: &Point, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    fn random(&self, _origin: &Point) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    // Whether the hittable should be gathered into the scene's light list.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // Media scatter rays at random distances in `hit`. Shadow rays skip them and are attenuated
+    // by their `transmittance` between `t_min` and `t_max` instead.
+    fn is_medium(&self) -> bool {
+        false
+    }
+
+    fn transmittance(&self, _ray: &Ray, _t_min: f64, _t_max: f64) -> f64 {
+        1.0
+    }
 }
 
 pub struct Sphere {
@@ -30,66 +86,202 @@ pub struct Sphere {
     pub material: Arc<dyn Material + Send + Sync>,
 }
 
+// A sphere whose center moves in a straight line from `sphere.center` at time 0 to `center1`
+// at time 1.
+pub struct MovingSphere {
+    pub sphere: Sphere,
+    pub center1: Point,
+}
+
+// Moves any hittable by `offset` over the frame, in a straight line from where it is at time 0.
+pub struct Moving {
+    pub hittable: Arc<dyn Hittable + Send + Sync>,
+    pub offset: Vec3,
+}
+
 pub struct HittableCollection {
-    pub hittables: Vec<Box<dyn Hittable + Send + Sync>>,
+    pub hittables: Vec<Arc<dyn Hittable + Send + Sync>>,
 }
 
-#[allow(dead_code)]
 pub struct Camera {
     origin: Point,
     u: Vec3,
     v: Vec3,
-    w: Vec3,
     horizontal: Vec3,
     vertical: Vec3,
     lower_left_corner: Point,
     lens_radius: f64,
+    shutter_open: f64,
+    shutter_close: f64,
+    // Where the camera is at time 1 if it moves during the frame.
+    end: Option<Box<Camera>>,
 }
 
 pub trait Material {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord, attenuation: &mut Color) -> Option<Ray>;
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord>;
+
+    // BSDF times the cosine of `direction` with the normal, for materials that scatter with a
+    // pdf. `direction` points away from the surface.
+    fn eval(&self, _ray: &Ray, _hit: &HitRecord, _direction: &Vec3) -> Color {
+        BLACK
+    }
+
+    fn emitted(&self, _ray: &Ray, _hit: &HitRecord) -> Color {
+        BLACK
+    }
+
+    fn is_emissive(&self) -> bool {
+        false
+    }
+}
+
+// Specular scattering has a single outgoing ray that light sampling can never produce, so it
+// carries its own weight. Everything else hands the integrator a pdf to sample and evaluates
+// the BSDF through `Material::eval`.
+pub enum ScatterRecord {
+    Specular { ray: Ray, attenuation: Color },
+    Sampled(Box<dyn Pdf>),
 }
 
 pub struct LambertianMaterial {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture + Send + Sync>,
 }
 
+// `fuzziness` is the GGX roughness; zero is a perfect mirror.
 pub struct MetalMaterial {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture + Send + Sync>,
     pub fuzziness: f64,
 }
 
 pub struct DiaelectriMaterial {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture + Send + Sync>,
     pub ref_idx: f64,
 }
 
+// Emits `emit` from the front face only unless `two_sided` is set. Lights do not scatter.
+pub struct DiffuseLightMaterial {
+    pub emit: Arc<dyn Texture + Send + Sync>,
+    pub two_sided: bool,
+}
+
+// What a ray sees when it leaves the scene. Closed scenes lit only by emitters use a black
+// `Solid` background.
+pub enum Background {
+    Sky,
+    Solid(Color),
+}
+
+// Emissive hittables are gathered into `lights` before the rest of the world is moved into the
+// BVH, so the integrator can sample them directly. Media are gathered the same way, and when
+// there are any, shadow rays trace `surfaces`, a second BVH without them.
+pub struct Scene {
+    pub world: Bvh,
+    pub surfaces: Option<Bvh>,
+    pub lights: HittableCollection,
+    pub media: HittableCollection,
+    pub background: Background,
+}
+
 impl HitRecord {
     pub fn from_hit(
         point: &Point,
         ray: &Ray,
         t: f64,
         outward_normal: &Vec3,
+        u: f64,
+        v: f64,
         material: Arc<dyn Material + Send + Sync>,
     ) -> Self {
+        let tangents = Onb::from_w(outward_normal);
         let mut result = HitRecord {
             point: *point,
             normal: Vec3::new(0.0, 0.0, 0.0),
+            geometric_normal: Vec3::new(0.0, 0.0, 0.0),
+            dpdu: tangents.u,
+            dpdv: tangents.v,
             t,
+            u,
+            v,
+            time: ray.time,
             front_face: false,
+            color: WHITE,
             material,
         };
         result.set_face_normal(ray, outward_normal);
         result
     }
 
+    // A scattering event inside a medium. There is no surface, so the geometric normal is zero
+    // and rays spawned from the hit start exactly at it; the shading frame faces back along the
+    // ray.
+    pub fn in_medium(ray: &Ray, t: f64, material: Arc<dyn Material + Send + Sync>) -> Self {
+        let normal = -to_unit_vector(&ray.direction);
+        let tangents = Onb::from_w(&normal);
+        HitRecord {
+            point: ray.at(t),
+            normal,
+            geometric_normal: Vec3::new(0.0, 0.0, 0.0),
+            dpdu: tangents.u,
+            dpdv: tangents.v,
+            t,
+            u: 0.0,
+            v: 0.0,
+            time: ray.time,
+            front_face: true,
+            color: WHITE,
+            material,
+        }
+    }
+
+    // Looks up a surface's albedo, tinted by the vertex color.
+    pub fn albedo(&self, texture: &dyn Texture) -> Color {
+        texture.value(self.u, self.v, &self.point) * self.color
+    }
+
+    pub fn is_medium_event(&self) -> bool {
+        self.geometric_normal.length_squared() == 0.0
+    }
+
     pub fn set_face_normal(&mut self, ray: &Ray, outward_normal: &Vec3) {
-        self.front_face = dot_product(&ray.direction, &outward_normal) < 0.0;
+        self.front_face = dot_product(&ray.direction, outward_normal) < 0.0;
         self.normal = if self.front_face {
             *outward_normal
         } else {
             -*outward_normal
         };
+        self.geometric_normal = self.normal;
+    }
+
+    // Replaces the shading normal, flipped onto the side of the geometric normal.
+    pub fn set_shading_normal(&mut self, shading_normal: &Vec3) {
+        let shading_normal = to_unit_vector(shading_normal);
+        self.normal = if dot_product(&shading_normal, &self.geometric_normal) < 0.0 {
+            -shading_normal
+        } else {
+            shading_normal
+        };
+    }
+
+    // Starts a ray at the hit point, nudged along the geometric normal to the side `direction`
+    // leaves from. Shading normals never decide this, so perturbed normals cannot start rays
+    // inside the surface.
+    pub fn spawn_ray(&self, direction: &Vec3) -> Ray {
+        const OFFSET: f64 = 1e-4;
+        let offset = if dot_product(direction, &self.geometric_normal) < 0.0 {
+            -OFFSET
+        } else {
+            OFFSET
+        };
+        Ray {
+            origin: self.point + self.geometric_normal * offset,
+            direction: *direction,
+            time: self.time,
+        }
+    }
+
+    pub fn set_tangents(&mut self, dpdu: &Vec3, dpdv: &Vec3) {
+        self.dpdu = *dpdu;
+        self.dpdv = *dpdv;
     }
 }
 
@@ -102,16 +294,29 @@ impl Sphere {
         }
     }
 
-    fn calc_hit(&self, t: f64, ray: &Ray) -> HitRecord {
+    fn calc_hit(&self, center: &Point, t: f64, ray: &Ray) -> HitRecord {
         let point = ray.at(t);
-        let outward_normal = (point - self.center) / self.radius;
-        HitRecord::from_hit(&point, &ray, t, &outward_normal, self.material.clone())
+        let outward_normal = (point - *center) / self.radius;
+        let (u, v) = Sphere::get_uv(&outward_normal);
+        let mut hit =
+            HitRecord::from_hit(&point, ray, t, &outward_normal, u, v, self.material.clone());
+
+        // Derivatives of the spherical mapping below; they vanish at the poles, where the default
+        // frame is kept.
+        let (x, y, z) = (outward_normal.x(), outward_normal.y(), outward_normal.z());
+        let sin_theta = (x * x + z * z).sqrt();
+        if sin_theta > 1e-8 {
+            let dpdu = Vec3::new(z, 0.0, -x) * (2.0 * PI * self.radius);
+            let dpdv =
+                Vec3::new(-x * y / sin_theta, sin_theta, -y * z / sin_theta) * (PI * self.radius);
+            hit.set_tangents(&dpdu, &dpdv);
+        }
+        hit
     }
-}
 
-impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
+    // Intersects the sphere as if it were centered at `center`.
+    fn hit_at(&self, center: &Point, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let oc = ray.origin - *center;
         let a = ray.direction.length_squared();
         let half_b = dot_product(&oc, &ray.direction);
         let c = oc.length_squared() - self.radius * self.radius;
@@ -125,15 +330,151 @@ impl Hittable for Sphere {
                 let t_root1 = (-half_b - root) / a;
                 let t_root2 = (-half_b + root) / a;
                 if is_in_range(t_root1, t_min, t_max) {
-                    Some(self.calc_hit(t_root1, &ray))
+                    Some(self.calc_hit(center, t_root1, ray))
                 } else if is_in_range(t_root2, t_min, t_max) {
-                    Some(self.calc_hit(t_root2, &ray))
+                    Some(self.calc_hit(center, t_root2, ray))
                 } else {
                     None
                 }
             }
         }
     }
+
+    // Maps a point on the unit sphere to u = longitude from -x around through +z, v = latitude
+    // from -y to +y.
+    fn get_uv(p: &Point) -> (f64, f64) {
+        let theta = (-p.y()).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.hit_at(&self.center, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+
+    // Directions are sampled uniformly from the cone the sphere subtends, or from all
+    // directions when the origin is inside it.
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f64 {
+        // Static geometry looks the same at any time.
+        let ray = Ray {
+            origin: *origin,
+            direction: *direction,
+            time: 0.0,
+        };
+        if self.hit(&ray, 0.001, f64::INFINITY).is_none() {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center - *origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return 1.0 / (4.0 * PI);
+        }
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn random(&self, origin: &Point) -> Vec3 {
+        let direction = self.center - *origin;
+        let distance_squared = direction.length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return lambertian_random_in_unit_sphere();
+        }
+
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let z = 1.0 + random_float() * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * random_float();
+        let sin_theta = (1.0 - z * z).sqrt();
+        let uvw = Onb::from_w(&direction);
+        uvw.local(&Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: &Point,
+        center1: &Point,
+        radius: f64,
+        material: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        MovingSphere {
+            sphere: Sphere::new(center0, radius, material),
+            center1: *center1,
+        }
+    }
+
+    fn center(&self, time: f64) -> Point {
+        let time = time.clamp(0.0, 1.0);
+        self.sphere.center * (1.0 - time) + self.center1 * time
+    }
+}
+
+// Moving hittables are never sampled as lights, since light sampling has no notion of time;
+// emission they show is still picked up when rays hit them.
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.sphere
+            .hit_at(&self.center(ray.time), ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = Vec3::new(self.sphere.radius, self.sphere.radius, self.sphere.radius);
+        Some(Aabb::surrounding(
+            &Aabb::new(self.sphere.center - extent, self.sphere.center + extent),
+            &Aabb::new(self.center1 - extent, self.center1 + extent),
+        ))
+    }
+}
+
+impl Moving {
+    fn offset(&self, time: f64) -> Vec3 {
+        self.offset * time.clamp(0.0, 1.0)
+    }
+}
+
+impl Hittable for Moving {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let offset = self.offset(ray.time);
+        let moved_ray = Ray {
+            origin: ray.origin - offset,
+            ..*ray
+        };
+        let mut hit = self.hittable.hit(&moved_ray, t_min, t_max)?;
+        hit.point += offset;
+        Some(hit)
+    }
+
+    fn is_medium(&self) -> bool {
+        self.hittable.is_medium()
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let moved_ray = Ray {
+            origin: ray.origin - self.offset(ray.time),
+            ..*ray
+        };
+        self.hittable.transmittance(&moved_ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let bbox = self.hittable.bounding_box()?;
+        Some(Aabb::surrounding(
+            &bbox,
+            &Aabb::new(bbox.min + self.offset, bbox.max + self.offset),
+        ))
+    }
 }
 
 impl HittableCollection {
@@ -141,9 +482,32 @@ impl HittableCollection {
         HittableCollection { hittables: vec![] }
     }
 
-    pub fn add(&mut self, hittable: Box<dyn Hittable + Send + Sync>) {
+    pub fn add(&mut self, hittable: Arc<dyn Hittable + Send + Sync>) {
         self.hittables.push(hittable);
     }
+
+    pub fn lights(&self) -> HittableCollection {
+        self.filtered(|hittable| hittable.is_emissive())
+    }
+
+    pub fn media(&self) -> HittableCollection {
+        self.filtered(|hittable| hittable.is_medium())
+    }
+
+    pub fn surfaces(&self) -> HittableCollection {
+        self.filtered(|hittable| !hittable.is_medium())
+    }
+
+    fn filtered(&self, keep: impl Fn(&dyn Hittable) -> bool) -> HittableCollection {
+        HittableCollection {
+            hittables: self
+                .hittables
+                .iter()
+                .filter(|hittable| keep(hittable.as_ref()))
+                .cloned()
+                .collect(),
+        }
+    }
 }
 
 impl Hittable for HittableCollection {
@@ -152,7 +516,7 @@ impl Hittable for HittableCollection {
         let mut closest_hit_t = t_max;
 
         for hittable in self.hittables.iter() {
-            if let Some(hit) = hittable.hit(&ray, t_min, closest_hit_t) {
+            if let Some(hit) = hittable.hit(ray, t_min, closest_hit_t) {
                 closest_hit_t = hit.t;
                 closest_hit = Some(hit);
             }
@@ -160,6 +524,62 @@ impl Hittable for HittableCollection {
 
         closest_hit
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if self.hittables.is_empty() {
+            return None;
+        }
+
+        let mut bbox = Aabb::empty();
+        for hittable in self.hittables.iter() {
+            bbox = Aabb::surrounding(&bbox, &hittable.bounding_box()?);
+        }
+        Some(bbox)
+    }
+
+    // Each member is picked with equal probability, so the density is the average of theirs.
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f64 {
+        if self.hittables.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .hittables
+            .iter()
+            .map(|hittable| hittable.pdf_value(origin, direction))
+            .sum();
+        sum / self.hittables.len() as f64
+    }
+
+    fn random(&self, origin: &Point) -> Vec3 {
+        let idx = (random_float() * self.hittables.len() as f64) as usize;
+        self.hittables[idx.min(self.hittables.len() - 1)].random(origin)
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.hittables
+            .iter()
+            .map(|hittable| hittable.transmittance(ray, t_min, t_max))
+            .product()
+    }
+}
+
+impl Scene {
+    pub fn new(world: HittableCollection, background: Background) -> Self {
+        let lights = world.lights();
+        let media = world.media();
+        let surfaces = if media.hittables.is_empty() {
+            None
+        } else {
+            Some(Bvh::new(world.surfaces()))
+        };
+        Scene {
+            world: Bvh::new(world),
+            surfaces,
+            lights,
+            media,
+            background,
+        }
+    }
 }
 
 impl Camera {
@@ -178,7 +598,7 @@ impl Camera {
         let viewport_width = aspect_ratio * viewport_height;
 
         let w = to_unit_vector(&(*look_from - *look_at));
-        let u = to_unit_vector(&cross_product(&vup, &w));
+        let u = to_unit_vector(&cross_product(vup, &w));
         let v = cross_product(&w, &u);
 
         let origin = *look_from;
@@ -190,50 +610,141 @@ impl Camera {
             origin,
             u,
             v,
-            w,
             horizontal,
             vertical,
             lower_left_corner,
             lens_radius: aperture / 2.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            end: None,
+        }
+    }
+
+    // Rays are spread uniformly over the times between `open` and `close`, clamped to the frame.
+    pub fn with_shutter(self, open: f64, close: f64) -> Self {
+        Camera {
+            shutter_open: open.clamp(0.0, 1.0),
+            shutter_close: close.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    // Moves the camera from its own pose at time 0 to `end`'s pose at time 1.
+    pub fn with_motion(self, end: Camera) -> Self {
+        Camera {
+            end: Some(Box::new(end)),
+            ..self
         }
     }
 
     pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = random_in_unit_disk() * self.lens_radius;
-        let offset = self.u * rd.x() + self.v * rd.y();
-        let origin = self.origin + offset;
-        let direction = self.lower_left_corner + self.horizontal * s + self.vertical * t - origin;
-        Ray { origin, direction }
+        let time = self.shutter_open + random_float() * (self.shutter_close - self.shutter_open);
+        let rd = random_in_unit_disk();
+        let (origin, target) = self.lens_and_focus_points(s, t, &rd);
+        let (origin, target) = match &self.end {
+            Some(end) => {
+                let (end_origin, end_target) = end.lens_and_focus_points(s, t, &rd);
+                (
+                    origin * (1.0 - time) + end_origin * time,
+                    target * (1.0 - time) + end_target * time,
+                )
+            }
+            None => (origin, target),
+        };
+        Ray {
+            origin,
+            direction: target - origin,
+            time,
+        }
+    }
+
+    // The point on the lens for the unit disk sample `rd`, and the point on the focus plane at
+    // viewport coordinates `s`, `t`.
+    fn lens_and_focus_points(&self, s: f64, t: f64, rd: &Vec3) -> (Point, Point) {
+        let offset = self.u * (rd.x() * self.lens_radius) + self.v * (rd.y() * self.lens_radius);
+        (
+            self.origin + offset,
+            self.lower_left_corner + self.horizontal * s + self.vertical * t,
+        )
+    }
+}
+
+impl LambertianMaterial {
+    pub fn new(albedo: Color) -> Self {
+        LambertianMaterial {
+            albedo: Arc::new(SolidColor::new(albedo)),
+        }
     }
 }
 
 impl Material for LambertianMaterial {
-    fn scatter(&self, _ray: &Ray, hit: &HitRecord, attenuation: &mut Color) -> Option<Ray> {
-        let scatter_direction = hit.normal + lambertian_random_in_unit_sphere();
-        *attenuation = self.albedo;
-        let scattered_ray = Ray {
-            origin: hit.point,
-            direction: scatter_direction,
-        };
-        Some(scattered_ray)
+    fn scatter(&self, _ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Sampled(Box::new(CosinePdf::new(
+            &hit.normal,
+        ))))
+    }
+
+    fn eval(&self, _ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color {
+        let cosine = dot_product(&hit.normal, &to_unit_vector(direction));
+        if cosine <= 0.0 {
+            BLACK
+        } else {
+            hit.albedo(self.albedo.as_ref()) * (cosine / PI)
+        }
+    }
+}
+
+impl MetalMaterial {
+    const MIN_ALPHA: f64 = 1e-3;
+
+    pub fn new(albedo: Color, fuzziness: f64) -> Self {
+        MetalMaterial {
+            albedo: Arc::new(SolidColor::new(albedo)),
+            fuzziness,
+        }
+    }
+
+    fn alpha(&self) -> f64 {
+        self.fuzziness * self.fuzziness
     }
 }
 
 impl Material for MetalMaterial {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord, attenuation: &mut Color) -> Option<Ray> {
-        let reflected_direction = reflect_around_normal(&ray.direction, &hit.normal);
-        let fuzzed_direction =
-            reflected_direction + lambertian_random_in_unit_sphere() * self.fuzziness;
-        let scattered_ray = Ray {
-            origin: hit.point,
-            direction: fuzzed_direction,
-        };
-        *attenuation = self.albedo;
-        if dot_product(&scattered_ray.direction, &hit.normal) > 0.0 {
-            Some(scattered_ray)
-        } else {
-            None
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
+        if self.alpha() < MetalMaterial::MIN_ALPHA {
+            let reflected_direction = reflect_around_normal(&ray.direction, &hit.normal);
+            return Some(ScatterRecord::Specular {
+                ray: hit.spawn_ray(&reflected_direction),
+                attenuation: hit.albedo(self.albedo.as_ref()),
+            });
+        }
+        Some(ScatterRecord::Sampled(Box::new(GgxPdf::new(
+            &hit.normal,
+            &(-ray.direction),
+            self.alpha(),
+        ))))
+    }
+
+    // Cook-Torrance with GGX normals, Smith masking and Schlick Fresnel using the albedo as the
+    // reflectance at normal incidence.
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color {
+        let outgoing = -to_unit_vector(&ray.direction);
+        let incoming = to_unit_vector(direction);
+        let n_dot_o = dot_product(&hit.normal, &outgoing);
+        let n_dot_i = dot_product(&hit.normal, &incoming);
+        if n_dot_o <= 0.0 || n_dot_i <= 0.0 {
+            return BLACK;
         }
+
+        let half = to_unit_vector(&(incoming + outgoing));
+        let n_dot_h = dot_product(&hit.normal, &half);
+        let o_dot_h = dot_product(&outgoing, &half).max(0.0);
+        let alpha = self.alpha();
+        let albedo = hit.albedo(self.albedo.as_ref());
+        let fresnel = albedo + (WHITE - albedo) * (1.0 - o_dot_h).powi(5);
+        let d = ggx_distribution(n_dot_h, alpha);
+        let g = ggx_smith_g1(n_dot_o, alpha) * ggx_smith_g1(n_dot_i, alpha);
+        fresnel * (d * g / (4.0 * n_dot_o))
     }
 }
 
@@ -241,7 +752,7 @@ impl DiaelectriMaterial {
     pub fn new(ref_idx: f64) -> Self {
         DiaelectriMaterial {
             ref_idx,
-            albedo: WHITE,
+            albedo: Arc::new(SolidColor::new(WHITE)),
         }
     }
 
@@ -253,8 +764,7 @@ impl DiaelectriMaterial {
 }
 
 impl Material for DiaelectriMaterial {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord, attenuation: &mut Color) -> Option<Ray> {
-        *attenuation = self.albedo;
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
         let etai_over_etat = if hit.front_face {
             1.0 / self.ref_idx
         } else {
@@ -271,11 +781,41 @@ impl Material for DiaelectriMaterial {
                 refract_around_normal(&direction, &hit.normal, etai_over_etat)
             };
 
-        let scattered_ray = Ray {
-            origin: hit.point,
-            direction: scattered_direction,
-        };
-        Some(scattered_ray)
+        Some(ScatterRecord::Specular {
+            ray: hit.spawn_ray(&scattered_direction),
+            attenuation: hit.albedo(self.albedo.as_ref()),
+        })
+    }
+}
+
+impl Material for DiffuseLightMaterial {
+    fn scatter(&self, _ray: &Ray, _hit: &HitRecord) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, _ray: &Ray, hit: &HitRecord) -> Color {
+        if hit.front_face || self.two_sided {
+            self.emit.value(hit.u, hit.v, &hit.point)
+        } else {
+            BLACK
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+impl Background {
+    pub fn color(&self, ray: &Ray) -> Color {
+        match self {
+            Background::Sky => {
+                let unit_direction = to_unit_vector(&ray.direction);
+                let t = (unit_direction.y() + (1.0)) * (0.5);
+                WHITE * (1.0 - t) + LIGHT_BLUE * t
+            }
+            Background::Solid(color) => *color,
+        }
     }
 }
 
@@ -286,41 +826,177 @@ pub fn lambertian_random_in_unit_sphere() -> Vec3 {
     Vec3::new(r * a.cos(), r * a.sin(), z)
 }
 
-pub fn get_ray_color(ray: &Ray, world: &HittableCollection, depth: u32) -> Color {
+// Radiance arriving along a camera ray, and whether the ray hit the world rather than escaping
+// to the background.
+pub fn get_ray_color(ray: &Ray, scene: &Scene, depth: u32) -> (Color, bool) {
+    if depth == 0 {
+        return (BLACK, false);
+    }
+    match scene.world.hit(ray, 0.001, f64::INFINITY) {
+        Some(hit) => (shade(ray, &hit, scene, None, depth), true),
+        None => (scene.background.color(ray), false),
+    }
+}
+
+fn trace_ray(ray: &Ray, scene: &Scene, scattering: Option<(Point, f64)>, depth: u32) -> Color {
     if depth == 0 {
         return BLACK;
     }
+    match scene.world.hit(ray, 0.001, f64::INFINITY) {
+        Some(hit) => shade(ray, &hit, scene, scattering, depth),
+        None => scene.background.color(ray),
+    }
+}
 
-    if let Some(hit) = world.hit(ray, 0.001, f64::INFINITY) {
-        let mut attenuation = WHITE;
-        if let Some(scattered_ray) = hit.material.scatter(&ray, &hit, &mut attenuation) {
-            return attenuation * get_ray_color(&scattered_ray, world, depth - 1);
-        } else {
-            return BLACK;
-        }
+// `scattering` is the previous bounce's hit point and the density with which its material chose
+// `ray`, or None when light sampling could not have produced it (camera rays and specular
+// bounces). Sampled bounces combine a light sample and a material sample, and any emitter the
+// material sample hits is weighted by the power heuristic so the two estimates are not counted
+// twice. The light density is evaluated at the hit point, as `sample_light` does, rather than
+// at the offset origin of `ray`.
+fn shade(
+    ray: &Ray,
+    hit: &HitRecord,
+    scene: &Scene,
+    scattering: Option<(Point, f64)>,
+    depth: u32,
+) -> Color {
+    let mut color = hit.material.emitted(ray, hit);
+    // Shadow rays pass through media, so light sampling never finds their emission.
+    let light_sampled = color.length_squared() > 0.0 && !hit.is_medium_event();
+    if let Some((origin, scattering_pdf)) = scattering.filter(|_| light_sampled) {
+        let light_pdf = scene.lights.pdf_value(&origin, &ray.direction);
+        color *= power_heuristic(scattering_pdf, light_pdf);
     }
 
-    let unit_direction = to_unit_vector(&ray.direction);
-    let t = (unit_direction.y() + (1.0)) * (0.5);
+    let pdf = match hit.material.scatter(ray, hit) {
+        None => return color,
+        Some(ScatterRecord::Specular {
+            ray: scattered_ray,
+            attenuation,
+        }) => {
+            return color + attenuation * trace_ray(&scattered_ray, scene, None, depth - 1);
+        }
+        Some(ScatterRecord::Sampled(pdf)) => pdf,
+    };
+
+    color += sample_light(ray, hit, pdf.as_ref(), scene);
 
-    WHITE * (1.0 - t) + LIGHT_BLUE * t
+    let scattered_ray = hit.spawn_ray(&pdf.generate());
+    let scattering_pdf = pdf.value(&scattered_ray.direction);
+    if scattering_pdf <= 0.0 {
+        return color;
+    }
+    let bsdf = hit.material.eval(ray, hit, &scattered_ray.direction);
+    color
+        + bsdf / scattering_pdf
+            * trace_ray(
+                &scattered_ray,
+                scene,
+                Some((hit.point, scattering_pdf)),
+                depth - 1,
+            )
 }
 
-pub fn write_pixel(out: &mut dyn Write, pixel_color: &Color) -> std::io::Result<()> {
-    let corrected_color = apply_gamma_correction(pixel_color);
-    writeln!(
-        out,
-        "{} {} {}",
-        to_color_byte(corrected_color.x()),
-        to_color_byte(corrected_color.y()),
-        to_color_byte(corrected_color.z())
-    )
+fn sample_light(ray: &Ray, hit: &HitRecord, scattering: &dyn Pdf, scene: &Scene) -> Color {
+    if scene.lights.hittables.is_empty() {
+        return BLACK;
+    }
+
+    let lights = HittablePdf::new(&hit.point, &scene.lights);
+    let light_ray = hit.spawn_ray(&lights.generate());
+    let light_pdf = lights.value(&light_ray.direction);
+    if light_pdf <= 0.0 {
+        return BLACK;
+    }
+    let bsdf = hit.material.eval(ray, hit, &light_ray.direction);
+    if bsdf.length_squared() <= 0.0 {
+        return BLACK;
+    }
+
+    let surfaces = scene.surfaces.as_ref().unwrap_or(&scene.world);
+    match surfaces.hit(&light_ray, 0.001, f64::INFINITY) {
+        Some(light_hit) => {
+            let emitted = light_hit.material.emitted(&light_ray, &light_hit)
+                * scene.media.transmittance(&light_ray, 0.001, light_hit.t);
+            let scattering_pdf = scattering.value(&light_ray.direction);
+            bsdf * emitted * (power_heuristic(light_pdf, scattering_pdf) / light_pdf)
+        }
+        None => BLACK,
+    }
 }
 
-fn apply_gamma_correction(color: &Color) -> Color {
-    Color::new(color.e[0].sqrt(), color.e[1].sqrt(), color.e[2].sqrt())
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let pdf_squared = pdf * pdf;
+    pdf_squared / (pdf_squared + other_pdf * other_pdf)
 }
 
-fn to_color_byte(c: f64) -> u8 {
-    ((256.0) * clamp(c, 0.0, 0.999)) as u8
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Average radiance leaving a diffuse floor at the origin, facing +z with albedo 0.5,
+    // under a sphere light of radiance 4 and nothing else.
+    fn floor_radiance(light_center: &Point, light_radius: f64) -> f64 {
+        let light = DiffuseLightMaterial {
+            emit: Arc::new(SolidColor::new(Color::new(4.0, 4.0, 4.0))),
+            two_sided: false,
+        };
+        let mut world = HittableCollection::new();
+        world.add(Arc::new(Sphere::new(
+            light_center,
+            light_radius,
+            Arc::new(light),
+        )));
+        let scene = Scene::new(world, Background::Solid(BLACK));
+
+        let ray = Ray {
+            origin: Point::new(1.0, 0.0, 1.0),
+            direction: Vec3::new(-1.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let floor = Arc::new(LambertianMaterial::new(Color::new(0.5, 0.5, 0.5)));
+        let hit = HitRecord::from_hit(
+            &Point::new(0.0, 0.0, 0.0),
+            &ray,
+            1.0,
+            &Vec3::new(0.0, 0.0, 1.0),
+            0.0,
+            0.0,
+            floor,
+        );
+        const NUM_SAMPLES: usize = 200_000;
+        let total: f64 = (0..NUM_SAMPLES)
+            .map(|_| shade(&ray, &hit, &scene, None, 2).x())
+            .sum();
+        total / NUM_SAMPLES as f64
+    }
+
+    // A sphere of radiance L subtending a cone of half angle a, whose axis makes an angle b
+    // with the normal, gives an irradiance of pi L sin^2(a) cos(b) while it is wholly above
+    // the horizon. A diffuse surface of albedo p reflects p / pi of that.
+    fn expected_radiance(light_center: &Point, light_radius: f64) -> f64 {
+        let distance = light_center.length();
+        let sin_squared = (light_radius / distance).powi(2);
+        0.5 * 4.0 * sin_squared * light_center.z() / distance
+    }
+
+    #[test]
+    fn light_and_material_samples_add_up_to_the_irradiance() {
+        for (center, radius) in [
+            (Point::new(0.0, 0.0, 3.0), 1.0),
+            (Point::new(0.0, 0.0, 2.5), 2.0),
+            (Point::new(2.0, 0.0, 1.5), 0.5),
+        ] {
+            let actual = floor_radiance(&center, radius);
+            let expected = expected_radiance(&center, radius);
+            assert!(
+                (actual - expected).abs() < 0.02 * expected,
+                "light at {:?}: {} != {}",
+                center,
+                actual,
+                expected
+            );
+        }
+    }
 }
@@ -0,0 +1,722 @@
+use crate::math::{random_float, Aabb, Color, Point, Ray, Vec3};
+use crate::texture::Perlin;
+use crate::trace::{HitRecord, Hittable, Material, ScatterRecord};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// A density that varies over the unit cube.
+pub trait DensityField {
+    fn density(&self, point: &Point) -> f64;
+
+    // An upper bound on the density between `min` and `max`.
+    fn max_density(&self, min: &Point, max: &Point) -> f64;
+}
+
+// Voxels in x, then y, then z order, with their centers evenly spaced over the unit cube and
+// trilinearly interpolated between.
+pub struct DensityGrid {
+    resolution: [usize; 3],
+    values: Vec<f64>,
+}
+
+// Turbulence in [0, 1] at `frequency` cycles per unit.
+pub struct NoiseDensity {
+    noise: Perlin,
+    pub frequency: f64,
+    pub octaves: u32,
+}
+
+// A medium filling `bounds` whose absorption and scattering coefficients are `absorption` and
+// `scattering` times their density fields stretched over the box. Absorbing media with
+// `emission` glow, more brightly where `emission_field` is higher if there is one.
+//
+// Collisions are sampled by delta tracking and shadow rays are attenuated by ratio tracking,
+// both against majorants kept for each cell of a coarse grid over the box, so thin regions are
+// crossed in few steps. Distances and coefficients are in the units of the medium's own space.
+pub struct HeterogeneousMedium {
+    bounds: Aabb,
+    absorption_field: Arc<dyn DensityField + Send + Sync>,
+    absorption: f64,
+    scattering_field: Arc<dyn DensityField + Send + Sync>,
+    scattering: f64,
+    emission: Color,
+    emission_field: Option<Arc<dyn DensityField + Send + Sync>>,
+    phase_function: Arc<dyn Material + Send + Sync>,
+    majorants: Vec<f64>,
+}
+
+// Ends paths absorbed in a medium, giving back the medium's emission there.
+struct AbsorbedMaterial {
+    radiance: Color,
+}
+
+impl DensityGrid {
+    // Reads a NRRD file (raw or ascii encoded, any of the integer or floating point types), or
+    // otherwise a headerless file of little-endian 32-bit floats with the given resolution.
+    // Integer samples are scaled to [0, 1].
+    pub fn load(path: &Path, resolution: Option<[usize; 3]>) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let is_nrrd = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("nrrd"));
+        let result = if is_nrrd {
+            DensityGrid::from_nrrd(&data)
+        } else {
+            let resolution = resolution.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "raw density grids need a resolution",
+                )
+            })?;
+            let values = data
+                .chunks_exact(4)
+                .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64)
+                .collect();
+            DensityGrid::new(resolution, values)
+        };
+        result.map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))
+    }
+
+    pub fn new(resolution: [usize; 3], values: Vec<f64>) -> io::Result<Self> {
+        // Sizes read from a header may be large enough to overflow.
+        let expected = resolution
+            .iter()
+            .try_fold(1usize, |product, &n| product.checked_mul(n))
+            .unwrap_or(usize::MAX);
+        if expected == 0 || values.len() != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "expected {} voxels for a {}x{}x{} grid, found {}",
+                    expected,
+                    resolution[0],
+                    resolution[1],
+                    resolution[2],
+                    values.len()
+                ),
+            ));
+        }
+        Ok(DensityGrid { resolution, values })
+    }
+
+    fn from_nrrd(data: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        if !data.starts_with(b"NRRD") {
+            return Err(invalid("not a NRRD file".to_string()));
+        }
+        // The header ends at a blank line, with either line ending.
+        let (header_end, body_start) = (0..data.len())
+            .find_map(|i| {
+                if data[i..].starts_with(b"\n\n") {
+                    Some((i, i + 2))
+                } else if data[i..].starts_with(b"\r\n\r\n") {
+                    Some((i, i + 4))
+                } else {
+                    None
+                }
+            })
+            .ok_or_else(|| invalid("unterminated NRRD header".to_string()))?;
+        let header = String::from_utf8_lossy(&data[..header_end]);
+        let body = &data[body_start..];
+
+        let mut fields = std::collections::HashMap::new();
+        for line in header.lines().skip(1) {
+            if line.starts_with('#') {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                // `key:=value` lines are key/value pairs, not fields.
+                if !value.starts_with('=') {
+                    fields.insert(key.trim().to_lowercase(), value.trim().to_string());
+                }
+            }
+        }
+        let field = |name: &str| {
+            fields
+                .get(name)
+                .map(String::as_str)
+                .ok_or_else(|| invalid(format!("NRRD header has no '{}' field", name)))
+        };
+
+        if fields.contains_key("data file") || fields.contains_key("datafile") {
+            return Err(invalid(
+                "detached NRRD data files are not supported".to_string(),
+            ));
+        }
+        if field("dimension")? != "3" {
+            return Err(invalid(
+                "only 3-dimensional NRRD grids are supported".to_string(),
+            ));
+        }
+        let sizes: Vec<usize> = field("sizes")?
+            .split_whitespace()
+            .map(|size| size.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid("invalid NRRD sizes".to_string()))?;
+        let resolution = match sizes[..] {
+            [x, y, z] => [x, y, z],
+            _ => return Err(invalid("expected three NRRD sizes".to_string())),
+        };
+
+        // Sample size in bytes and the scale that brings integers to [0, 1].
+        let sample_type = field("type")?;
+        let (size, scale, float) = match sample_type {
+            "uchar" | "unsigned char" | "uint8" | "uint8_t" => (1, 255.0, false),
+            "ushort" | "unsigned short" | "unsigned short int" | "uint16" | "uint16_t" => {
+                (2, 65535.0, false)
+            }
+            "float" => (4, 1.0, true),
+            "double" => (8, 1.0, true),
+            _ => return Err(invalid(format!("unsupported NRRD type '{}'", sample_type))),
+        };
+        let big_endian = fields.get("endian").is_some_and(|endian| endian == "big");
+
+        let values = match field("encoding")? {
+            "raw" => body
+                .chunks_exact(size)
+                .map(|bytes| {
+                    let mut bytes = bytes.to_vec();
+                    if big_endian {
+                        bytes.reverse();
+                    }
+                    match (size, float) {
+                        (1, _) => bytes[0] as f64 / scale,
+                        (2, _) => u16::from_le_bytes([bytes[0], bytes[1]]) as f64 / scale,
+                        (4, _) => {
+                            f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+                        }
+                        _ => f64::from_le_bytes([
+                            bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6],
+                            bytes[7],
+                        ]),
+                    }
+                })
+                .collect(),
+            "ascii" | "text" | "txt" => String::from_utf8_lossy(body)
+                .split_whitespace()
+                .map(|token| token.parse::<f64>().map(|value| value / scale))
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid("invalid NRRD ascii data".to_string()))?,
+            encoding => return Err(invalid(format!("unsupported NRRD encoding '{}'", encoding))),
+        };
+        DensityGrid::new(resolution, values)
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        let [nx, ny, _] = self.resolution;
+        self.values[(z * ny + y) * nx + x]
+    }
+
+    // Range of voxels whose interpolation reaches from `min` to `max` along `axis`.
+    fn voxel_range(&self, min: f64, max: f64, axis: usize) -> (usize, usize) {
+        let n = self.resolution[axis];
+        let first = (min * n as f64 - 0.5).floor().max(0.0) as usize;
+        let last = ((max * n as f64 - 0.5).ceil().max(0.0) as usize).min(n - 1);
+        (first.min(n - 1), last)
+    }
+}
+
+impl DensityField for DensityGrid {
+    fn density(&self, point: &Point) -> f64 {
+        let mut base = [0; 3];
+        let mut fraction = [0.0; 3];
+        for axis in 0..3 {
+            let n = self.resolution[axis];
+            let x = (point.e[axis] * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            base[axis] = (x.floor() as usize).min(n.saturating_sub(2));
+            fraction[axis] = x - base[axis] as f64;
+        }
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut index = [0; 3];
+            for axis in 0..3 {
+                let upper = (corner >> axis) & 1 == 1;
+                let n = self.resolution[axis];
+                index[axis] = if upper {
+                    (base[axis] + 1).min(n - 1)
+                } else {
+                    base[axis]
+                };
+                weight *= if upper {
+                    fraction[axis]
+                } else {
+                    1.0 - fraction[axis]
+                };
+            }
+            if weight > 0.0 {
+                value += weight * self.voxel(index[0], index[1], index[2]);
+            }
+        }
+        value
+    }
+
+    fn max_density(&self, min: &Point, max: &Point) -> f64 {
+        let (x0, x1) = self.voxel_range(min.x(), max.x(), 0);
+        let (y0, y1) = self.voxel_range(min.y(), max.y(), 1);
+        let (z0, z1) = self.voxel_range(min.z(), max.z(), 2);
+        let mut result: f64 = 0.0;
+        for z in z0..=z1 {
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    result = result.max(self.voxel(x, y, z));
+                }
+            }
+        }
+        result
+    }
+}
+
+impl NoiseDensity {
+    pub fn new(frequency: f64, octaves: u32) -> Self {
+        NoiseDensity {
+            noise: Perlin::new(),
+            frequency,
+            octaves,
+        }
+    }
+}
+
+impl DensityField for NoiseDensity {
+    fn density(&self, point: &Point) -> f64 {
+        self.noise
+            .turbulence(&(*point * self.frequency), self.octaves)
+            .min(1.0)
+    }
+
+    fn max_density(&self, _min: &Point, _max: &Point) -> f64 {
+        1.0
+    }
+}
+
+impl HeterogeneousMedium {
+    const MAJORANT_RESOLUTION: usize = 16;
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        bounds: Aabb,
+        absorption_field: Arc<dyn DensityField + Send + Sync>,
+        absorption: f64,
+        scattering_field: Arc<dyn DensityField + Send + Sync>,
+        scattering: f64,
+        emission: Color,
+        emission_field: Option<Arc<dyn DensityField + Send + Sync>>,
+        phase_function: Arc<dyn Material + Send + Sync>,
+    ) -> Self {
+        let n = HeterogeneousMedium::MAJORANT_RESOLUTION;
+        let mut majorants = Vec::with_capacity(n * n * n);
+        for z in 0..n {
+            for y in 0..n {
+                for x in 0..n {
+                    let cell = Vec3::new(x as f64, y as f64, z as f64);
+                    let min = cell / n as f64;
+                    let max = (cell + Vec3::new(1.0, 1.0, 1.0)) / n as f64;
+                    majorants.push(
+                        absorption * absorption_field.max_density(&min, &max)
+                            + scattering * scattering_field.max_density(&min, &max),
+                    );
+                }
+            }
+        }
+        HeterogeneousMedium {
+            bounds,
+            absorption_field,
+            absorption,
+            scattering_field,
+            scattering,
+            emission,
+            emission_field,
+            phase_function,
+            majorants,
+        }
+    }
+
+    fn to_unit(&self, point: &Point) -> Point {
+        let extent = self.bounds.diagonal();
+        let offset = *point - self.bounds.min;
+        Point::new(
+            offset.x() / extent.x(),
+            offset.y() / extent.y(),
+            offset.z() / extent.z(),
+        )
+    }
+
+    // Absorption and scattering coefficients at `point`, in the unit cube.
+    fn coefficients(&self, point: &Point) -> (f64, f64) {
+        let absorption_density = self.absorption_field.density(point);
+        let scattering_density = if Arc::ptr_eq(&self.absorption_field, &self.scattering_field) {
+            absorption_density
+        } else {
+            self.scattering_field.density(point)
+        };
+        (
+            self.absorption * absorption_density,
+            self.scattering * scattering_density,
+        )
+    }
+
+    // Steps through the majorant cells the ray crosses between `t_min` and `t_max`, and calls
+    // `collide` at each tentative collision with its distance, the absorption and scattering
+    // coefficients there and the cell's majorant, until it returns a value.
+    fn track<T>(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        mut collide: impl FnMut(f64, (f64, f64), f64) -> Option<T>,
+    ) -> Option<T> {
+        let speed = ray.direction.length();
+
+        let (t0, t1) = self.bounds.clip(ray, t_min, t_max)?;
+
+        // Amanatides and Woo's traversal, in cell units.
+        let n = HeterogeneousMedium::MAJORANT_RESOLUTION;
+        let start = self.to_unit(&ray.at(t0)) * n as f64;
+        let unit_direction = self.to_unit(&(self.bounds.min + ray.direction)) * n as f64;
+        let mut cell = [0usize; 3];
+        let mut step = [0isize; 3];
+        let mut next_crossing = [f64::INFINITY; 3];
+        let mut crossing_interval = [f64::INFINITY; 3];
+        for axis in 0..3 {
+            cell[axis] = (start.e[axis].floor().max(0.0) as usize).min(n - 1);
+            let d = unit_direction.e[axis];
+            if d > 0.0 {
+                step[axis] = 1;
+                next_crossing[axis] = t0 + ((cell[axis] + 1) as f64 - start.e[axis]) / d;
+                crossing_interval[axis] = 1.0 / d;
+            } else if d < 0.0 {
+                step[axis] = -1;
+                next_crossing[axis] = t0 + (cell[axis] as f64 - start.e[axis]) / d;
+                crossing_interval[axis] = -1.0 / d;
+            }
+        }
+
+        let mut t = t0;
+        loop {
+            let axis = if next_crossing[0] < next_crossing[1] {
+                if next_crossing[0] < next_crossing[2] {
+                    0
+                } else {
+                    2
+                }
+            } else if next_crossing[1] < next_crossing[2] {
+                1
+            } else {
+                2
+            };
+            let cell_end = next_crossing[axis].min(t1);
+            let majorant = self.majorants[(cell[2] * n + cell[1]) * n + cell[0]];
+            if majorant > 0.0 {
+                loop {
+                    t -= (1.0 - random_float()).ln() / (majorant * speed);
+                    if t >= cell_end {
+                        break;
+                    }
+                    let coefficients = self.coefficients(&self.to_unit(&ray.at(t)));
+                    if let Some(result) = collide(t, coefficients, majorant) {
+                        return Some(result);
+                    }
+                }
+            }
+            if next_crossing[axis] >= t1 {
+                return None;
+            }
+            t = cell_end;
+            let next = cell[axis] as isize + step[axis];
+            if next < 0 || next >= n as isize {
+                return None;
+            }
+            cell[axis] = next as usize;
+            next_crossing[axis] += crossing_interval[axis];
+        }
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    // Delta tracking: each tentative collision is an absorption, a scattering or a null
+    // collision in proportion to the coefficients there and what the majorant leaves over.
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.track(
+            ray,
+            t_min,
+            t_max,
+            |t, (absorption, scattering), majorant| {
+                let choice = random_float() * majorant;
+                if choice < absorption {
+                    let mut radiance = self.emission;
+                    if let Some(field) = &self.emission_field {
+                        radiance *= field.density(&self.to_unit(&ray.at(t)));
+                    }
+                    Some(HitRecord::in_medium(
+                        ray,
+                        t,
+                        Arc::new(AbsorbedMaterial { radiance }),
+                    ))
+                } else if choice < absorption + scattering {
+                    Some(HitRecord::in_medium(ray, t, self.phase_function.clone()))
+                } else {
+                    None
+                }
+            },
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    fn is_medium(&self) -> bool {
+        true
+    }
+
+    // Ratio tracking: every tentative collision scales the transmittance by the chance it was
+    // a null collision. Russian roulette ends the walk once little is left.
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let mut transmittance = 1.0;
+        let ended = self.track(
+            ray,
+            t_min,
+            t_max,
+            |_, (absorption, scattering), majorant| {
+                transmittance *= 1.0 - (absorption + scattering) / majorant;
+                if transmittance < 0.1 {
+                    let survival = 0.5;
+                    if random_float() >= survival {
+                        return Some(());
+                    }
+                    transmittance /= survival;
+                }
+                None
+            },
+        );
+        if ended.is_some() {
+            0.0
+        } else {
+            transmittance
+        }
+    }
+}
+
+impl Material for AbsorbedMaterial {
+    fn scatter(&self, _ray: &Ray, _hit: &HitRecord) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, _ray: &Ray, _hit: &HitRecord) -> Color {
+        self.radiance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::medium::IsotropicMaterial;
+    use crate::test_util::error_message;
+    use crate::texture::SolidColor;
+
+    // Density 1 below x = 0.5 and none above.
+    struct Slab;
+
+    impl DensityField for Slab {
+        fn density(&self, point: &Point) -> f64 {
+            if point.x() < 0.5 {
+                1.0
+            } else {
+                0.0
+            }
+        }
+
+        fn max_density(&self, min: &Point, _max: &Point) -> f64 {
+            self.density(min)
+        }
+    }
+
+    struct Uniform;
+
+    impl DensityField for Uniform {
+        fn density(&self, _point: &Point) -> f64 {
+            1.0
+        }
+
+        fn max_density(&self, _min: &Point, _max: &Point) -> f64 {
+            1.0
+        }
+    }
+
+    // Absorbs 0.5 everywhere in the unit cube and scatters 2 in the half below x = 0.5.
+    fn medium() -> HeterogeneousMedium {
+        HeterogeneousMedium::new(
+            Aabb::new(Point::new(0.0, 0.0, 0.0), Point::new(1.0, 1.0, 1.0)),
+            Arc::new(Uniform),
+            0.5,
+            Arc::new(Slab),
+            2.0,
+            Color::new(1.0, 1.0, 1.0),
+            None,
+            Arc::new(IsotropicMaterial {
+                albedo: Arc::new(SolidColor::new(Color::new(0.5, 0.5, 0.5))),
+            }),
+        )
+    }
+
+    fn upwards(x: f64) -> Ray {
+        Ray {
+            origin: Point::new(x, -1.0, 0.5),
+            direction: Vec3::new(0.0, 1.0, 0.0),
+            time: 0.0,
+        }
+    }
+
+    #[test]
+    fn absorption_and_scattering_follow_their_own_fields() {
+        const NUM_SAMPLES: usize = 50_000;
+        let medium = medium();
+        for (x, extinction, scattered) in [(0.25, 2.5f64, 0.8), (0.75, 0.5, 0.0)] {
+            let ray = upwards(x);
+            let transmittance = (0..NUM_SAMPLES)
+                .map(|_| medium.transmittance(&ray, 0.001, f64::INFINITY))
+                .sum::<f64>()
+                / NUM_SAMPLES as f64;
+            let expected = (-extinction).exp();
+            assert!((transmittance - expected).abs() < 0.01, "{}", transmittance);
+
+            let hits: Vec<HitRecord> = (0..NUM_SAMPLES)
+                .filter_map(|_| medium.hit(&ray, 0.001, f64::INFINITY))
+                .collect();
+            let fraction = hits.len() as f64 / NUM_SAMPLES as f64;
+            assert!((fraction - (1.0 - expected)).abs() < 0.01, "{}", fraction);
+            let num_scattered = hits
+                .iter()
+                .filter(|hit| hit.material.scatter(&ray, hit).is_some())
+                .count();
+            let fraction = num_scattered as f64 / hits.len() as f64;
+            assert!((fraction - scattered).abs() < 0.01, "{}", fraction);
+        }
+    }
+
+    fn nrrd(header: &str, body: &[u8]) -> Vec<u8> {
+        let mut data = format!("NRRD0004\n{}\n\n", header).into_bytes();
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn raw_little_endian_floats() {
+        let body: Vec<u8> = [0.25f32, 0.5, 1.0, 2.0]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let header = "# a comment\ntype: float\ndimension: 3\nsizes: 2 2 1\n\
+                      encoding: raw\nendian: little\nspace:=not a field";
+        let grid = DensityGrid::from_nrrd(&nrrd(header, &body)).unwrap();
+        assert_eq!(grid.resolution, [2, 2, 1]);
+        assert_eq!(grid.values, vec![0.25, 0.5, 1.0, 2.0]);
+        assert_eq!(grid.voxel(1, 1, 0), 2.0);
+    }
+
+    #[test]
+    fn crlf_headers() {
+        let data = b"NRRD0004\r\ntype: uchar\r\ndimension: 3\r\nsizes: 2 1 1\r\n\
+                     encoding: raw\r\n\r\n\x0d\x0a";
+        let grid = DensityGrid::from_nrrd(data).unwrap();
+        assert_eq!(grid.values, vec![13.0 / 255.0, 10.0 / 255.0]);
+    }
+
+    #[test]
+    fn raw_big_endian_and_integer_samples() {
+        let body: Vec<u8> = [0.5f64, 3.0]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+        let header = "type: double\ndimension: 3\nsizes: 1 1 2\nencoding: raw\nendian: big";
+        let grid = DensityGrid::from_nrrd(&nrrd(header, &body)).unwrap();
+        assert_eq!(grid.values, vec![0.5, 3.0]);
+
+        let header = "type: uint16\ndimension: 3\nsizes: 2 1 1\nencoding: raw\nendian: big";
+        let grid = DensityGrid::from_nrrd(&nrrd(header, &[0xff, 0xff, 0x00, 0x00])).unwrap();
+        assert_eq!(grid.values, vec![1.0, 0.0]);
+
+        let header = "type: uchar\ndimension: 3\nsizes: 1 1 1\nencoding: raw";
+        let grid = DensityGrid::from_nrrd(&nrrd(header, &[51])).unwrap();
+        assert_eq!(grid.values, vec![0.2]);
+    }
+
+    #[test]
+    fn ascii_samples() {
+        let header = "type: uchar\ndimension: 3\nsizes: 1 2 1\nencoding: ascii";
+        let grid = DensityGrid::from_nrrd(&nrrd(header, b"255\n0\n")).unwrap();
+        assert_eq!(grid.values, vec![1.0, 0.0]);
+
+        let message = error_message(DensityGrid::from_nrrd(&nrrd(header, b"255 x")));
+        assert_eq!(message, "invalid NRRD ascii data");
+    }
+
+    #[test]
+    fn wrong_payload_size_is_an_error() {
+        let header = "type: float\ndimension: 3\nsizes: 2 2 2\nencoding: raw";
+        let message = error_message(DensityGrid::from_nrrd(&nrrd(header, &[0; 28])));
+        assert_eq!(message, "expected 8 voxels for a 2x2x2 grid, found 7");
+
+        let header = "type: float\ndimension: 3\nsizes: 0 2 2\nencoding: raw";
+        error_message(DensityGrid::from_nrrd(&nrrd(header, &[])));
+    }
+
+    #[test]
+    fn huge_sizes_do_not_overflow() {
+        let header = format!(
+            "type: uchar\ndimension: 3\nsizes: {} {} 2\nencoding: raw",
+            usize::MAX,
+            usize::MAX
+        );
+        error_message(DensityGrid::from_nrrd(&nrrd(&header, &[0; 4])));
+    }
+
+    #[test]
+    fn unsupported_headers_are_errors() {
+        let header = "type: float\ndimension: 3\nsizes: 1 1 1\nencoding: gzip";
+        assert_eq!(
+            error_message(DensityGrid::from_nrrd(&nrrd(header, &[0; 4]))),
+            "unsupported NRRD encoding 'gzip'"
+        );
+        let header = "type: int64\ndimension: 3\nsizes: 1 1 1\nencoding: raw";
+        assert_eq!(
+            error_message(DensityGrid::from_nrrd(&nrrd(header, &[0; 8]))),
+            "unsupported NRRD type 'int64'"
+        );
+        let header = "type: float\ndimension: 2\nsizes: 1 1\nencoding: raw";
+        assert_eq!(
+            error_message(DensityGrid::from_nrrd(&nrrd(header, &[0; 4]))),
+            "only 3-dimensional NRRD grids are supported"
+        );
+        let header = "type: float\ndimension: 3\nsizes: 1 1\nencoding: raw";
+        assert_eq!(
+            error_message(DensityGrid::from_nrrd(&nrrd(header, &[0; 4]))),
+            "expected three NRRD sizes"
+        );
+        let header = "type: float\ndimension: 3\nsizes: 1 one 1\nencoding: raw";
+        assert_eq!(
+            error_message(DensityGrid::from_nrrd(&nrrd(header, &[0; 4]))),
+            "invalid NRRD sizes"
+        );
+        let header = "type: float\ndimension: 3\nsizes: 1 1 1";
+        assert_eq!(
+            error_message(DensityGrid::from_nrrd(&nrrd(header, &[0; 4]))),
+            "NRRD header has no 'encoding' field"
+        );
+        let header = "type: float\ndimension: 3\nsizes: 1 1 1\nencoding: raw\ndata file: a.raw";
+        assert_eq!(
+            error_message(DensityGrid::from_nrrd(&nrrd(header, &[]))),
+            "detached NRRD data files are not supported"
+        );
+        assert_eq!(
+            error_message(DensityGrid::from_nrrd(b"P6\n1 1\n255\n")),
+            "not a NRRD file"
+        );
+        assert_eq!(
+            error_message(DensityGrid::from_nrrd(b"NRRD0004\ntype: float\n")),
+            "unterminated NRRD header"
+        );
+    }
+}